@@ -1,7 +1,12 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use app_config::CONFIGURATION;
-use app_helpers::dirs::create_temp_dir;
+use app_downloader::DownloaderError;
+use app_helpers::{dirs::create_temp_dir, layout::move_file};
+use app_jobs::JobError;
 use app_logger::trace;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 
@@ -9,6 +14,8 @@ use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 pub struct DownloadResult {
     download_dir: PathBuf,
     files: Vec<PathBuf>,
+    /// Why some of the files could not be downloaded, if only some of them were.
+    errors: Vec<String>,
 }
 
 impl DownloadResult {
@@ -16,6 +23,10 @@ impl DownloadResult {
         &self.files
     }
 
+    pub const fn errors(&self) -> &Vec<String> {
+        &self.errors
+    }
+
     pub fn cleanup(&self) -> Result<(), String> {
         fs::remove_dir_all(&self.download_dir)
             .map_err(|e| format!("Error while removing download dir: {e:?}"))?;
@@ -23,37 +34,61 @@ impl DownloadResult {
         Ok(())
     }
 
+    /// Move the downloaded files into the memes directory,
+    /// keeping the subdirectories the directory template put them in.
     pub fn move_files_to_memes_dir(&self) -> Result<Vec<PathBuf>, String> {
         let memes_dir = &CONFIGURATION.memes_directory;
+        let download_dir = self
+            .download_dir
+            .canonicalize()
+            .unwrap_or_else(|_| self.download_dir.clone());
 
-        self.files
+        let new_paths = self
+            .files
             .par_iter()
             .map(|file_path| {
                 let name = file_path.file_name().ok_or_else(|| {
                     format!("Error while getting file name: {path:?}", path = file_path)
                 })?;
-                let new_file_path = memes_dir.join(name);
-
-                fs::copy(file_path, &new_file_path)
-                    .map_err(|e| format!("Error while copying file: {e:?}"))?;
+                let relative_dir = file_path
+                    .parent()
+                    .and_then(|x| x.strip_prefix(&download_dir).ok())
+                    .unwrap_or_else(|| Path::new(""));
 
-                fs::remove_file(file_path)
-                    .map_err(|e| format!("Error while removing file: {e:?}"))?;
-
-                Ok(new_file_path)
+                move_file(
+                    file_path,
+                    &memes_dir.join(relative_dir),
+                    &name.to_string_lossy(),
+                )
+                .map_err(|e| format!("Error while moving file: {e:?}"))
             })
-            .collect::<Result<Vec<_>, String>>()
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(app_dedupe::deduplicate_new_files(&new_paths))
     }
 }
 
-pub fn download_tmp_file(url: &str) -> Result<DownloadResult, String> {
-    let download_dir =
-        create_temp_dir().map_err(|e| format!("Error while getting temp dir: {e:?}"))?;
+pub fn download_tmp_file(url: &str) -> Result<DownloadResult, JobError> {
+    let download_dir = create_temp_dir()
+        .map_err(|e| JobError::permanent(format!("Error while getting temp dir: {e:?}")))?;
     trace!("Downloading to temp dir: {:?}", &download_dir);
-    let files = app_downloader::download_file(url, &download_dir)?;
+    let download = app_downloader::download_file_detailed(url, &download_dir).map_err(|e| {
+        let _ = fs::remove_dir_all(&download_dir);
+
+        match e {
+            DownloaderError::UnsupportedUrl(url) => {
+                JobError::permanent(format!("Don't know how to download {url:?}"))
+            }
+            e if e.is_transient() => {
+                JobError::transient(format!("Error while downloading {url:?}: {e}"))
+            }
+            e => JobError::permanent(format!("Error while downloading {url:?}: {e}")),
+        }
+    })?;
 
     Ok(DownloadResult {
         download_dir,
-        files,
+        files: download.files.into_iter().map(|x| x.path).collect(),
+        errors: download.errors.iter().map(ToString::to_string).collect(),
     })
 }
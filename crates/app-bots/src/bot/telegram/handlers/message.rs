@@ -5,7 +5,15 @@ use std::{
 
 use anyhow::anyhow;
 use app_config::CONFIGURATION;
-use app_helpers::{dirs::create_temp_dir, id::time_id, results::option_contains};
+use app_helpers::{
+    dirs::create_temp_dir,
+    id::time_id,
+    layout::{layout_directory, move_file},
+    metadata::PostMetadata,
+    results::option_contains,
+    template::TemplateContext,
+};
+use app_jobs::{JobKind, JobQueue};
 use app_logger::{debug, error, info, trace};
 use async_recursion::async_recursion;
 use futures::{self};
@@ -25,7 +33,7 @@ use teloxide::{
 use tokio::fs::File;
 
 use crate::bot::telegram::{
-    download_helper::{self, DownloadResult},
+    jobs::{JobContext, JOB_ORIGIN},
     Command,
 };
 
@@ -33,11 +41,12 @@ pub struct MessageHandler<'a> {
     bot: &'a Bot,
     me: &'a Me,
     msg: &'a Message,
+    queue: &'a JobQueue,
     is_owner: bool,
 }
 
 impl<'a> MessageHandler<'a> {
-    pub fn new(bot: &'a Bot, me: &'a Me, msg: &'a Message) -> Self {
+    pub fn new(bot: &'a Bot, me: &'a Me, msg: &'a Message, queue: &'a JobQueue) -> Self {
         let owner_id = CONFIGURATION
             .telegram
             .as_ref()
@@ -51,6 +60,7 @@ impl<'a> MessageHandler<'a> {
             bot,
             me,
             msg,
+            queue,
             is_owner,
         }
     }
@@ -173,56 +183,24 @@ impl<'a> MessageHandler<'a> {
 
                 let status_msg = self.send_reply("Received URL(s). Processing...").await?;
 
-                let result = urls
-                    .into_iter()
-                    .map(|x| {
-                        tokio::task::spawn_blocking(move || download_helper::download_tmp_file(&x))
-                    })
-                    .collect::<Vec<_>>();
-                let result = futures::future::join_all(result).await;
-                let download_results = result
-                    .into_iter()
-                    .collect::<Result<Result<Vec<_>, String>, _>>()
-                    .map_err(|e| format!("Error while downloading file:\n\n{e:?}"))??;
-
-                defer! {
-                    download_results
-                        .par_iter()
-                        .map(DownloadResult::cleanup)
-                        .collect::<Vec<_>>()
-                        .into_iter()
-                        .filter_map(std::result::Result::err)
-                        .for_each(|err| {
-                            error!("Error while cleaning up: {err:?}", err = err);
-                        });
-                }
-
-                self.edit_message(&status_msg, "Downloaded files. Uploading here...")
-                    .await?;
-
-                let files =
-                    files_to_input_media(download_results.iter().flat_map(DownloadResult::files));
-
-                self.bot
-                    .send_media_group(self.msg.chat.id, files)
-                    .reply_to_message_id(self.msg.id)
-                    .await
-                    .map_err(|e| format!("Error while sending media group: {e:?}"))?;
-
-                if self.is_owner {
-                    let new_paths = download_results
-                        .par_iter()
-                        .map(DownloadResult::move_files_to_memes_dir)
-                        .collect::<Result<Vec<_>, _>>()?;
-                    let new_paths = new_paths.into_iter().flatten().collect::<Vec<_>>();
-                    info!("Downloaded files: {new_paths:?}");
+                let context = serde_json::to_value(JobContext {
+                    chat_id: self.msg.chat.id.0,
+                    message_id: self.msg.id.0,
+                    status_message_id: status_msg.id.0,
+                    is_owner: self.is_owner,
+                })
+                .map_err(|e| format!("Error while creating job context: {e:?}"))?;
+
+                for url in urls {
+                    self.queue
+                        .enqueue(
+                            JOB_ORIGIN,
+                            &JobKind::Download { url, force: false },
+                            Some(&context),
+                        )
+                        .map_err(|e| format!("Error while queueing download: {e:?}"))?;
                 }
 
-                self.bot
-                    .delete_message(self.msg.chat.id, status_msg.id)
-                    .await
-                    .map_err(|e| format!("Error while deleting status message: {e:?}"))?;
-
                 Ok(())
             }
 
@@ -355,21 +333,23 @@ impl<'a> MessageHandler<'a> {
             .map_err(|e| format!("Error while sending media group: {e:?}"))?;
 
         if self.is_owner {
+            let post = PostMetadata::default();
+            let memes_dir = CONFIGURATION
+                .memes_directory
+                .join(layout_directory(&TemplateContext::new("", &post)));
+
             let new_paths = paths
                 .par_iter()
                 .map(|x| {
                     let name = x
                         .file_name()
                         .ok_or_else(|| format!("Error while getting file name: {x:?}", x = x))?;
-                    let new_file_path = CONFIGURATION.memes_directory.join(name);
-
-                    fs::copy(x, &new_file_path)
-                        .map_err(|e| format!("Error while copying file: {e:?}"))?;
 
-                    Ok(new_file_path)
+                    move_file(x, &memes_dir, &name.to_string_lossy())
+                        .map_err(|e| format!("Error while moving file: {e:?}"))
                 })
                 .collect::<Result<Vec<_>, String>>()?;
-            paths = new_paths;
+            paths = app_dedupe::deduplicate_new_files(&new_paths);
             info!("Downloaded files: {paths:?}");
         }
 
@@ -475,7 +455,7 @@ async fn send_files<'a>(
     Ok(reqs)
 }
 
-fn files_to_input_media<TFiles, TFile>(files: TFiles) -> Vec<InputMedia>
+pub fn files_to_input_media<TFiles, TFile>(files: TFiles) -> Vec<InputMedia>
 where
     TFiles: IntoIterator<Item = TFile>,
     TFile: AsRef<Path> + Into<PathBuf> + Clone,
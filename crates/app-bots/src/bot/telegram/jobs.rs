@@ -0,0 +1,211 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use app_helpers::progress;
+use app_jobs::{Job, JobError, JobHandler, JobKind, JobOutput, JobQueue, JobStatus};
+use app_logger::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use teloxide::{prelude::*, types::MessageId};
+use tokio::runtime::Handle;
+
+use crate::bot::telegram::{download_helper, handlers::message::files_to_input_media};
+
+/// Origin of the jobs the Telegram bot enqueues.
+pub const JOB_ORIGIN: &str = "telegram";
+
+/// How many recent jobs to look through for ones that share a status message.
+const MAX_RELATED_JOBS: usize = 100;
+
+/// How often the status message is edited to show the progress of a job at most.
+///
+/// Telegram rate limits edits, so this can't be much more often.
+const PROGRESS_UPDATE_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Where to report the result of a download job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobContext {
+    pub chat_id: i64,
+    /// The message the URL was sent in.
+    pub message_id: i32,
+    /// The "Processing..." reply, removed once all jobs for the message are done.
+    pub status_message_id: i32,
+    pub is_owner: bool,
+}
+
+/// Downloads URLs sent to the bot and replies with the files.
+pub struct TelegramJobHandler {
+    pub bot: Bot,
+    pub runtime: Handle,
+    pub queue: Arc<JobQueue>,
+}
+
+impl JobHandler for TelegramJobHandler {
+    fn run(&self, job: &Job) -> Result<JobOutput, JobError> {
+        let JobKind::Download { url, .. } = &job.kind else {
+            return Err(JobError::permanent("The bot can only run download jobs"));
+        };
+        let context = job_context(job)?;
+
+        let download_result =
+            self.with_status_updates(&context, || download_helper::download_tmp_file(url))?;
+        defer! {
+            if let Err(e) = download_result.cleanup() {
+                error!("Error while cleaning up: {e:?}");
+            }
+        }
+
+        let files = files_to_input_media(download_result.files());
+        if files.is_empty() {
+            return Err(JobError::permanent("No media found"));
+        }
+
+        self.runtime
+            .block_on(
+                self.bot
+                    .send_media_group(ChatId(context.chat_id), files)
+                    .reply_to_message_id(MessageId(context.message_id))
+                    .send(),
+            )
+            .map_err(|e| JobError::transient(format!("Error while sending media group: {e:?}")))?;
+
+        let paths = if context.is_owner {
+            let paths = download_result
+                .move_files_to_memes_dir()
+                .map_err(JobError::permanent)?;
+            info!("Downloaded files: {paths:?}");
+            paths
+        } else {
+            vec![]
+        };
+
+        Ok(JobOutput::partial(paths, download_result.errors().clone()))
+    }
+
+    fn finished(&self, job: &Job) {
+        let Ok(context) = job_context(job) else {
+            return;
+        };
+        let chat_id = ChatId(context.chat_id);
+
+        let message = match job.status {
+            JobStatus::Failed => {
+                let error = job.error.as_deref().unwrap_or("Unknown error");
+                error!("Job {id} failed: {error}", id = job.id);
+                Some(format!("Error: {error}"))
+            }
+            JobStatus::Partial => {
+                let error = job.error.as_deref().unwrap_or("Unknown error");
+                warn!("Job {id} partly failed: {error}", id = job.id);
+                Some(format!("Some files could not be downloaded:\n{error}"))
+            }
+            _ => None,
+        };
+
+        if let Some(message) = message {
+            let res = self.runtime.block_on(
+                self.bot
+                    .send_message(chat_id, message)
+                    .allow_sending_without_reply(true)
+                    .reply_to_message_id(MessageId(context.message_id))
+                    .send(),
+            );
+            if let Err(e) = res {
+                error!("Error while sending error message: {e:?}");
+            }
+        }
+
+        let others_pending = self
+            .queue
+            .list(Some(JOB_ORIGIN), MAX_RELATED_JOBS)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|x| !x.status.is_finished())
+            .filter_map(|x| job_context(&x).ok())
+            .any(|x| {
+                x.chat_id == context.chat_id && x.status_message_id == context.status_message_id
+            });
+
+        if !others_pending {
+            trace!("All jobs for message {id} done", id = context.message_id);
+
+            let res = self.runtime.block_on(
+                self.bot
+                    .delete_message(chat_id, MessageId(context.status_message_id))
+                    .send(),
+            );
+            if let Err(e) = res {
+                debug!("Error while deleting status message: {e:?}");
+            }
+        }
+    }
+}
+
+impl TelegramJobHandler {
+    /// Run `f`, showing the progress it reports in the status message of the job.
+    fn with_status_updates<F, R>(&self, context: &JobContext, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let updater = {
+            let stop = Arc::clone(&stop);
+            let bot = self.bot.clone();
+            let runtime = self.runtime.clone();
+            let chat_id = ChatId(context.chat_id);
+            let message_id = MessageId(context.status_message_id);
+
+            thread::spawn(move || {
+                let mut last_text = String::new();
+                let mut last_update = Instant::now();
+                let mut latest = None;
+
+                while !stop.load(Ordering::Relaxed) {
+                    if let Ok(progress) = receiver.recv_timeout(Duration::from_secs(1)) {
+                        latest = Some(progress);
+                    }
+                    if last_update.elapsed() < PROGRESS_UPDATE_INTERVAL {
+                        continue;
+                    }
+                    let Some(progress) = latest.take() else {
+                        continue;
+                    };
+
+                    let text = format!("Processing...\n{progress}");
+                    if text == last_text {
+                        continue;
+                    }
+
+                    let res =
+                        runtime.block_on(bot.edit_message_text(chat_id, message_id, &text).send());
+                    if let Err(e) = res {
+                        debug!("Error while updating status message: {e:?}");
+                    }
+                    last_text = text;
+                    last_update = Instant::now();
+                }
+            })
+        };
+
+        let result = progress::with_progress(Some(sender), f);
+
+        stop.store(true, Ordering::Relaxed);
+        let _ = updater.join();
+
+        result
+    }
+}
+
+fn job_context(job: &Job) -> Result<JobContext, JobError> {
+    job.context
+        .clone()
+        .and_then(|x| serde_json::from_value(x).ok())
+        .ok_or_else(|| JobError::permanent("Job has no Telegram context"))
+}
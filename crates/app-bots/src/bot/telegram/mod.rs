@@ -1,15 +1,20 @@
-use std::{process::exit, thread};
+use std::{process::exit, sync::Arc, thread};
 
-use app_config::CONFIGURATION;
+use app_config::{CONFIG, CONFIGURATION};
+use app_jobs::{JobQueue, WorkerPool, KEEP_FINISHED_JOBS_FOR};
 use app_logger::{debug, error, info, trace};
 use reqwest::Url;
 use teloxide::{prelude::*, types::Me, utils::command::BotCommands};
 use tokio::runtime;
 
-use crate::bot::telegram::handlers::message::MessageHandler;
+use crate::bot::telegram::{
+    handlers::message::MessageHandler,
+    jobs::{TelegramJobHandler, JOB_ORIGIN},
+};
 
 mod download_helper;
 mod handlers;
+mod jobs;
 
 #[derive(Debug, BotCommands)]
 #[command(
@@ -71,60 +76,94 @@ pub async fn run() {
         }
     }
 
-    run_listener(bot).await;
-
-    info!("Telegram bot stopped");
-}
-
-async fn run_listener(bot: Bot) {
-    let handler = Update::filter_message().endpoint(|bot: Bot, msg: Message, me: Me| async move {
-        trace!("Received message: {msg:?}");
-        thread::spawn(move || {
-            trace!("Spawned new thread for message handler");
+    let queue = JobQueue::open_default()
+        .or_else(|e| {
+            error!("Job queue is unavailable, unfinished jobs won't survive a restart: {e:?}");
+            JobQueue::open_in_memory()
+        })
+        .unwrap_or_else(|e| {
+            error!("Error while opening job queue: {e:?}");
+            exit(1);
+        });
+    let queue = Arc::new(queue);
 
-            let msg_handler = MessageHandler::new(&bot, &me, &msg);
+    if let Err(e) = queue.prune(Some(JOB_ORIGIN), KEEP_FINISHED_JOBS_FOR) {
+        error!("Error while pruning old jobs: {e:?}");
+    }
+    match queue.requeue_interrupted(JOB_ORIGIN) {
+        Ok(0) => {}
+        Ok(n) => info!("Resuming {n} interrupted downloads"),
+        Err(e) => error!("Error while resuming interrupted downloads: {e:?}"),
+    }
 
-            let runtime = runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .map_err(|e| format!("Error while creating runtime: {e:?}"));
+    let _workers = WorkerPool::spawn(
+        Arc::clone(&queue),
+        JOB_ORIGIN,
+        CONFIG.run.jobs,
+        &Arc::new(TelegramJobHandler {
+            bot: bot.clone(),
+            runtime: runtime::Handle::current(),
+            queue: Arc::clone(&queue),
+        }),
+    );
 
-            let runtime = match runtime {
-                Ok(runtime) => runtime,
-                Err(e) => {
-                    error!("Error while creating runtime: {e:?}");
-                    return;
-                }
-            };
+    run_listener(bot, queue).await;
 
-            let resp = runtime.block_on(msg_handler.handle());
+    info!("Telegram bot stopped");
+}
 
-            let e = match resp {
-                Ok(()) => {
-                    return;
+async fn run_listener(bot: Bot, queue: Arc<JobQueue>) {
+    let handler = Update::filter_message().endpoint(
+        |bot: Bot, msg: Message, me: Me, queue: Arc<JobQueue>| async move {
+            trace!("Received message: {msg:?}");
+            thread::spawn(move || {
+                trace!("Spawned new thread for message handler");
+
+                let msg_handler = MessageHandler::new(&bot, &me, &msg, &queue);
+
+                let runtime = runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(|e| format!("Error while creating runtime: {e:?}"));
+
+                let runtime = match runtime {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        error!("Error while creating runtime: {e:?}");
+                        return;
+                    }
+                };
+
+                let resp = runtime.block_on(msg_handler.handle());
+
+                let e = match resp {
+                    Ok(()) => {
+                        return;
+                    }
+                    Err(e) => e,
+                };
+
+                error!("Error while handling message: {e:?}");
+
+                let res = runtime.block_on(
+                    bot.send_message(msg.chat.id, format!("Error: {e:?}"))
+                        .allow_sending_without_reply(true)
+                        .reply_to_message_id(msg.id)
+                        .send(),
+                );
+                if let Err(e) = res {
+                    error!("Error while sending error message: {e:?}");
                 }
-                Err(e) => e,
-            };
-
-            error!("Error while handling message: {e:?}");
+            });
 
-            let res = runtime.block_on(
-                bot.send_message(msg.chat.id, format!("Error: {e:?}"))
-                    .allow_sending_without_reply(true)
-                    .reply_to_message_id(msg.id)
-                    .send(),
-            );
-            if let Err(e) = res {
-                error!("Error while sending error message: {e:?}");
-            }
-        });
-
-        respond(())
-    });
+            respond(())
+        },
+    );
 
     let handler_tree = dptree::entry().branch(handler);
 
     Dispatcher::builder(bot, handler_tree)
+        .dependencies(dptree::deps![queue])
         .build()
         .dispatch()
         .await;
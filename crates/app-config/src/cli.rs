@@ -1,15 +1,21 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser, ValueEnum, ValueHint};
+use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    common::{AppConfig, EndpointConfig, ProgramPathConfig},
+    common::{
+        AppConfig, CookiesConfig, EndpointConfig, InstagramConfig, ProgramPathConfig, YtDlpConfig,
+    },
     Config,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CliArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[command(flatten)]
     pub app: AppArgs,
 
@@ -20,11 +26,25 @@ pub struct CliArgs {
     #[command(flatten, next_help_heading = Some("Bot config"))]
     pub bots: self::bot::BotArgs,
 
+    #[cfg(feature = "http-server")]
+    #[command(flatten, next_help_heading = Some("HTTP server config"))]
+    pub server: self::server::ServerArgs,
+
     #[command(flatten, next_help_heading = Some("Endpoint config"))]
     pub endpoints: EndpointConfig,
+
+    #[command(flatten, next_help_heading = Some("Instagram config"))]
+    pub instagram: InstagramConfig,
+
+    #[command(flatten, next_help_heading = Some("Cookies config"))]
+    pub cookies: CookiesConfig,
+
+    #[command(flatten, next_help_heading = Some("yt-dlp config"))]
+    pub yt_dlp: YtDlpConfig,
 }
 
 impl CliArgs {
+    #[allow(clippy::too_many_lines)]
     pub(crate) fn merge_into_config(&self, config: &mut Config) {
         if let Some(yt_dlp_path) = &self.paths.yt_dlp_path {
             eprintln!(
@@ -42,6 +62,58 @@ impl CliArgs {
                 );
                 config.app.memes_directory = memes_directory.into();
             }
+
+            if let Some(duplicate_action) = app_config.duplicate_action {
+                eprintln!("Found duplicate action from arguments: {duplicate_action:?}");
+                config.app.duplicate_action = duplicate_action;
+            }
+
+            if let Some(near_duplicate_action) = app_config.near_duplicate_action {
+                eprintln!("Found near-duplicate action from arguments: {near_duplicate_action:?}");
+                config.app.near_duplicate_action = near_duplicate_action;
+            }
+
+            if let Some(near_duplicate_distance) = app_config.near_duplicate_distance {
+                eprintln!(
+                    "Found near-duplicate distance from arguments: {near_duplicate_distance:?}"
+                );
+                config.app.near_duplicate_distance = Some(near_duplicate_distance);
+            }
+
+            if let Some(sidecar_metadata) = app_config.sidecar_metadata {
+                eprintln!("Found sidecar metadata setting from arguments: {sidecar_metadata:?}");
+                config.app.sidecar_metadata = sidecar_metadata;
+            }
+
+            if let Some(embed_metadata) = app_config.embed_metadata {
+                eprintln!("Found embed metadata setting from arguments: {embed_metadata:?}");
+                config.app.embed_metadata = embed_metadata;
+            }
+
+            if let Some(filename_template) = &app_config.filename_template {
+                eprintln!("Found filename template from arguments: {filename_template:?}");
+                config.app.filename_template = Some(filename_template.clone());
+            }
+
+            if let Some(directory_template) = &app_config.directory_template {
+                eprintln!("Found directory template from arguments: {directory_template:?}");
+                config.app.directory_template = Some(directory_template.clone());
+            }
+
+            if let Some(hosts) = &app_config.fediverse_instances {
+                eprintln!("Found fediverse instances from arguments: {hosts:?}");
+                config.app.fediverse_instances.clone_from(hosts);
+            }
+
+            if let Some(hosts) = &app_config.not_fediverse_instances {
+                eprintln!("Found non-fediverse instances from arguments: {hosts:?}");
+                config.app.not_fediverse_instances.clone_from(hosts);
+            }
+
+            if let Some(include_quoted_posts) = app_config.include_quoted_posts {
+                eprintln!("Found quoted posts setting from arguments: {include_quoted_posts:?}");
+                config.app.include_quoted_posts = include_quoted_posts;
+            }
         }
 
         #[cfg(feature = "telegram-bot")]
@@ -76,16 +148,95 @@ impl CliArgs {
             }
         }
 
+        #[cfg(feature = "http-server")]
+        if let Some(server_config) = &self.server.config {
+            if let Some(address) = &server_config.address {
+                eprintln!("Found server address from arguments: {address:?}");
+            }
+            config.server.merge(server_config);
+        }
+
         if let Some(config_path) = &self.app.config_path {
             config.app.config_path = config_path.into();
         }
 
-        config.run.download_url = self.app.download_url.clone();
+        config.run.download_urls.clone_from(&self.app.download_urls);
+        config.run.input_file.clone_from(&self.app.input_file);
+        config.run.jobs = self.app.jobs;
         config.run.fix = self.app.fix;
+        config.run.force = self.app.force;
+        config.run.command.clone_from(&self.command);
         config.endpoints.merge(&self.endpoints);
+        config.instagram.merge(&self.instagram);
+        config.cookies.merge(&self.cookies);
+        config.yt_dlp.merge(&self.yt_dlp);
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Subcommand)]
+pub enum Command {
+    /// List and search previously downloaded URLs.
+    History {
+        /// Only show entries where the URL, downloader or file path contains this text.
+        query: Option<String>,
+
+        #[arg(short = 'n', long, default_value_t = 20)]
+        /// Maximum number of entries to show.
+        limit: usize,
+    },
+
+    /// List recent downloads and fixes from the job queue, with their status.
+    Jobs {
+        #[arg(short = 'n', long, default_value_t = 20)]
+        /// Maximum number of jobs to show.
+        limit: usize,
+    },
+
+    /// Find identical files in a directory and send all but the oldest copy to the trash.
+    Dedupe {
+        #[arg(value_hint = ValueHint::DirPath)]
+        /// The directory to scan.
+        ///
+        /// If not provided, the memes directory will be used
+        directory: Option<PathBuf>,
+
+        #[arg(long)]
+        /// Only list the duplicates, don't remove anything.
+        dry_run: bool,
+    },
+
+    /// Find groups of images and videos that look alike, even if they aren't identical.
+    Similar {
+        #[arg(value_hint = ValueHint::DirPath)]
+        /// The directory to scan.
+        ///
+        /// If not provided, the memes directory will be used
+        directory: Option<PathBuf>,
+
+        #[arg(short, long, value_name = "BITS")]
+        /// How many bits (out of 64) perceptual hashes may differ by.
+        ///
+        /// If not provided, the configured near-duplicate distance will be used
+        distance: Option<u32>,
+    },
+
+    /// Move files into the subdirectories given by the directory template.
+    ///
+    /// Where a file goes is decided by its sidecar file if it has one,
+    /// otherwise by the date in its name or its modification time.
+    Reorganise {
+        #[arg(value_hint = ValueHint::DirPath)]
+        /// The directory to reorganise.
+        ///
+        /// If not provided, the memes directory will be used
+        directory: Option<PathBuf>,
+
+        #[arg(long)]
+        /// Only list where files would be moved, don't move anything.
+        dry_run: bool,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ValueEnum)]
 pub enum DumpType {
     Toml,
@@ -93,14 +244,32 @@ pub enum DumpType {
 }
 #[derive(Debug, Clone, Serialize, Deserialize, Args)]
 pub struct AppArgs {
-    #[arg(default_value = None, value_hint = ValueHint::Url)]
-    /// The URL to download media from.
-    pub download_url: Option<String>,
+    #[arg(value_hint = ValueHint::Url)]
+    /// The URL(s) to download media from.
+    ///
+    /// If none are provided and stdin is not a terminal,
+    /// URLs will be read from stdin, one per line.
+    pub download_urls: Vec<String>,
+
+    #[arg(short, long, default_value = None, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    /// Read URLs to download from a file, one per line.
+    ///
+    /// Empty lines and lines starting with `#` are skipped.
+    /// Use `-` to read the list from stdin.
+    pub input_file: Option<PathBuf>,
+
+    #[arg(short, long, default_value_t = 4, value_name = "N")]
+    /// How many URLs to process at the same time.
+    pub jobs: usize,
 
     #[arg(short, long)]
-    /// Just fix the given file, don't download anything.
+    /// Just fix the given file(s), don't download anything.
     pub fix: bool,
 
+    #[arg(long)]
+    /// Download URLs even if they are already in the download history.
+    pub force: bool,
+
     #[arg(short='c', long, default_value = None, env = "MEME_DOWNLOADER_CONFIG", value_hint = ValueHint::FilePath)]
     /// Location of the configuration file.
     ///
@@ -149,3 +318,24 @@ mod bot {
         pub config: Option<TelegramBotConfig>,
     }
 }
+
+#[cfg(feature = "http-server")]
+mod server {
+    use clap::Args;
+    use serde::{Deserialize, Serialize};
+
+    use crate::common::ServerConfig;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, Args)]
+    pub struct ServerArgs {
+        #[arg(long)]
+        /// Run a local HTTP API instead of downloading anything.
+        ///
+        /// Accepts URLs to download and files to fix, and runs them as jobs
+        /// whose status and resulting files can be fetched later.
+        pub serve: bool,
+
+        #[command(flatten)]
+        pub config: Option<ServerConfig>,
+    }
+}
@@ -1,8 +1,19 @@
-use std::path::PathBuf;
+use std::{fmt, path::PathBuf, str::FromStr};
 
-use clap::{Args, ValueHint};
+use clap::{Args, ValueEnum, ValueHint};
 use serde::{Deserialize, Serialize};
 
+/// Logged in place of secrets, so the config can be logged.
+const REDACTED: &str = "<redacted>";
+
+const fn redacted(is_set: bool) -> Option<&'static str> {
+    if is_set {
+        Some(REDACTED)
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize, Args)]
 pub struct AppConfig {
     #[arg(short='d', long, default_value = None, env = "MEME_DOWNLOADER_MEMES_DIR", value_hint = ValueHint::DirPath)]
@@ -10,6 +21,83 @@ pub struct AppConfig {
     ///
     /// If not provided, `$HOME/MEMES' will be used
     pub memes_directory: Option<PathBuf>,
+
+    #[arg(long, default_value = None, value_enum, env = "MEME_DOWNLOADER_DUPLICATE_ACTION")]
+    /// What to do when a downloaded file is identical to one already in the memes directory.
+    ///
+    /// If not provided, `skip` will be used
+    pub duplicate_action: Option<DuplicateAction>,
+
+    #[arg(long, default_value = None, value_enum, env = "MEME_DOWNLOADER_NEAR_DUPLICATE_ACTION")]
+    /// What to do when a downloaded image or video looks like one already in the memes directory.
+    ///
+    /// Downloads are checked against the files with perceptual hashes so far,
+    /// run `similar` once to compute them for the rest of the memes directory.
+    /// If not provided, `off` will be used
+    pub near_duplicate_action: Option<NearDuplicateAction>,
+
+    #[arg(long, default_value = None, value_name = "BITS", env = "MEME_DOWNLOADER_NEAR_DUPLICATE_DISTANCE")]
+    /// How many bits (out of 64) perceptual hashes may differ by for files to count as near-duplicates.
+    ///
+    /// If not provided, 8 will be used
+    pub near_duplicate_distance: Option<u32>,
+
+    #[arg(long, default_value = None, num_args = 0..=1, require_equals = true, default_missing_value = "true", env = "MEME_DOWNLOADER_SIDECAR_METADATA")]
+    /// Write a `<file>.meta.json` file next to each download
+    /// with the source URL, author, caption and other details of the post.
+    ///
+    /// If not provided, no sidecar files will be written
+    pub sidecar_metadata: Option<bool>,
+
+    #[arg(long, default_value = None, num_args = 0..=1, require_equals = true, default_missing_value = "true", env = "MEME_DOWNLOADER_EMBED_METADATA")]
+    /// Write the source URL, author and caption of the post into the downloaded file itself.
+    ///
+    /// Videos get container tags, JPEGs an XMP packet and PNGs text chunks.
+    /// This changes the file contents, so copies of a meme from different posts
+    /// are only caught as near-duplicates.
+    ///
+    /// If not provided, files will be left as they are
+    pub embed_metadata: Option<bool>,
+
+    #[arg(long, default_value = None, value_name = "TEMPLATE", env = "MEME_DOWNLOADER_FILENAME_TEMPLATE")]
+    /// Template for the names of downloaded files, without the extension.
+    ///
+    /// Placeholders: `{date}`, `{time}`, `{yyyy}`, `{mm}`, `{dd}`, `{site}`, `{author}`,
+    /// `{post_id}`, `{title}`, `{index}` (position in a gallery), `{name}` (name of the file
+    /// at its source) and `{id}` (short random id).
+    /// `{a|b}` uses the first one that isn't empty.
+    ///
+    /// If not provided, `{date}_{time}_{site}_{post_id|name|id}_{index}` will be used
+    pub filename_template: Option<String>,
+
+    #[arg(long, default_value = None, value_name = "TEMPLATE", env = "MEME_DOWNLOADER_DIRECTORY_TEMPLATE")]
+    /// Template for the subdirectory of the memes directory files are saved into, eg. `{site}/{yyyy}/{mm}`.
+    ///
+    /// Uses the same placeholders as the filename template.
+    /// Parts of the path that end up empty are left out.
+    ///
+    /// If not provided, files will be saved directly into the memes directory
+    pub directory_template: Option<String>,
+
+    #[arg(long, default_value = None, value_name = "HOST", value_delimiter = ',', env = "MEME_DOWNLOADER_FEDIVERSE_INSTANCES")]
+    /// Hosts to always treat as Mastodon or other fediverse instances, eg. `mastodon.social`.
+    ///
+    /// Other hosts are checked once through `NodeInfo` and remembered.
+    /// If not provided, only the check will be used
+    pub fediverse_instances: Option<Vec<String>>,
+
+    #[arg(long, default_value = None, value_name = "HOST", value_delimiter = ',', env = "MEME_DOWNLOADER_NOT_FEDIVERSE_INSTANCES")]
+    /// Hosts to never treat as fediverse instances, even if they look like one.
+    ///
+    /// If not provided, only the check will be used
+    pub not_fediverse_instances: Option<Vec<String>>,
+
+    #[arg(long, default_value = None, num_args = 0..=1, require_equals = true, default_missing_value = "true", env = "MEME_DOWNLOADER_INCLUDE_QUOTED_POSTS")]
+    /// Also download the media of posts that a post quotes.
+    ///
+    /// Only Bluesky posts are supported.
+    /// If not provided, only the media of the post itself will be downloaded
+    pub include_quoted_posts: Option<bool>,
 }
 impl AppConfig {
     pub(crate) fn merge(&mut self, config: &Self) -> &Self {
@@ -17,10 +105,74 @@ impl AppConfig {
             self.memes_directory = Some(memes_directory.clone());
         }
 
+        if let Some(duplicate_action) = config.duplicate_action {
+            self.duplicate_action = Some(duplicate_action);
+        }
+
+        if let Some(near_duplicate_action) = config.near_duplicate_action {
+            self.near_duplicate_action = Some(near_duplicate_action);
+        }
+
+        if let Some(near_duplicate_distance) = config.near_duplicate_distance {
+            self.near_duplicate_distance = Some(near_duplicate_distance);
+        }
+
+        if let Some(sidecar_metadata) = config.sidecar_metadata {
+            self.sidecar_metadata = Some(sidecar_metadata);
+        }
+
+        if let Some(embed_metadata) = config.embed_metadata {
+            self.embed_metadata = Some(embed_metadata);
+        }
+
+        if let Some(filename_template) = config.filename_template.as_ref() {
+            self.filename_template = Some(filename_template.clone());
+        }
+
+        if let Some(directory_template) = config.directory_template.as_ref() {
+            self.directory_template = Some(directory_template.clone());
+        }
+
+        if let Some(fediverse_instances) = config.fediverse_instances.as_ref() {
+            self.fediverse_instances = Some(fediverse_instances.clone());
+        }
+
+        if let Some(not_fediverse_instances) = config.not_fediverse_instances.as_ref() {
+            self.not_fediverse_instances = Some(not_fediverse_instances.clone());
+        }
+
+        if let Some(include_quoted_posts) = config.include_quoted_posts {
+            self.include_quoted_posts = Some(include_quoted_posts);
+        }
+
         self
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateAction {
+    /// Remove the new file and use the existing one instead.
+    #[default]
+    Skip,
+    /// Replace the new file with a hard link to the existing one.
+    Hardlink,
+    /// Keep both files.
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum NearDuplicateAction {
+    /// Don't check for near-duplicates.
+    #[default]
+    Off,
+    /// Log a warning, but keep the new file.
+    Warn,
+    /// Remove the new file and use the existing one instead.
+    Skip,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize, Args)]
 pub struct BotConfig {
     #[cfg(feature = "telegram-bot")]
@@ -49,7 +201,7 @@ impl BotConfig {
 }
 
 #[cfg(feature = "telegram-bot")]
-#[derive(Debug, Clone, Default, Serialize, Deserialize, Args)]
+#[derive(Clone, Default, Serialize, Deserialize, Args)]
 /// Configuration for the Telegram bot functionality
 pub struct TelegramBotConfig {
     #[arg(long = "telegram-bot-token", default_value = None, value_name = "BOT_TOKEN", env = "MEME_DOWNLOADER_TELEGRAM_TOKEN", value_hint = ValueHint::Other)]
@@ -73,6 +225,16 @@ pub struct TelegramBotConfig {
     pub api_url: Option<String>,
 }
 #[cfg(feature = "telegram-bot")]
+impl fmt::Debug for TelegramBotConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TelegramBotConfig")
+            .field("bot_token", &redacted(self.bot_token.is_some()))
+            .field("owner_id", &self.owner_id)
+            .field("api_url", &self.api_url)
+            .finish()
+    }
+}
+#[cfg(feature = "telegram-bot")]
 impl TelegramBotConfig {
     pub(crate) fn merge(&mut self, config: &Self) -> &Self {
         if let Some(bot_token) = config.bot_token.as_ref() {
@@ -91,6 +253,56 @@ impl TelegramBotConfig {
     }
 }
 
+#[cfg(feature = "http-server")]
+const DEFAULT_SERVER_ADDRESS: &str = "127.0.0.1:8080";
+
+#[cfg(feature = "http-server")]
+#[derive(Clone, Default, Serialize, Deserialize, Args)]
+/// Configuration for the HTTP API server
+pub struct ServerConfig {
+    #[arg(long = "server-address", default_value = None, value_name = "ADDRESS", env = "MEME_DOWNLOADER_SERVER_ADDRESS", value_hint = ValueHint::Other)]
+    /// The address the HTTP API listens on.
+    ///
+    /// If not provided, 127.0.0.1:8080 will be used
+    pub address: Option<String>,
+
+    #[arg(long = "server-token", default_value = None, value_name = "TOKEN", env = "MEME_DOWNLOADER_SERVER_TOKEN", value_hint = ValueHint::Other)]
+    /// A token clients have to send in an `Authorization: Bearer <token>` header.
+    ///
+    /// If not provided, anyone who can reach the address can use the API
+    pub token: Option<String>,
+}
+#[cfg(feature = "http-server")]
+impl fmt::Debug for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerConfig")
+            .field("address", &self.address)
+            .field("token", &redacted(self.token.is_some()))
+            .finish()
+    }
+}
+#[cfg(feature = "http-server")]
+impl ServerConfig {
+    pub(crate) fn merge(&mut self, config: &Self) -> &Self {
+        if let Some(address) = config.address.as_ref() {
+            self.address = Some(address.clone());
+        }
+
+        if let Some(token) = config.token.as_ref() {
+            self.token = Some(token.clone());
+        }
+
+        self
+    }
+
+    #[must_use]
+    pub fn address(&self) -> String {
+        self.address
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SERVER_ADDRESS.to_string())
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize, Args)]
 #[allow(clippy::struct_field_names)]
 pub struct ProgramPathConfig {
@@ -140,13 +352,336 @@ impl ProgramPathConfig {
     }
 }
 
+/// A `PolarisPostActionLoadPostQueryQuery` query, as used by the Instagram website.
+const DEFAULT_INSTAGRAM_GRAPHQL_DOC_ID: &str = "8845758582119845";
+
+#[derive(Clone, Default, Serialize, Deserialize, Args)]
+/// Configuration for downloading from Instagram
+pub struct InstagramConfig {
+    #[arg(long = "instagram-cookies-file", default_value = None, value_name = "FILE", env = "MEME_DOWNLOADER_INSTAGRAM_COOKIES_FILE", value_hint = ValueHint::FilePath)]
+    /// A Netscape `cookies.txt` file with a logged in Instagram session.
+    ///
+    /// Needed for stories and for posts that are only shown to logged in users.
+    /// Shorthand for `--cookies instagram.com=FILE`,
+    /// a cookies file configured there for instagram.com takes precedence.
+    /// If not provided, only public posts can be downloaded
+    pub cookies_file: Option<PathBuf>,
+
+    #[arg(long = "instagram-session-id", default_value = None, value_name = "SESSION_ID", env = "MEME_DOWNLOADER_INSTAGRAM_SESSION_ID", value_hint = ValueHint::Other)]
+    /// The `sessionid` cookie of a logged in Instagram session, instead of a cookies file.
+    ///
+    /// Only used when looking up posts, `yt-dlp` needs a cookies file.
+    pub session_id: Option<String>,
+
+    #[arg(long = "instagram-graphql-doc-id", default_value = None, value_name = "DOC_ID", env = "MEME_DOWNLOADER_INSTAGRAM_GRAPHQL_DOC_ID", value_hint = ValueHint::Other)]
+    /// ID of the GraphQL query used to look up posts.
+    ///
+    /// Instagram changes these every now and then, the current one can be found
+    /// in the requests the website makes to `/graphql/query`.
+    /// If not provided, a recently working ID will be used
+    pub(crate) graphql_doc_id: Option<String>,
+}
+
+impl fmt::Debug for InstagramConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InstagramConfig")
+            .field("cookies_file", &redacted(self.cookies_file.is_some()))
+            .field("session_id", &redacted(self.session_id.is_some()))
+            .field("graphql_doc_id", &self.graphql_doc_id)
+            .finish()
+    }
+}
+
+impl InstagramConfig {
+    pub(crate) fn merge(&mut self, config: &Self) -> &Self {
+        if let Some(cookies_file) = config.cookies_file.as_ref() {
+            self.cookies_file = Some(cookies_file.clone());
+        }
+
+        if let Some(session_id) = config.session_id.as_ref() {
+            self.session_id = Some(session_id.clone());
+        }
+
+        if let Some(graphql_doc_id) = config.graphql_doc_id.as_ref() {
+            self.graphql_doc_id = Some(graphql_doc_id.clone());
+        }
+
+        self
+    }
+
+    pub fn graphql_doc_id(&self) -> String {
+        self.graphql_doc_id
+            .clone()
+            .unwrap_or_else(|| DEFAULT_INSTAGRAM_GRAPHQL_DOC_ID.to_string())
+    }
+}
+
+/// A setting for one site, written as `site=value`, eg. `instagram.com=/path/to/cookies.txt`.
+///
+/// A site also covers its subdomains.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SiteSetting {
+    pub site: String,
+    pub value: String,
+}
+
+impl SiteSetting {
+    /// Whether the setting applies to `host`.
+    #[must_use]
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+
+        host == self.site || host.ends_with(&format!(".{}", self.site))
+    }
+}
+
+impl FromStr for SiteSetting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (site, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected `site=value`, got {s:?}"))?;
+        let site = site.trim().trim_matches('.').to_lowercase();
+        let value = value.trim();
+
+        if site.is_empty() || value.is_empty() {
+            return Err(format!("Expected `site=value`, got {s:?}"));
+        }
+
+        Ok(Self {
+            site,
+            value: value.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for SiteSetting {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<SiteSetting> for String {
+    fn from(value: SiteSetting) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for SiteSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.site, self.value)
+    }
+}
+
+/// Where to get the cookies for a site from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CookieSource {
+    /// A Netscape `cookies.txt` file.
+    File(PathBuf),
+    /// A browser profile, in the format of `yt-dlp`'s `--cookies-from-browser`.
+    Browser(String),
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Args)]
+/// Cookies to send to sites that need a logged in session
+pub struct CookiesConfig {
+    #[arg(long = "cookies", default_value = None, value_name = "SITE=FILE", value_delimiter = ',', env = "MEME_DOWNLOADER_COOKIES")]
+    /// Netscape `cookies.txt` files to use for sites, eg. `instagram.com=/path/to/cookies.txt`.
+    ///
+    /// A site also covers its subdomains. The cookies are sent along with
+    /// requests to the site and passed to `yt-dlp` with `--cookies`.
+    /// If not provided, no cookies will be sent
+    pub(crate) files: Option<Vec<SiteSetting>>,
+
+    #[arg(long = "cookies-from-browser", default_value = None, value_name = "SITE=BROWSER", value_delimiter = ',', env = "MEME_DOWNLOADER_COOKIES_FROM_BROWSER")]
+    /// Browsers to take the cookies for sites from, eg. `twitter.com=firefox`.
+    ///
+    /// Takes anything `yt-dlp`'s `--cookies-from-browser` does, eg. `firefox:default-release`.
+    /// A cookies file configured for the same site takes precedence.
+    /// If not provided, no cookies will be sent
+    pub(crate) from_browser: Option<Vec<SiteSetting>>,
+}
+
+impl fmt::Debug for CookiesConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files = self.files.as_ref().map(|files| {
+            files
+                .iter()
+                .map(|x| format!("{}={REDACTED}", x.site))
+                .collect::<Vec<_>>()
+        });
+
+        f.debug_struct("CookiesConfig")
+            .field("files", &files)
+            .field("from_browser", &self.from_browser)
+            .finish()
+    }
+}
+
+impl CookiesConfig {
+    /// Settings for the same site replace the ones that are already there.
+    pub(crate) fn merge(&mut self, config: &Self) -> &Self {
+        fn merge_sites(current: &mut Option<Vec<SiteSetting>>, new: Option<&[SiteSetting]>) {
+            let Some(new) = new else {
+                return;
+            };
+
+            let current = current.get_or_insert_with(Vec::new);
+            current.retain(|x| !new.iter().any(|y| y.site == x.site));
+            current.extend_from_slice(new);
+        }
+
+        merge_sites(&mut self.files, config.files.as_deref());
+        merge_sites(&mut self.from_browser, config.from_browser.as_deref());
+
+        self
+    }
+
+    /// Use `cookies_file` for `site`, unless a file is already configured for it.
+    pub(crate) fn add_default_file(&mut self, site: &str, cookies_file: &std::path::Path) {
+        let files = self.files.get_or_insert_with(Vec::new);
+
+        if !files.iter().any(|x| x.site == site) {
+            files.push(SiteSetting {
+                site: site.to_string(),
+                value: cookies_file.to_string_lossy().to_string(),
+            });
+        }
+    }
+
+    /// Where to get the cookies for `host` from.
+    ///
+    /// The most specific site configured for the host wins.
+    #[must_use]
+    pub fn for_host(&self, host: &str) -> Option<CookieSource> {
+        let files = self
+            .files
+            .iter()
+            .flatten()
+            .map(|x| (x, CookieSource::File(PathBuf::from(&x.value))));
+        let browsers = self
+            .from_browser
+            .iter()
+            .flatten()
+            .map(|x| (x, CookieSource::Browser(x.value.clone())));
+
+        files
+            .chain(browsers)
+            .filter(|(setting, _)| setting.matches(host))
+            .fold(
+                None,
+                |best: Option<(&SiteSetting, CookieSource)>, x| match best {
+                    Some(best) if best.0.site.len() >= x.0.site.len() => Some(best),
+                    _ => Some(x),
+                },
+            )
+            .map(|(_, source)| source)
+    }
+}
+
+/// How many items of a playlist `yt-dlp` downloads by default.
+const DEFAULT_YT_DLP_MAX_ITEMS: usize = 25;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Args)]
+/// Configuration for running `yt-dlp`
+pub struct YtDlpConfig {
+    #[arg(long = "yt-dlp-max-items", default_value = None, value_name = "COUNT", env = "MEME_DOWNLOADER_YT_DLP_MAX_ITEMS")]
+    /// The most items of a playlist or multi-video post to download with `yt-dlp`.
+    ///
+    /// Use 0 to download all of them. A profile's `playlist_items` takes precedence.
+    /// If not provided, 25 will be used
+    pub(crate) max_items: Option<usize>,
+
+    #[arg(skip)]
+    /// Arguments to run `yt-dlp` with for URLs matching a pattern.
+    ///
+    /// The first profile that matches the URL given to `yt-dlp` is used.
+    #[serde(default)]
+    pub profiles: Vec<YtDlpProfile>,
+}
+
+impl YtDlpConfig {
+    pub(crate) fn merge(&mut self, config: &Self) -> &Self {
+        if let Some(max_items) = config.max_items {
+            self.max_items = Some(max_items);
+        }
+
+        if !config.profiles.is_empty() {
+            self.profiles.clone_from(&config.profiles);
+        }
+
+        self
+    }
+
+    /// The most items to download, `None` if there's no limit.
+    #[must_use]
+    pub fn max_items(&self) -> Option<usize> {
+        Some(self.max_items.unwrap_or(DEFAULT_YT_DLP_MAX_ITEMS)).filter(|x| *x > 0)
+    }
+}
+
+/// Arguments to run `yt-dlp` with for some URLs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct YtDlpProfile {
+    /// Name to refer to the profile by in logs.
+    pub name: Option<String>,
+
+    /// Regular expression the URL has to match for the profile to be used.
+    pub url_pattern: String,
+
+    /// Format selector, passed as `--format`, eg. `bv*[vcodec^=avc1][height<=1080]+ba/b`.
+    pub format: Option<String>,
+
+    /// How to sort formats, passed as `--format-sort`, eg. `vcodec:h264,res:1080`.
+    pub format_sort: Option<String>,
+
+    /// Skip files larger than this, passed as `--max-filesize`, eg. `200M`.
+    pub max_filesize: Option<String>,
+
+    /// Which items of a playlist to download, passed as `--playlist-items`, eg. `1:5`.
+    pub playlist_items: Option<String>,
+
+    /// Embed subtitles into downloaded videos.
+    #[serde(default)]
+    pub embed_subtitles: bool,
+
+    /// Also embed automatically generated subtitles.
+    #[serde(default)]
+    pub auto_subtitles: bool,
+
+    /// Languages of the subtitles to embed, passed as `--sub-langs`, eg. `en.*`.
+    pub subtitle_langs: Option<Vec<String>>,
+
+    /// Any other arguments, added after the ones above.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
 const DEFAULT_TWITTER_SCREENSHOT_BASE_URL: &str = "https://twitter.igr.ec";
+const DEFAULT_BLUESKY_API_BASE_URL: &str = "https://public.api.bsky.app";
+/// The client ID the Imgur website itself uses.
+const DEFAULT_IMGUR_CLIENT_ID: &str = "546c25a59c58ad7";
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize, Args)]
 pub struct EndpointConfig {
     #[arg(long, default_value = None, env = "MEME_DOWNLOADER_ENDPOINT_TWITTER_SCREENSHOT", value_hint = ValueHint::Url)]
     /// The base URL for the Twitter screenshot API.
     pub(crate) twitter_screenshot_base_url: Option<String>,
+
+    #[arg(long, default_value = None, env = "MEME_DOWNLOADER_ENDPOINT_BLUESKY_API", value_hint = ValueHint::Url)]
+    /// The base URL of the Bluesky API to look up posts with.
+    pub(crate) bluesky_api_base_url: Option<String>,
+
+    #[arg(long, default_value = None, env = "MEME_DOWNLOADER_ENDPOINT_IMGUR_CLIENT_ID", value_hint = ValueHint::Other)]
+    /// The client ID to use the Imgur API with.
+    ///
+    /// Register an application at <https://api.imgur.com/oauth2/addclient> to get your own.
+    /// If not provided, the client ID of the Imgur website will be used
+    pub(crate) imgur_client_id: Option<String>,
 }
 
 impl EndpointConfig {
@@ -155,6 +690,14 @@ impl EndpointConfig {
             self.twitter_screenshot_base_url = Some(twitter_screenshot_base_url.clone());
         }
 
+        if let Some(bluesky_api_base_url) = config.bluesky_api_base_url.as_ref() {
+            self.bluesky_api_base_url = Some(bluesky_api_base_url.clone());
+        }
+
+        if let Some(imgur_client_id) = config.imgur_client_id.as_ref() {
+            self.imgur_client_id = Some(imgur_client_id.clone());
+        }
+
         self
     }
 
@@ -164,4 +707,16 @@ impl EndpointConfig {
             .cloned()
             .unwrap_or_else(|| DEFAULT_TWITTER_SCREENSHOT_BASE_URL.to_string())
     }
+
+    pub fn bluesky_api_base_url(&self) -> String {
+        self.bluesky_api_base_url
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BLUESKY_API_BASE_URL.to_string())
+    }
+
+    pub fn imgur_client_id(&self) -> String {
+        self.imgur_client_id
+            .clone()
+            .unwrap_or_else(|| DEFAULT_IMGUR_CLIENT_ID.to_string())
+    }
 }
@@ -8,7 +8,10 @@ use anyhow::{anyhow, bail};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    common::{AppConfig, BotConfig, EndpointConfig, ProgramPathConfig},
+    common::{
+        AppConfig, BotConfig, CookiesConfig, EndpointConfig, InstagramConfig, ProgramPathConfig,
+        YtDlpConfig,
+    },
     Config, Configuration,
 };
 
@@ -42,6 +45,16 @@ impl From<OldFileConfiguration> for FileConfiguration {
         Self {
             app: val.memes_directory.map(|x| AppConfig {
                 memes_directory: Some(x),
+                duplicate_action: None,
+                near_duplicate_action: None,
+                near_duplicate_distance: None,
+                sidecar_metadata: None,
+                embed_metadata: None,
+                filename_template: None,
+                directory_template: None,
+                fediverse_instances: None,
+                not_fediverse_instances: None,
+                include_quoted_posts: None,
             }),
             dependencies: Some(ProgramPathConfig {
                 yt_dlp_path: val.yt_dlp_path,
@@ -53,7 +66,12 @@ impl From<OldFileConfiguration> for FileConfiguration {
                 #[cfg(feature = "telegram-bot")]
                 telegram: val.telegram,
             }),
+            #[cfg(feature = "http-server")]
+            server: None,
             endpoints: None,
+            instagram: None,
+            cookies: None,
+            yt_dlp: None,
         }
     }
 }
@@ -66,7 +84,16 @@ pub struct FileConfiguration {
 
     pub bots: Option<BotConfig>,
 
+    #[cfg(feature = "http-server")]
+    pub server: Option<crate::common::ServerConfig>,
+
     pub endpoints: Option<EndpointConfig>,
+
+    pub instagram: Option<InstagramConfig>,
+
+    pub cookies: Option<CookiesConfig>,
+
+    pub yt_dlp: Option<YtDlpConfig>,
 }
 
 impl FileConfiguration {
@@ -86,7 +113,7 @@ impl FileConfiguration {
         Self::load_from_file(config_path)
     }
 
-    #[allow(clippy::unused_self)]
+    #[allow(clippy::unused_self, clippy::too_many_lines)]
     pub(crate) fn merge_into_config(&self, config: &mut Config) {
         if Self::is_default_config_path(&config.app.config_path) {
             config.app.config_path =
@@ -120,12 +147,84 @@ impl FileConfiguration {
                 eprintln!("Found memes directory from config file: {memes_directory:?}");
                 config.app.memes_directory = memes_directory.into();
             }
+
+            if let Some(duplicate_action) = app.duplicate_action {
+                eprintln!("Found duplicate action from config file: {duplicate_action:?}");
+                config.app.duplicate_action = duplicate_action;
+            }
+
+            if let Some(near_duplicate_action) = app.near_duplicate_action {
+                eprintln!(
+                    "Found near-duplicate action from config file: {near_duplicate_action:?}"
+                );
+                config.app.near_duplicate_action = near_duplicate_action;
+            }
+
+            if let Some(near_duplicate_distance) = app.near_duplicate_distance {
+                eprintln!(
+                    "Found near-duplicate distance from config file: {near_duplicate_distance:?}"
+                );
+                config.app.near_duplicate_distance = Some(near_duplicate_distance);
+            }
+
+            if let Some(sidecar_metadata) = app.sidecar_metadata {
+                eprintln!("Found sidecar metadata setting from config file: {sidecar_metadata:?}");
+                config.app.sidecar_metadata = sidecar_metadata;
+            }
+
+            if let Some(embed_metadata) = app.embed_metadata {
+                eprintln!("Found embed metadata setting from config file: {embed_metadata:?}");
+                config.app.embed_metadata = embed_metadata;
+            }
+
+            if let Some(filename_template) = &app.filename_template {
+                eprintln!("Found filename template from config file: {filename_template:?}");
+                config.app.filename_template = Some(filename_template.clone());
+            }
+
+            if let Some(directory_template) = &app.directory_template {
+                eprintln!("Found directory template from config file: {directory_template:?}");
+                config.app.directory_template = Some(directory_template.clone());
+            }
+
+            if let Some(hosts) = &app.fediverse_instances {
+                eprintln!("Found fediverse instances from config file: {hosts:?}");
+                config.app.fediverse_instances.clone_from(hosts);
+            }
+
+            if let Some(hosts) = &app.not_fediverse_instances {
+                eprintln!("Found non-fediverse instances from config file: {hosts:?}");
+                config.app.not_fediverse_instances.clone_from(hosts);
+            }
+
+            if let Some(include_quoted_posts) = app.include_quoted_posts {
+                eprintln!("Found quoted posts setting from config file: {include_quoted_posts:?}");
+                config.app.include_quoted_posts = include_quoted_posts;
+            }
         }
 
         if let Some(endpoints) = &self.endpoints {
             config.endpoints.merge(endpoints);
         }
 
+        if let Some(instagram) = &self.instagram {
+            config.instagram.merge(instagram);
+        }
+
+        if let Some(cookies) = &self.cookies {
+            config.cookies.merge(cookies);
+        }
+
+        if let Some(yt_dlp) = &self.yt_dlp {
+            if !yt_dlp.profiles.is_empty() {
+                eprintln!(
+                    "Found {} yt-dlp profiles from config file",
+                    yt_dlp.profiles.len()
+                );
+            }
+            config.yt_dlp.merge(yt_dlp);
+        }
+
         #[cfg(feature = "telegram-bot")]
         {
             if let Some(bots) = &self.bots {
@@ -135,6 +234,16 @@ impl FileConfiguration {
                 }
             }
         }
+
+        #[cfg(feature = "http-server")]
+        {
+            if let Some(server) = &self.server {
+                if let Some(address) = &server.address {
+                    eprintln!("Found server address from config file: {address:?}");
+                }
+                config.server.merge(server);
+            }
+        }
     }
 
     fn merge(self, other: Self) -> Self {
@@ -168,6 +277,18 @@ impl FileConfiguration {
             })
             .or(Some(other_bots));
 
+        #[cfg(feature = "http-server")]
+        let server = {
+            let other_server = other.server.unwrap_or_default();
+            self.server
+                .map(|mut server| {
+                    server.merge(&other_server);
+
+                    server.clone()
+                })
+                .or(Some(other_server))
+        };
+
         let other_endpoint = other.endpoints.unwrap_or_default();
         let endpoints = self
             .endpoints
@@ -178,11 +299,46 @@ impl FileConfiguration {
             })
             .or(Some(other_endpoint));
 
+        let other_instagram = other.instagram.unwrap_or_default();
+        let instagram = self
+            .instagram
+            .map(|mut instagram| {
+                instagram.merge(&other_instagram);
+
+                instagram.clone()
+            })
+            .or(Some(other_instagram));
+
+        let other_cookies = other.cookies.unwrap_or_default();
+        let cookies = self
+            .cookies
+            .map(|mut cookies| {
+                cookies.merge(&other_cookies);
+
+                cookies.clone()
+            })
+            .or(Some(other_cookies));
+
+        let other_yt_dlp = other.yt_dlp.unwrap_or_default();
+        let yt_dlp = self
+            .yt_dlp
+            .map(|mut yt_dlp| {
+                yt_dlp.merge(&other_yt_dlp);
+
+                yt_dlp.clone()
+            })
+            .or(Some(other_yt_dlp));
+
         Self {
             app,
             dependencies,
             bots,
+            #[cfg(feature = "http-server")]
+            server,
             endpoints,
+            instagram,
+            cookies,
+            yt_dlp,
         }
     }
 
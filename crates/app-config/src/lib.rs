@@ -11,6 +11,10 @@ use serde::{Deserialize, Serialize};
 use which::which;
 
 use crate::cli::DumpType;
+pub use crate::{
+    cli::Command,
+    common::{CookieSource, DuplicateAction, NearDuplicateAction, YtDlpProfile},
+};
 
 mod cli;
 mod common;
@@ -37,7 +41,16 @@ pub struct Config {
 
     pub bots: common::BotConfig,
 
+    #[cfg(feature = "http-server")]
+    pub server: common::ServerConfig,
+
     pub endpoints: common::EndpointConfig,
+
+    pub instagram: common::InstagramConfig,
+
+    pub cookies: common::CookiesConfig,
+
+    pub yt_dlp: common::YtDlpConfig,
 }
 
 impl Config {
@@ -64,6 +77,20 @@ impl Config {
         Self::get_cache_dir()
     }
 
+    /// Directory for persistent application state, like the download history.
+    #[must_use]
+    pub fn get_data_dir() -> PathBuf {
+        Self::get_project_dir().map_or_else(
+            || env::temp_dir().join(APPLICATION_NAME),
+            |x| x.data_dir().into(),
+        )
+    }
+
+    #[must_use]
+    pub fn data_dir(&self) -> PathBuf {
+        Self::get_data_dir()
+    }
+
     fn get_project_dir() -> Option<ProjectDirs> {
         ProjectDirs::from(ORGANIZATION_QUALIFIER, ORGANIZATION_NAME, APPLICATION_NAME)
     }
@@ -77,6 +104,12 @@ impl Config {
         config.merge_file_config(&file_config);
         config.merge_args(&args);
 
+        if let Some(cookies_file) = &config.instagram.cookies_file {
+            config
+                .cookies
+                .add_default_file("instagram.com", cookies_file);
+        }
+
         {
             if config.dependencies.yt_dlp_path.is_none() {
                 config.dependencies.yt_dlp_path = Some(
@@ -142,6 +175,16 @@ impl Config {
             }
         }
 
+        #[cfg(feature = "http-server")]
+        if args.server.serve {
+            if config.run.run_as_bot.is_some() {
+                eprintln!("Can't run as a bot and an HTTP server at the same time");
+                std::process::exit(1);
+            }
+
+            config.run.run_as_bot = Some(RunAsBot::HttpServer);
+        }
+
         if let Some(dump_type) = args.app.dump_config {
             match dump_type.unwrap_or(DumpType::Toml) {
                 DumpType::Toml => {
@@ -181,23 +224,38 @@ impl Config {
 pub struct AppConfig {
     pub memes_directory: PathBuf,
     pub config_path: PathBuf,
+    pub duplicate_action: DuplicateAction,
+    pub near_duplicate_action: NearDuplicateAction,
+    pub near_duplicate_distance: Option<u32>,
+    pub sidecar_metadata: bool,
+    pub embed_metadata: bool,
+    pub filename_template: Option<String>,
+    pub directory_template: Option<String>,
+    pub fediverse_instances: Vec<String>,
+    pub not_fediverse_instances: Vec<String>,
+    pub include_quoted_posts: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RunAsBot {
     Telegram,
+    HttpServer,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RunConfig {
-    pub download_url: Option<String>,
+    pub download_urls: Vec<String>,
+    pub input_file: Option<PathBuf>,
+    pub jobs: usize,
     pub fix: bool,
+    pub force: bool,
+    pub command: Option<Command>,
     pub run_as_bot: Option<RunAsBot>,
 }
 
 #[derive(Debug, Default, Serialize)]
 pub struct Configuration {
-    pub args_download_url: Option<String>,
+    pub args_download_urls: Vec<String>,
     pub args_fix: bool,
     pub config_path: PathBuf,
 
@@ -246,7 +304,7 @@ impl Configuration {
 
     fn from_new(config: Config) -> Self {
         Self {
-            args_download_url: config.run.download_url.clone(),
+            args_download_urls: config.run.download_urls.clone(),
             args_fix: config.run.fix,
             config_path: config.app.config_path.clone(),
 
@@ -0,0 +1,360 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use app_config::CONFIG;
+use app_helpers::dirs;
+use app_logger::{debug, trace, warn};
+use rayon::prelude::*;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{hash_file, is_same_file, perceptual, DuplicateGroup};
+
+pub static INDEX_FILE_NAME: &str = "content-index.sqlite3";
+
+/// Cache of file content hashes and perceptual hashes.
+///
+/// Files are only re-hashed when their size or modification time change.
+#[derive(Debug)]
+pub struct ContentIndex {
+    conn: Mutex<Connection>,
+}
+
+impl ContentIndex {
+    /// Open the index in the default location.
+    pub fn open_default() -> anyhow::Result<Self> {
+        Self::open(&CONFIG.cache_dir().join(INDEX_FILE_NAME))
+    }
+
+    /// Open the index at `path`, creating it if needed.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create index directory {parent:?}"))?;
+        }
+
+        trace!("Opening content index at {path:?}");
+
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open content index {path:?}"))?;
+
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS files (
+                path TEXT PRIMARY KEY NOT NULL,
+                hash TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                modified INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS files_hash ON files (hash);
+            CREATE TABLE IF NOT EXISTS perceptual_hashes (
+                path TEXT PRIMARY KEY NOT NULL,
+                hashes BLOB NOT NULL,
+                size INTEGER NOT NULL,
+                modified INTEGER NOT NULL
+            );
+            ",
+        )
+        .context("Failed to create content index tables")?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Get the content hash of a file, hashing it if it isn't indexed or has changed.
+    pub fn hash(&self, path: &Path) -> anyhow::Result<String> {
+        let (size, modified) = file_stamp(path)?;
+        let path_str = path.to_string_lossy();
+
+        let cached = self
+            .conn()
+            .query_row(
+                "SELECT hash FROM files WHERE path = ?1 AND size = ?2 AND modified = ?3",
+                params![path_str, size, modified],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+
+        if let Some(hash) = cached {
+            return Ok(hash);
+        }
+
+        debug!("Hashing {path:?}");
+
+        let hash = hash_file(path).with_context(|| format!("Failed to hash {path:?}"))?;
+
+        self.conn().execute(
+            "INSERT OR REPLACE INTO files (path, hash, size, modified) VALUES (?1, ?2, ?3, ?4)",
+            params![path_str, hash, size, modified],
+        )?;
+
+        Ok(hash)
+    }
+
+    /// Bring the index up to date with the files in `dir` and its subdirectories.
+    ///
+    /// Files that can't be read are skipped with a warning.
+    pub fn refresh(&self, dir: &Path) -> anyhow::Result<()> {
+        trace!("Refreshing content index for {dir:?}");
+
+        for path in files_in(dir)? {
+            if let Err(e) = self.hash(&path) {
+                warn!("Skipping {path:?} while refreshing content index: {e:?}");
+            }
+        }
+
+        let indexed = self.paths_in(dir)?;
+        for path in indexed.iter().filter(|x| !x.is_file()) {
+            self.remove(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the perceptual hashes of a file, computing them if they aren't indexed
+    /// or the file has changed.
+    ///
+    /// See [`perceptual::perceptual_hashes`].
+    pub fn perceptual_hashes(&self, path: &Path) -> anyhow::Result<Vec<u64>> {
+        if let Some(hashes) = self.cached_perceptual_hashes(path)? {
+            return Ok(hashes);
+        }
+
+        debug!("Computing perceptual hashes of {path:?}");
+
+        let hashes = perceptual::perceptual_hashes(path)?;
+        self.store_perceptual_hashes(path, &hashes)?;
+
+        Ok(hashes)
+    }
+
+    /// Compute the perceptual hashes of all files in `dir` and its subdirectories
+    /// that aren't indexed yet.
+    ///
+    /// This is slow the first time it's run on a big directory,
+    /// so the files are processed in parallel.
+    pub fn refresh_perceptual(&self, dir: &Path) -> anyhow::Result<()> {
+        trace!("Refreshing perceptual hashes for {dir:?}");
+
+        let mut stale = vec![];
+        for path in files_in(dir)? {
+            match self.cached_perceptual_hashes(&path) {
+                Ok(Some(_)) => {}
+                Ok(None) => stale.push(path),
+                Err(e) => warn!("Skipping {path:?} while refreshing perceptual hashes: {e:?}"),
+            }
+        }
+
+        debug!("Computing perceptual hashes of {} files", stale.len());
+
+        let computed = stale
+            .into_par_iter()
+            .map(|path| {
+                let hashes = perceptual::perceptual_hashes(&path);
+                (path, hashes)
+            })
+            .collect::<Vec<_>>();
+
+        for (path, hashes) in computed {
+            match hashes {
+                Ok(hashes) => {
+                    if let Err(e) = self.store_perceptual_hashes(&path, &hashes) {
+                        warn!("Failed to store perceptual hashes of {path:?}: {e:?}");
+                    }
+                }
+                Err(e) => warn!("Failed to compute perceptual hashes of {path:?}: {e:?}"),
+            }
+        }
+
+        let indexed = self
+            .conn()
+            .prepare("SELECT path FROM perceptual_hashes")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|x| x.map(PathBuf::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        for path in indexed
+            .iter()
+            .filter(|x| x.starts_with(dir) && !x.is_file())
+        {
+            self.remove(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// All indexed files in `dir` that have perceptual hashes.
+    pub fn all_perceptual_hashes(&self, dir: &Path) -> anyhow::Result<Vec<(PathBuf, Vec<u64>)>> {
+        let rows = self
+            .conn()
+            .prepare("SELECT path, hashes FROM perceptual_hashes ORDER BY modified, path")?
+            .query_map([], |row| {
+                Ok((
+                    PathBuf::from(row.get::<_, String>(0)?),
+                    row.get::<_, Vec<u8>>(1)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let hashes = rows
+            .into_iter()
+            .filter(|(path, _)| path.starts_with(dir) && path.is_file())
+            .map(|(path, hashes)| (path, decode_hashes(&hashes)))
+            .filter(|(_, hashes)| !hashes.is_empty())
+            .collect();
+
+        Ok(hashes)
+    }
+
+    /// All indexed files with the given content hash.
+    pub fn find(&self, hash: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let paths = self
+            .conn()
+            .prepare("SELECT path FROM files WHERE hash = ?1 ORDER BY modified, path")?
+            .query_map(params![hash], |row| row.get::<_, String>(0))?
+            .map(|x| x.map(PathBuf::from))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(paths)
+    }
+
+    /// Drop a file from the index.
+    pub fn remove(&self, path: &Path) -> anyhow::Result<()> {
+        let conn = self.conn();
+        let path = path.to_string_lossy();
+
+        conn.execute("DELETE FROM files WHERE path = ?1", params![path])?;
+        conn.execute(
+            "DELETE FROM perceptual_hashes WHERE path = ?1",
+            params![path],
+        )?;
+        drop(conn);
+
+        Ok(())
+    }
+
+    /// Groups of indexed files in `dir` that have identical contents.
+    ///
+    /// The oldest file of each group is considered the original.
+    /// Hard links to the original are not reported as duplicates.
+    pub fn duplicates(&self, dir: &Path) -> anyhow::Result<Vec<DuplicateGroup>> {
+        let rows = self
+            .conn()
+            .prepare(
+                "SELECT hash, path FROM files \
+                 WHERE hash IN (SELECT hash FROM files GROUP BY hash HAVING COUNT(*) > 1) \
+                 ORDER BY hash, modified, path",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    PathBuf::from(row.get::<_, String>(1)?),
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut groups: Vec<DuplicateGroup> = vec![];
+        for (hash, path) in rows {
+            if !path.starts_with(dir) || !path.is_file() {
+                continue;
+            }
+
+            match groups.last_mut() {
+                Some(group) if group.hash == hash => {
+                    if !is_same_file(&group.original, &path) {
+                        group.duplicates.push(path);
+                    }
+                }
+                _ => groups.push(DuplicateGroup {
+                    hash,
+                    original: path,
+                    duplicates: vec![],
+                }),
+            }
+        }
+
+        groups.retain(|x| !x.duplicates.is_empty());
+
+        Ok(groups)
+    }
+
+    fn cached_perceptual_hashes(&self, path: &Path) -> anyhow::Result<Option<Vec<u64>>> {
+        let (size, modified) = file_stamp(path)?;
+
+        let hashes = self
+            .conn()
+            .query_row(
+                "SELECT hashes FROM perceptual_hashes WHERE path = ?1 AND size = ?2 AND modified = ?3",
+                params![path.to_string_lossy(), size, modified],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+
+        Ok(hashes.as_deref().map(decode_hashes))
+    }
+
+    fn store_perceptual_hashes(&self, path: &Path, hashes: &[u64]) -> anyhow::Result<()> {
+        let (size, modified) = file_stamp(path)?;
+        let hashes = hashes
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        self.conn().execute(
+            "INSERT OR REPLACE INTO perceptual_hashes (path, hashes, size, modified) \
+             VALUES (?1, ?2, ?3, ?4)",
+            params![path.to_string_lossy(), hashes, size, modified],
+        )?;
+
+        Ok(())
+    }
+
+    fn paths_in(&self, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let paths = self
+            .conn()
+            .prepare("SELECT path FROM files")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|x| x.map(PathBuf::from))
+            .filter(|x| x.as_ref().map_or(true, |x| x.starts_with(dir)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(paths)
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Size and modification time of a file, used to tell if it changed since it was indexed.
+fn file_stamp(path: &Path) -> anyhow::Result<(i64, i64)> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("Failed to read metadata of {path:?}"))?;
+    let size = i64::try_from(metadata.len()).unwrap_or(i64::MAX);
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |x| i64::try_from(x.as_nanos()).unwrap_or(i64::MAX));
+
+    Ok((size, modified))
+}
+
+fn files_in(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    dirs::files_in(dir).with_context(|| format!("Failed to read directory {dir:?}"))
+}
+
+fn decode_hashes(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .as_chunks::<8>()
+        .0
+        .iter()
+        .copied()
+        .map(u64::from_le_bytes)
+        .collect()
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use anyhow::Context;
+use app_config::{DuplicateAction, NearDuplicateAction, CONFIG};
+use app_helpers::{id::time_thread_id, metadata::sidecar_path, trash::move_to_trash};
+use app_logger::{debug, info, warn};
+pub use index::ContentIndex;
+pub use perceptual::{distance, perceptual_hashes};
+
+mod index;
+mod perceptual;
+
+/// Used when `near_duplicate_distance` isn't configured.
+pub const DEFAULT_NEAR_DUPLICATE_DISTANCE: u32 = 8;
+
+/// Only one thread at a time may check files against the memes directory,
+/// otherwise two identical files downloaded in parallel would not see each other.
+static DEDUPE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Files with identical contents.
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    /// The oldest copy of the file.
+    pub original: PathBuf,
+    pub duplicates: Vec<PathBuf>,
+}
+
+/// Files that look alike according to their perceptual hashes.
+#[derive(Debug, Clone)]
+pub struct SimilarGroup {
+    /// Sorted from oldest to newest.
+    pub files: Vec<PathBuf>,
+}
+
+/// Hash the contents of a file with BLAKE3.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    let mut file = fs::File::open(path)?;
+
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Check freshly downloaded files against the memes directory
+/// and handle exact duplicates according to the configured [`DuplicateAction`],
+/// and near-duplicates according to the configured [`NearDuplicateAction`].
+///
+/// The content index is brought up to date with the memes directory first.
+/// Hashes are cached by file size and modification time, so only files that are new
+/// or changed since the last check are read. Perceptual hashes are only computed
+/// for the new files, the rest are indexed by [`find_similar`].
+///
+/// Returns the paths where the files can be found afterwards.
+/// Failures are only logged, and the original path is kept.
+pub fn deduplicate_new_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let _lock = DEDUPE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    // The index stores resolved paths, so they only match if symlinks are resolved here too
+    let memes_dir = CONFIG
+        .app
+        .memes_directory
+        .canonicalize()
+        .unwrap_or_else(|_| CONFIG.app.memes_directory.clone());
+    let index = match ContentIndex::open_default() {
+        Ok(index) => index,
+        Err(e) => {
+            warn!("Content index is unavailable, not checking for duplicates: {e:?}");
+            return paths.to_vec();
+        }
+    };
+
+    if let Err(e) = index.refresh(&memes_dir) {
+        warn!("Failed to index {memes_dir:?}, only checking against indexed files: {e:?}");
+    }
+
+    let check_near_duplicates = CONFIG.app.near_duplicate_action != NearDuplicateAction::Off;
+
+    paths
+        .iter()
+        .map(|original_path| {
+            let path = &original_path
+                .canonicalize()
+                .unwrap_or_else(|_| original_path.clone());
+
+            let new_path = deduplicate_file(&index, &memes_dir, path, CONFIG.app.duplicate_action)
+                .unwrap_or_else(|e| {
+                    warn!("Failed to check {path:?} for duplicates: {e:?}");
+                    path.clone()
+                });
+
+            let new_path = if !check_near_duplicates || new_path != *path {
+                new_path
+            } else {
+                check_near_duplicate(&index, &memes_dir, path, CONFIG.app.near_duplicate_action)
+                    .unwrap_or_else(|e| {
+                        warn!("Failed to check {path:?} for near-duplicates: {e:?}");
+                        new_path
+                    })
+            };
+
+            // Keep the path as it was given if the file stayed where it was
+            if new_path == *path {
+                original_path.clone()
+            } else {
+                new_path
+            }
+        })
+        .collect()
+}
+
+/// Send a file to the trash, along with its sidecar file if it has one.
+pub fn trash_with_sidecar(path: &Path) -> io::Result<()> {
+    move_to_trash(&path.to_path_buf())?;
+
+    let sidecar = sidecar_path(path);
+    if sidecar.is_file() {
+        move_to_trash(&sidecar)?;
+    }
+
+    Ok(())
+}
+
+/// Find files with identical contents in `dir` and its subdirectories.
+pub fn find_duplicates(dir: &Path) -> anyhow::Result<Vec<DuplicateGroup>> {
+    let _lock = DEDUPE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve directory {dir:?}"))?;
+
+    let index = ContentIndex::open_default()?;
+    index.refresh(&dir)?;
+    index.duplicates(&dir)
+}
+
+/// Find groups of images and videos in `dir` and its subdirectories
+/// whose perceptual hashes differ by at most `max_distance` bits.
+pub fn find_similar(dir: &Path, max_distance: u32) -> anyhow::Result<Vec<SimilarGroup>> {
+    let _lock = DEDUPE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve directory {dir:?}"))?;
+
+    let index = ContentIndex::open_default()?;
+    index.refresh(&dir)?;
+    index.refresh_perceptual(&dir)?;
+
+    let files = index.all_perceptual_hashes(&dir)?;
+
+    // Union-find over all pairs that are close enough
+    let mut parents = (0..files.len()).collect::<Vec<_>>();
+
+    for (i, (a_path, a)) in files.iter().enumerate() {
+        for (j, (b_path, b)) in files.iter().enumerate().skip(i + 1) {
+            if distance(a, b).is_some_and(|d| d <= max_distance) && !is_same_file(a_path, b_path) {
+                let (a_root, b_root) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[b_root] = a_root;
+            }
+        }
+    }
+
+    let mut groups = BTreeMap::<usize, Vec<PathBuf>>::new();
+    for (i, (path, _)) in files.into_iter().enumerate() {
+        groups
+            .entry(find_root(&mut parents, i))
+            .or_default()
+            .push(path);
+    }
+
+    Ok(groups
+        .into_values()
+        .filter(|x| x.len() > 1)
+        .map(|files| SimilarGroup { files })
+        .collect())
+}
+
+const fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+
+    i
+}
+
+fn check_near_duplicate(
+    index: &ContentIndex,
+    memes_dir: &Path,
+    path: &Path,
+    action: NearDuplicateAction,
+) -> anyhow::Result<PathBuf> {
+    let max_distance = CONFIG
+        .app
+        .near_duplicate_distance
+        .unwrap_or(DEFAULT_NEAR_DUPLICATE_DISTANCE);
+    let hashes = index.perceptual_hashes(path)?;
+
+    let closest = index
+        .all_perceptual_hashes(memes_dir)?
+        .into_iter()
+        .filter(|(x, _)| x != path && !is_same_file(x, path))
+        .filter_map(|(x, other)| distance(&hashes, &other).map(|d| (x, d)))
+        .filter(|(_, d)| *d <= max_distance)
+        .min_by_key(|(_, d)| *d);
+
+    let Some((existing, distance)) = closest else {
+        debug!("No near-duplicates of {path:?} found");
+        return Ok(path.to_path_buf());
+    };
+
+    match action {
+        NearDuplicateAction::Off => Ok(path.to_path_buf()),
+
+        NearDuplicateAction::Warn => {
+            warn!("{path:?} looks like {existing:?} (distance {distance})");
+
+            Ok(path.to_path_buf())
+        }
+
+        NearDuplicateAction::Skip => {
+            info!("{path:?} looks like {existing:?} (distance {distance}). Removing it...");
+
+            trash_with_sidecar(path)?;
+            index.remove(path)?;
+
+            Ok(existing)
+        }
+    }
+}
+
+fn deduplicate_file(
+    index: &ContentIndex,
+    memes_dir: &Path,
+    path: &Path,
+    action: DuplicateAction,
+) -> anyhow::Result<PathBuf> {
+    let hash = index.hash(path)?;
+
+    let existing = index
+        .find(&hash)?
+        .into_iter()
+        .find(|x| x != path && x.starts_with(memes_dir) && x.is_file());
+
+    let Some(existing) = existing else {
+        debug!("No duplicates of {path:?} found");
+        return Ok(path.to_path_buf());
+    };
+
+    if is_same_file(path, &existing) {
+        debug!("{path:?} is already linked to {existing:?}");
+        return Ok(path.to_path_buf());
+    }
+
+    match action {
+        DuplicateAction::Skip => {
+            info!("{path:?} is a duplicate of {existing:?}. Removing it...");
+
+            trash_with_sidecar(path)?;
+            index.remove(path)?;
+
+            Ok(existing)
+        }
+
+        DuplicateAction::Hardlink => {
+            info!("{path:?} is a duplicate of {existing:?}. Replacing it with a hard link...");
+
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let tmp_path = path.with_file_name(format!(".{file_name}.{}", time_thread_id()));
+
+            fs::hard_link(&existing, &tmp_path)?;
+            if let Err(e) = fs::rename(&tmp_path, path) {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(e.into());
+            }
+            index.hash(path)?;
+
+            Ok(path.to_path_buf())
+        }
+
+        DuplicateAction::Keep => {
+            info!("{path:?} is a duplicate of {existing:?}. Keeping both...");
+
+            Ok(path.to_path_buf())
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
@@ -0,0 +1,119 @@
+use std::{path::Path, process, time::Duration};
+
+use anyhow::{anyhow, bail};
+use app_config::CONFIGURATION;
+use app_helpers::ffprobe::{self, FfProbeError};
+use app_logger::{debug, trace};
+
+/// How many frames are sampled from a video.
+const VIDEO_SAMPLE_FRAMES: u32 = 5;
+
+/// Width and height of the grayscale thumbnail a hash is computed from.
+///
+/// One pixel wider than tall because each bit compares two neighbouring pixels.
+const THUMB_WIDTH: usize = 9;
+const THUMB_HEIGHT: usize = 8;
+
+/// Compute difference hashes (dHash) of an image, or of frames sampled evenly from a video.
+///
+/// Files without a video stream and frames of a single colour produce no hashes.
+pub fn perceptual_hashes(path: &Path) -> anyhow::Result<Vec<u64>> {
+    let info = match ffprobe::ffprobe(path) {
+        Ok(info) => info,
+        Err(e @ (FfProbeError::MissingBinary(_) | FfProbeError::Io(_))) => return Err(e.into()),
+        Err(e) => {
+            debug!("{path:?} is not a media file, not hashing it: {e}");
+            return Ok(vec![]);
+        }
+    };
+
+    let has_video = info
+        .streams
+        .iter()
+        .any(|s| s.codec_type.as_deref() == Some("video"));
+    if !has_video {
+        debug!("{path:?} has no video stream, not hashing it");
+        return Ok(vec![]);
+    }
+
+    let scale_filter = format!("scale={THUMB_WIDTH}:{THUMB_HEIGHT}:flags=area,format=gray");
+    let (filter, frames) = match info
+        .format
+        .get_duration()
+        .filter(|x| *x >= Duration::from_secs(1))
+    {
+        Some(duration) => (
+            format!(
+                "fps={VIDEO_SAMPLE_FRAMES}/{duration},{scale_filter}",
+                duration = duration.as_secs_f64()
+            ),
+            VIDEO_SAMPLE_FRAMES,
+        ),
+        None => (scale_filter, 1),
+    };
+
+    let mut cmd = process::Command::new(&CONFIGURATION.ffmpeg_path);
+    let cmd = cmd
+        .arg("-hide_banner")
+        .args(["-loglevel", "error"])
+        .arg("-i")
+        .arg(path)
+        .args(["-vf", &filter])
+        .args(["-frames:v", &frames.to_string()])
+        .args(["-f", "rawvideo", "-"]);
+    trace!("Running command {cmd:?}");
+
+    let output = cmd
+        .output()
+        .map_err(|e| anyhow!("Failed to run command {cmd:?}: {e}"))?;
+    if !output.status.success() {
+        bail!(
+            "ffmpeg exited with status code {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let hashes = output
+        .stdout
+        .as_chunks::<{ THUMB_WIDTH * THUMB_HEIGHT }>()
+        .0
+        .iter()
+        .map(|x| dhash(x))
+        .filter(|x| *x != 0)
+        .collect();
+
+    Ok(hashes)
+}
+
+/// Hash a row-major grayscale thumbnail.
+///
+/// Each bit is set if a pixel is darker than its right neighbour.
+fn dhash(pixels: &[u8]) -> u64 {
+    let mut hash = 0;
+
+    for row in pixels.as_chunks::<THUMB_WIDTH>().0 {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | u64::from(pair[0] < pair[1]);
+        }
+    }
+
+    hash
+}
+
+/// How different two sets of frame hashes are, in bits.
+///
+/// Every frame of the shorter set has to be close to some frame of the other,
+/// so an image matches a video containing it, but two videos only match if all
+/// their sampled frames look alike.
+/// Returns [`None`] if either set is empty.
+#[must_use]
+pub fn distance(a: &[u64], b: &[u64]) -> Option<u32> {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+
+    short
+        .iter()
+        .map(|x| long.iter().map(|y| (x ^ y).count_ones()).min())
+        .try_fold(0, |acc, x| x.map(|x| acc.max(x)))
+        .filter(|_| !short.is_empty())
+}
@@ -0,0 +1,437 @@
+use std::path::Path;
+
+use app_config::CONFIG;
+use app_helpers::metadata::PostMetadata;
+use app_logger::{debug, trace};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use regex::Regex;
+use reqwest::blocking::Response;
+use serde::Deserialize;
+
+use super::{collect_downloads, generic, twitter, yt_dlp, Downloader, DownloaderReturn};
+use crate::{
+    downloaders::common::request::{Client, WithCookies},
+    DownloaderError,
+};
+
+pub static URL_MATCH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^https?://(?:www\.)?bsky\.app/profile/(?P<actor>[^/]+)/post/(?P<rkey>[a-zA-Z0-9._~:-]+)",
+    )
+    .expect("Invalid regex")
+});
+
+pub struct BlueskyDownloader;
+
+impl Downloader for BlueskyDownloader {
+    fn name(&self) -> &'static str {
+        "bluesky"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        URL_MATCH.is_match(url)
+    }
+
+    fn priority(&self) -> i32 {
+        65
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download(download_dir, url)
+    }
+}
+
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
+    let post = fetch_post(url)?;
+    trace!("Bluesky post: {post:?}");
+
+    let media = post.media(CONFIG.app.include_quoted_posts);
+    debug!("Bluesky post media: {media:?}");
+
+    if media.is_empty() {
+        debug!("Bluesky post has no media, taking a screenshot...");
+        return twitter::download(download_dir, url);
+    }
+
+    let res = media
+        .par_iter()
+        .map(|(media, metadata)| {
+            let files = match media {
+                BlueskyMedia::Image { url, .. } => generic::download(download_dir, url),
+                BlueskyMedia::Video { playlist, .. } => yt_dlp::download(download_dir, playlist),
+            }?;
+
+            let metadata = PostMetadata {
+                alt_text: media.alt_text(),
+                ..metadata.clone()
+            };
+
+            Ok(files
+                .into_iter()
+                .map(|mut file| {
+                    file.metadata = metadata.clone().merge_missing(&file.metadata).clone();
+                    file
+                })
+                .collect::<Vec<_>>())
+        })
+        .collect::<Vec<Result<_, DownloaderError>>>();
+
+    collect_downloads(res)
+}
+
+/// Look up the post a URL points to through the `getPostThread` XRPC endpoint.
+fn fetch_post(url: &str) -> Result<PostView, DownloaderError> {
+    let captures = URL_MATCH
+        .captures(url)
+        .ok_or_else(|| DownloaderError::UnsupportedUrl(url.to_string()))?;
+    let actor = &captures["actor"];
+    let rkey = &captures["rkey"];
+
+    // The API resolves handles in post URIs as well as DIDs
+    let post_uri = format!("at://{actor}/app.bsky.feed.post/{rkey}");
+    let api_url = format!(
+        "{}/xrpc/app.bsky.feed.getPostThread",
+        CONFIG
+            .endpoints
+            .bluesky_api_base_url()
+            .trim_end_matches('/')
+    );
+    debug!("Fetching bluesky post {post_uri:?} from {api_url:?}");
+
+    let thread = Client::default()?
+        .get(&api_url)
+        .with_cookies()
+        .query(&[
+            ("uri", post_uri.as_str()),
+            ("depth", "0"),
+            ("parentHeight", "0"),
+        ])
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::json::<PostThread>)?
+        .thread;
+
+    thread.into_post(&post_uri)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BlueskyMedia {
+    /// An image, at the largest size the CDN serves.
+    Image { url: String, alt: Option<String> },
+    /// A video as an HLS playlist.
+    Video {
+        playlist: String,
+        alt: Option<String>,
+    },
+}
+
+impl BlueskyMedia {
+    fn alt_text(&self) -> Option<String> {
+        match self {
+            Self::Image { alt, .. } | Self::Video { alt, .. } => {
+                alt.clone().filter(|x| !x.is_empty())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PostThread {
+    thread: ThreadView,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "$type")]
+enum ThreadView {
+    #[serde(rename = "app.bsky.feed.defs#threadViewPost")]
+    Post { post: PostView },
+    #[serde(rename = "app.bsky.feed.defs#notFoundPost")]
+    NotFound,
+    #[serde(rename = "app.bsky.feed.defs#blockedPost")]
+    Blocked,
+    #[serde(other)]
+    Other,
+}
+
+impl ThreadView {
+    fn into_post(self, post_uri: &str) -> Result<PostView, DownloaderError> {
+        match self {
+            Self::Post { post } => Ok(post),
+            Self::NotFound => Err(DownloaderError::extractor(format!(
+                "Bluesky post {post_uri:?} was not found"
+            ))),
+            Self::Blocked => Err(DownloaderError::extractor(format!(
+                "Bluesky post {post_uri:?} is blocked"
+            ))),
+            Self::Other => Err(DownloaderError::extractor(format!(
+                "Bluesky returned an unknown kind of thread for {post_uri:?}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PostView {
+    uri: String,
+    author: Author,
+    record: PostRecord,
+    embed: Option<EmbedView>,
+}
+
+impl PostView {
+    /// The media of the post, and optionally of the post it quotes,
+    /// each with the details of the post it belongs to.
+    fn media(&self, include_quoted: bool) -> Vec<(BlueskyMedia, PostMetadata)> {
+        let metadata = post_metadata(&self.uri, &self.author, &self.record);
+        let mut media = vec![];
+
+        if let Some(embed) = &self.embed {
+            media.extend(embed.media().into_iter().map(|x| (x, metadata.clone())));
+
+            if include_quoted {
+                media.extend(embed.quoted_media());
+            }
+        }
+
+        media
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Author {
+    handle: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PostRecord {
+    #[serde(default)]
+    text: String,
+}
+
+fn post_metadata(uri: &str, author: &Author, record: &PostRecord) -> PostMetadata {
+    PostMetadata {
+        author: Some(author.handle.clone()),
+        title: None,
+        description: Some(record.text.trim().to_string()).filter(|x| !x.is_empty()),
+        // `at://did:plc:.../app.bsky.feed.post/<rkey>`
+        post_id: uri.rsplit('/').next().map(ToString::to_string),
+        alt_text: None,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "$type")]
+enum EmbedView {
+    #[serde(rename = "app.bsky.embed.images#view")]
+    Images { images: Vec<ImageView> },
+    #[serde(rename = "app.bsky.embed.video#view")]
+    Video {
+        playlist: String,
+        alt: Option<String>,
+    },
+    #[serde(rename = "app.bsky.embed.record#view")]
+    Record { record: RecordView },
+    #[serde(rename = "app.bsky.embed.recordWithMedia#view")]
+    RecordWithMedia {
+        media: Box<Self>,
+        record: RecordEmbed,
+    },
+    /// Link cards and anything newer.
+    #[serde(other)]
+    Other,
+}
+
+impl EmbedView {
+    fn media(&self) -> Vec<BlueskyMedia> {
+        match self {
+            Self::Images { images } => images
+                .iter()
+                .map(|x| BlueskyMedia::Image {
+                    url: x.fullsize.clone(),
+                    alt: x.alt.clone(),
+                })
+                .collect(),
+            Self::Video { playlist, alt } => vec![BlueskyMedia::Video {
+                playlist: playlist.clone(),
+                alt: alt.clone(),
+            }],
+            Self::RecordWithMedia { media, .. } => media.media(),
+            Self::Record { .. } | Self::Other => vec![],
+        }
+    }
+
+    /// Media of the quoted post, without going into posts that it quotes.
+    fn quoted_media(&self) -> Vec<(BlueskyMedia, PostMetadata)> {
+        let record = match self {
+            Self::Record { record }
+            | Self::RecordWithMedia {
+                record: RecordEmbed { record },
+                ..
+            } => record,
+            Self::Images { .. } | Self::Video { .. } | Self::Other => return vec![],
+        };
+
+        let RecordView::Post {
+            uri,
+            author,
+            value,
+            embeds,
+        } = record
+        else {
+            return vec![];
+        };
+
+        let metadata = post_metadata(uri, author, value);
+
+        embeds
+            .iter()
+            .flat_map(Self::media)
+            .map(|x| (x, metadata.clone()))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ImageView {
+    fullsize: String,
+    alt: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RecordEmbed {
+    record: RecordView,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "$type")]
+enum RecordView {
+    #[serde(rename = "app.bsky.embed.record#viewRecord")]
+    Post {
+        uri: String,
+        author: Author,
+        value: PostRecord,
+        #[serde(default)]
+        embeds: Vec<EmbedView>,
+    },
+    /// Deleted or blocked posts, feeds, lists and other records.
+    #[serde(other)]
+    Other,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(url: &str, alt: &str) -> BlueskyMedia {
+        BlueskyMedia::Image {
+            url: url.to_string(),
+            alt: Some(alt.to_string()),
+        }
+    }
+
+    #[test]
+    fn images_post() {
+        let thread: PostThread = fixture!(json "bluesky/images.json");
+        let post = thread
+            .thread
+            .into_post("at://fixture")
+            .expect("Thread has no post");
+        let media = post.media(false);
+
+        assert_eq!(
+            media.iter().map(|x| x.0.clone()).collect::<Vec<_>>(),
+            [
+                image(
+                    "https://cdn.bsky.app/img/feed_fullsize/plain/did:plc:z72i7hdynmk6r22z27h6tvur/bafkreiasrfwkfr5vzbmb3mpcwfgzvxntbkrhnbsy3ewdn7rptijx6bmhkq@jpeg",
+                    "A dog in a burning room saying this is fine"
+                ),
+                image(
+                    "https://cdn.bsky.app/img/feed_fullsize/plain/did:plc:z72i7hdynmk6r22z27h6tvur/bafkreif6tqyxexrpxd7rnngdbnvxzz2s6lx3uqh3aigxvvtzzjbnfozq6e@jpeg",
+                    ""
+                ),
+            ]
+        );
+        assert_eq!(media[1].0.alt_text(), None);
+        assert_eq!(
+            media[0].1,
+            PostMetadata {
+                author: Some("memes.bsky.social".to_string()),
+                title: None,
+                description: Some("monday mood".to_string()),
+                post_id: Some("3kq4hz2j5ts2c".to_string()),
+                alt_text: None,
+            }
+        );
+    }
+
+    #[test]
+    fn video_post() {
+        let thread: PostThread = fixture!(json "bluesky/video.json");
+        let post = thread
+            .thread
+            .into_post("at://fixture")
+            .expect("Thread has no post");
+
+        assert_eq!(
+            post.media(false).into_iter().map(|x| x.0).collect::<Vec<_>>(),
+            [BlueskyMedia::Video {
+                playlist: "https://video.bsky.app/watch/did%3Aplc%3A4llrhdclvdlmmynkwsmg5tdc/bafkreihwihm6kpd6zuwhhlro75p5qks5qtrcu55jp3gddbfjsieiv7wuka/playlist.m3u8".to_string(),
+                alt: Some("A cat knocking a glass off a table".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn record_with_media_post() {
+        let thread: PostThread = fixture!(json "bluesky/record_with_media.json");
+        let post = thread
+            .thread
+            .into_post("at://fixture")
+            .expect("Thread has no post");
+        let own = image(
+            "https://cdn.bsky.app/img/feed_fullsize/plain/did:plc:ragtjsm2j2vknwkz3zp4oxrd/bafkreib3ww4v6jl4o4jdthv5vnlo2jtnfqgpcy5zdxmnp2m4atp7xvhfgm@jpeg",
+            "Same meme but worse",
+        );
+        let quoted = image(
+            "https://cdn.bsky.app/img/feed_fullsize/plain/did:plc:z72i7hdynmk6r22z27h6tvur/bafkreidh5e3yqxnsjbhx3cddhj5ncu4fyr7m4ktvjiiomrbmkdnsmzq3oq@jpeg",
+            "The original meme",
+        );
+
+        assert_eq!(
+            post.media(false)
+                .into_iter()
+                .map(|x| x.0)
+                .collect::<Vec<_>>(),
+            std::slice::from_ref(&own)
+        );
+
+        let media = post.media(true);
+        assert_eq!(
+            media.iter().map(|x| x.0.clone()).collect::<Vec<_>>(),
+            [own, quoted]
+        );
+        assert_eq!(media[1].1.author.as_deref(), Some("memes.bsky.social"));
+        assert_eq!(media[1].1.post_id.as_deref(), Some("3kzq6u5bpuk2x"));
+        assert_eq!(media[1].1.description.as_deref(), Some("the original"));
+    }
+
+    #[test]
+    fn blocked_quote_has_no_media() {
+        let thread: PostThread = fixture!(json "bluesky/blocked_quote.json");
+        let post = thread
+            .thread
+            .into_post("at://fixture")
+            .expect("Thread has no post");
+
+        assert_eq!(post.media(true), []);
+    }
+
+    #[test]
+    fn blocked_post_is_an_error() {
+        let PostThread { thread } = fixture!(json "bluesky/blocked_post.json");
+
+        assert!(matches!(thread, ThreadView::Blocked));
+        assert!(thread.into_post("at://fixture").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use percent_encoding::percent_decode_str;
+
+/// Get the file name a `Content-Disposition` header suggests.
+///
+/// Prefers the RFC 5987 `filename*` parameter over the plain `filename`,
+/// since servers send the plain one as a fallback for old clients.
+/// Any directories in the name are dropped.
+pub fn file_name(header: &str) -> Option<String> {
+    let params = parameters(header);
+    let find = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+
+    let name = find("filename*")
+        .and_then(decode_extended_value)
+        .or_else(|| find("filename").map(ToString::to_string))?;
+
+    let name = name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    if name.is_empty() || name == "." || name == ".." {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Split the `key=value` parameters after the disposition type,
+/// removing quotes and escapes from quoted values.
+fn parameters(header: &str) -> Vec<(String, String)> {
+    let mut params = vec![];
+    let mut chars = header.chars().peekable();
+
+    // Skip the disposition type, eg. `attachment`
+    for c in chars.by_ref() {
+        if c == ';' {
+            break;
+        }
+    }
+
+    loop {
+        let mut key = String::new();
+        let mut separator = None;
+        for c in chars.by_ref() {
+            if c == '=' || c == ';' {
+                separator = Some(c);
+                break;
+            }
+            key.push(c);
+        }
+        let key = key.trim().to_string();
+
+        match separator {
+            Some('=') => {}
+            // A parameter without a value, eg. `foo` in `attachment; foo; filename=a.jpg`
+            Some(_) => continue,
+            None => break,
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+            }
+        } else {
+            value = chars.by_ref().take_while(|c| *c != ';').collect();
+            value = value.trim().to_string();
+        }
+
+        if !key.is_empty() {
+            params.push((key, value));
+        }
+    }
+
+    params
+}
+
+/// Decode a `charset'language'percent-encoded` value from RFC 5987.
+fn decode_extended_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let bytes = percent_decode_str(parts.next()?).collect::<Vec<_>>();
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_utf8_extended_value() {
+        assert_eq!(
+            file_name("attachment; filename*=UTF-8''%E7%8C%AB%20meme.png").as_deref(),
+            Some("猫 meme.png")
+        );
+    }
+
+    #[test]
+    fn decodes_latin1_extended_value() {
+        assert_eq!(
+            file_name("attachment; filename*=iso-8859-1'en'Z%F6e%20%E9t%E9.jpg").as_deref(),
+            Some("Zöe été.jpg")
+        );
+    }
+
+    #[test]
+    fn prefers_extended_value() {
+        assert_eq!(
+            file_name("attachment; filename=\"fallback.jpg\"; filename*=UTF-8''%C3%BCber.jpg")
+                .as_deref(),
+            Some("über.jpg")
+        );
+    }
+
+    #[test]
+    fn unescapes_quoted_values() {
+        assert_eq!(
+            file_name(r#"inline; filename="a \"quoted\" n\ame; here.gif""#).as_deref(),
+            Some(r#"a "quoted" name; here.gif"#)
+        );
+    }
+
+    #[test]
+    fn skips_parameters_without_values() {
+        assert_eq!(
+            file_name("attachment; foo; filename=\"a.jpg\"").as_deref(),
+            Some("a.jpg")
+        );
+    }
+
+    #[test]
+    fn strips_directories() {
+        assert_eq!(
+            file_name("attachment; filename=\"../x.png\"").as_deref(),
+            Some("x.png")
+        );
+        assert_eq!(
+            file_name(r#"attachment; filename="C:\\x.png""#).as_deref(),
+            Some("x.png")
+        );
+        assert_eq!(file_name("attachment; filename=\"..\""), None);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs, io,
+    path::Path,
+    process,
+    sync::{Arc, Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use app_config::{CookieSource, CONFIG, CONFIGURATION};
+use app_helpers::id::short_id;
+use app_logger::{debug, trace, warn};
+use once_cell::sync::Lazy;
+use url::Url;
+
+/// Cookies of every source that was needed so far, so files are only read
+/// and browsers only asked once per run.
+static LOADED: Lazy<Mutex<HashMap<CookieSource, Arc<Vec<Cookie>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A cookie from a Netscape `cookies.txt` file, as browser extensions and `yt-dlp` write them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub domain: String,
+    pub include_subdomains: bool,
+    pub path: String,
+    pub secure: bool,
+    /// Seconds since the Unix epoch, 0 for session cookies.
+    pub expires: u64,
+    pub name: String,
+    pub value: String,
+}
+
+impl Cookie {
+    /// Whether a browser would send this cookie along with a request to `url`.
+    #[must_use]
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let domain = self.domain.trim_start_matches('.');
+
+        let domain_matches = host.eq_ignore_ascii_case(domain)
+            || (self.include_subdomains
+                && host
+                    .to_lowercase()
+                    .ends_with(&format!(".{}", domain.to_lowercase())));
+        let is_expired = self.expires != 0
+            && SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .is_ok_and(|now| now.as_secs() > self.expires);
+
+        domain_matches
+            && url.path().starts_with(&self.path)
+            && (!self.secure || url.scheme() == "https")
+            && !is_expired
+    }
+}
+
+/// Read the cookies in a Netscape `cookies.txt` file.
+pub fn read_cookies_file(path: &Path) -> io::Result<Vec<Cookie>> {
+    Ok(parse(&fs::read_to_string(path)?))
+}
+
+/// Parse the lines of a Netscape `cookies.txt` file, skipping any that are malformed.
+#[must_use]
+pub fn parse(contents: &str) -> Vec<Cookie> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            // curl marks `HttpOnly` cookies with a prefix on what would otherwise be a comment
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let mut fields = line.split('\t');
+            Some(Cookie {
+                domain: fields.next()?.to_string(),
+                include_subdomains: fields.next()?.eq_ignore_ascii_case("TRUE"),
+                path: fields.next()?.to_string(),
+                secure: fields.next()?.eq_ignore_ascii_case("TRUE"),
+                expires: fields.next()?.parse().unwrap_or_default(),
+                name: fields.next()?.to_string(),
+                value: fields.next().unwrap_or_default().trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The value of the `Cookie` header a browser would send to `url`.
+#[must_use]
+pub fn header_for(cookies: &[Cookie], url: &Url) -> Option<String> {
+    let header = cookies
+        .iter()
+        .filter(|x| x.matches(url))
+        .map(|x| format!("{}={}", x.name, x.value))
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    Some(header).filter(|x| !x.is_empty())
+}
+
+/// The `Cookie` header to send to `url`, from the cookies configured for its site.
+#[must_use]
+pub fn header_for_url(url: &Url) -> Option<String> {
+    let source = CONFIG.cookies.for_host(url.host_str()?)?;
+
+    header_for(&load(&source), url)
+}
+
+/// Arguments that make `yt-dlp` use the cookies configured for the site of `url`.
+#[must_use]
+pub fn yt_dlp_args(url: &str) -> Vec<OsString> {
+    let source = Url::parse(url)
+        .ok()
+        .and_then(|x| x.host_str().and_then(|host| CONFIG.cookies.for_host(host)));
+
+    match source {
+        Some(CookieSource::File(path)) => vec!["--cookies".into(), path.into()],
+        Some(CookieSource::Browser(browser)) => {
+            vec!["--cookies-from-browser".into(), browser.into()]
+        }
+        None => vec![],
+    }
+}
+
+fn load(source: &CookieSource) -> Arc<Vec<Cookie>> {
+    let mut loaded = LOADED.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if let Some(cookies) = loaded.get(source) {
+        return Arc::clone(cookies);
+    }
+
+    let cookies = match source {
+        CookieSource::File(path) => read_cookies_file(path),
+        CookieSource::Browser(browser) => read_browser_cookies(browser),
+    }
+    .unwrap_or_else(|e| {
+        warn!("Failed to load cookies from {source:?}: {e}");
+        vec![]
+    });
+    trace!("Loaded {} cookies from {source:?}", cookies.len());
+
+    let cookies = Arc::new(cookies);
+    loaded.insert(source.clone(), Arc::clone(&cookies));
+
+    cookies
+}
+
+/// Have `yt-dlp` export the cookies of a browser, as only it can read their cookie stores.
+///
+/// Without a URL `yt-dlp` exits with an error, but still writes out the cookies.
+fn read_browser_cookies(browser: &str) -> io::Result<Vec<Cookie>> {
+    let export_path = CONFIG
+        .cache_dir()
+        .join(format!("cookies-{}.txt", short_id()));
+    fs::create_dir_all(CONFIG.cache_dir())?;
+
+    let mut cmd = process::Command::new(&CONFIGURATION.yt_dlp_path);
+    cmd.arg("--ignore-config")
+        .args(["--cookies-from-browser", browser])
+        .arg("--cookies")
+        .arg(&export_path);
+    debug!("Exporting browser cookies with: {cmd:?}");
+
+    let output = cmd.output()?;
+    trace!("Cookie export output: {output:?}");
+
+    let cookies = read_cookies_file(&export_path);
+    remove_export(&export_path);
+
+    cookies.map_err(|e| {
+        io::Error::other(format!(
+            "yt-dlp did not export the cookies ({e}): {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    })
+}
+
+fn remove_export(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("Failed to remove exported cookies {path:?}: {e}");
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod content_disposition;
+pub mod cookies;
 pub mod request;
 
 pub static USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like \
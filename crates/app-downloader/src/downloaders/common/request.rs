@@ -1,16 +1,24 @@
 use std::time::Duration;
 
-use reqwest::blocking::{Client as ReqwestClient, ClientBuilder as ReqwestClientBuilder};
+use reqwest::{
+    blocking::{Client as ReqwestClient, ClientBuilder as ReqwestClientBuilder, RequestBuilder},
+    header,
+};
 
-use super::USER_AGENT;
+use super::{cookies, USER_AGENT};
+use crate::DownloaderError;
+
+/// How long to wait for a connection to a server when streaming a download.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a streamed download can go without receiving data.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct Client;
 
 impl Client {
-    pub fn default() -> Result<ReqwestClient, String> {
-        Self::builder()
-            .build()
-            .map_err(|e| format!("Failed to create client: {:?}", e))
+    pub fn default() -> Result<ReqwestClient, DownloaderError> {
+        Ok(Self::builder().build()?)
     }
 
     pub fn builder() -> ReqwestClientBuilder {
@@ -18,4 +26,37 @@ impl Client {
             .user_agent(USER_AGENT)
             .timeout(Duration::from_secs(5))
     }
+
+    /// A client for downloading files, which can take any amount of time.
+    ///
+    /// The blocking client applies its timeout to sending the request and to every
+    /// single read of the body, so it only fails once the server stops sending data.
+    pub fn streaming() -> Result<ReqwestClient, DownloaderError> {
+        Ok(ReqwestClient::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(IDLE_TIMEOUT)
+            .build()?)
+    }
+}
+
+pub trait WithCookies {
+    /// Send the cookies configured for the site of the request along with it.
+    #[must_use]
+    fn with_cookies(self) -> Self;
+}
+
+impl WithCookies for RequestBuilder {
+    fn with_cookies(self) -> Self {
+        // The builder doesn't expose the URL, only the request it builds does
+        let cookie = self
+            .try_clone()
+            .and_then(|x| x.build().ok())
+            .and_then(|x| cookies::header_for_url(x.url()));
+
+        match cookie {
+            Some(cookie) => self.header(header::COOKIE, cookie),
+            None => self,
+        }
+    }
 }
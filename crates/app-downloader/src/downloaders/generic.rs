@@ -1,53 +1,105 @@
-use std::{ffi::OsString, fs::File, path::PathBuf, string::ToString};
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    string::ToString,
+    thread,
+    time::{Duration, Instant},
+};
 
-use app_helpers::id::time_id;
+pub use app_helpers::template::MAX_FILENAME_LENGTH;
+use app_helpers::{
+    id::short_id,
+    progress::{self, Progress},
+};
+use reqwest::{
+    blocking::{Client as ReqwestClient, RequestBuilder, Response},
+    header, StatusCode,
+};
 use unicode_segmentation::UnicodeSegmentation;
 use url::Url;
 
-use super::DownloaderReturn;
-use crate::downloaders::common::request::Client;
+use super::{DownloadedFile, DownloaderReturn};
+use crate::{
+    downloaders::common::{
+        content_disposition,
+        request::{Client, WithCookies},
+    },
+    DownloaderError,
+};
 
-pub const MAX_FILENAME_LENGTH: usize = 120;
+/// How often to report download progress at most.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
-pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// How much of the start of a download to look at to recognize the file type.
+const SNIFF_LENGTH: u64 = 8 * 1024;
+
+/// `Content-Type`s that say nothing about what the file is.
+const GENERIC_MIME_TYPES: &[&str] = &[
+    "application/octet-stream",
+    "binary/octet-stream",
+    "application/binary",
+    "application/unknown",
+];
+
+/// Longest extension taken from a file name, longer ones are most likely part of the name.
+const MAX_EXTENSION_LENGTH: usize = 10;
+
+/// How many times to pick an interrupted download up again before giving up.
+const MAX_RESUMES: u32 = 5;
+
+/// How long to wait before resuming an interrupted download.
+const RESUME_DELAY: Duration = Duration::from_secs(1);
+
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
     app_logger::info!("Downloading {:?} to {:?}", url, download_dir);
 
-    let mut res = Client::default()?
-        .get(url)
-        .send()
-        .map_err(|e| format!("Failed to send request: {:?}", e))?
-        .error_for_status()
-        .map_err(|e| format!("Failed to get response: {:?}", e))?;
+    let client = Client::streaming()?;
+    let mut res = client.get(url).with_cookies().send()?.error_for_status()?;
+    // Has to be taken before reading, afterwards it no longer covers the whole body
+    let total = res.content_length();
 
-    let mime_type = res.headers().get("content-type").map(|x| x.to_str());
-    app_logger::debug!("Got mime type: {:?}", mime_type);
-    let mime_type = match mime_type {
-        Some(Ok(mime_type)) => mime_type,
-        _ => "",
-    };
+    let head = read_head(&mut res);
 
-    let extension =
-        mime2ext::mime2ext(mime_type).map_or("unknown".to_string(), |x| (*x).to_string());
+    let suggested_name = res
+        .headers()
+        .get(header::CONTENT_DISPOSITION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(content_disposition::file_name)
+        .map(PathBuf::from);
+    app_logger::debug!("Got suggested file name: {:?}", suggested_name);
 
+    let url_name = Url::parse(url)
+        .ok()
+        .map(|x| PathBuf::from(x.path()))
+        .filter(|x| x.file_stem().is_some());
+
+    let extension = file_extension(
+        &res,
+        &head.bytes,
+        suggested_name.as_deref().or(url_name.as_deref()),
+    );
     app_logger::debug!("Got extension: {:?}", extension);
 
-    let id = time_id();
+    let id = short_id();
     let mut file_name = OsString::from(&id);
 
     let taken_filename_len = id.len() + 1 + extension.len();
 
-    let url_file_name = Url::parse(url)
-        .ok()
-        .map(|x| PathBuf::from(x.path()))
-        .and_then(|x| {
-            let stem = x.file_stem()?;
-
+    let name_stem = suggested_name
+        .as_deref()
+        .or(url_name.as_deref())
+        .and_then(Path::file_stem)
+        .and_then(|stem| {
             let trunc = stem
                 .to_string_lossy()
                 .graphemes(true)
                 .filter(|x| !x.chars().all(char::is_control))
                 .filter(|x| !x.contains(['\\', '/', ':', '*', '?', '"', '<', '>', '|']))
-                .take(MAX_FILENAME_LENGTH - 1 - taken_filename_len)
+                .take(MAX_FILENAME_LENGTH.saturating_sub(1 + taken_filename_len))
                 .collect::<String>();
 
             if trunc.is_empty() {
@@ -57,22 +109,485 @@ pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
             }
         });
 
-    if let Some(url_file_name) = url_file_name {
-        app_logger::trace!("Got url file name: {:?}", url_file_name);
+    if let Some(name_stem) = &name_stem {
+        app_logger::trace!("Got file name: {:?}", name_stem);
         file_name.push(".");
-        file_name.push(url_file_name);
+        file_name.push(name_stem);
     }
 
     file_name.push(".");
     file_name.push(extension);
 
     let file_path = download_dir.join(file_name);
-    app_logger::debug!("Writing to file: {:?}", &file_path);
-    let mut out_file =
-        File::create(&file_path).map_err(|e| format!("Failed to create file: {:?}", e))?;
+    let part_path = {
+        let mut x = file_path.clone().into_os_string();
+        x.push(".part");
+        PathBuf::from(x)
+    };
+    app_logger::debug!("Writing to file: {:?}", &part_path);
+
+    let res = stream_to_file(
+        &client,
+        url,
+        WithCookies::with_cookies,
+        head,
+        res,
+        total,
+        &part_path,
+    );
+    if let Err(e) = res {
+        let _ = fs::remove_file(&part_path);
+        return Err(e);
+    }
+
+    fs::rename(&part_path, &file_path).map_err(DownloaderError::io(format!(
+        "Failed to rename {part_path:?} to {file_path:?}"
+    )))?;
+
+    Ok(vec![DownloadedFile::new(file_path).with_name(name_stem)])
+}
+
+/// The start of a response body, read to recognize the file type before the file is created.
+struct Head {
+    bytes: Vec<u8>,
+    /// Why reading stopped early, if the connection broke before [`SNIFF_LENGTH`] bytes.
+    error: Option<DownloaderError>,
+}
+
+fn read_head(res: &mut Response) -> Head {
+    let mut bytes = vec![];
+    // Whatever was read before an error is kept, so the download can resume after it
+    let error = res
+        .take(SNIFF_LENGTH)
+        .read_to_end(&mut bytes)
+        .err()
+        .map(read_error);
+
+    Head { bytes, error }
+}
+
+/// Write `head`, the start of the body that was already read, and the rest of `res` to `path`.
+///
+/// `total` is the size of the whole body, if the server said.
+/// If the connection breaks and the server supports range requests,
+/// the rest of the body is requested again with `prepare` applied, up to [`MAX_RESUMES`] times.
+fn stream_to_file(
+    client: &ReqwestClient,
+    url: &str,
+    prepare: impl Fn(RequestBuilder) -> RequestBuilder,
+    head: Head,
+    mut res: Response,
+    total: Option<u64>,
+    path: &Path,
+) -> Result<(), DownloaderError> {
+    let resumable = res
+        .headers()
+        .get(header::ACCEPT_RANGES)
+        .is_some_and(|x| x.as_bytes() == b"bytes");
+    // Makes the server send the whole file again instead of a range if it changed meanwhile
+    let validator = res
+        .headers()
+        .get(header::ETAG)
+        .or_else(|| res.headers().get(header::LAST_MODIFIED))
+        .cloned();
+
+    let mut out = PartFile::create(path, total)?;
+    out.copy_from(&mut &head.bytes[..])??;
+    let mut head_error = head.error;
+    let mut resumes = 0;
+
+    'download: loop {
+        let result = match head_error.take() {
+            Some(e) => Err(e),
+            None => out.copy_from(&mut res)?,
+        };
+        let mut error = match result {
+            Ok(()) => match total {
+                Some(total) if out.downloaded < total => {
+                    incomplete_error(url, out.downloaded, total)
+                }
+                _ => break,
+            },
+            Err(e) => e,
+        };
+
+        res = loop {
+            if !resumable || resumes >= MAX_RESUMES {
+                return Err(error);
+            }
+            resumes += 1;
 
-    res.copy_to(&mut out_file)
-        .map_err(|e| format!("Failed to copy response to file: {:?}", e))?;
+            app_logger::warn!(
+                "Download of {url:?} interrupted after {downloaded} bytes, resuming \
+                 ({resumes}/{MAX_RESUMES}): {error}",
+                downloaded = out.downloaded,
+            );
+            thread::sleep(RESUME_DELAY);
 
-    Ok(vec![file_path])
+            let mut req = client
+                .get(url)
+                .header(header::RANGE, format!("bytes={}-", out.downloaded));
+            if let Some(validator) = &validator {
+                req = req.header(header::IF_RANGE, validator);
+            }
+
+            match prepare(req).send().and_then(Response::error_for_status) {
+                Ok(res) => break res,
+                // Nothing left to send
+                Err(e) if e.status() == Some(StatusCode::RANGE_NOT_SATISFIABLE) => break 'download,
+                Err(e) => error = e.into(),
+            }
+        };
+
+        let start = res
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(content_range_start);
+
+        if res.status() != StatusCode::PARTIAL_CONTENT || start != Some(out.downloaded) {
+            app_logger::debug!("Server sent the whole file instead of the rest, starting over");
+            out.restart()?;
+        }
+    }
+
+    out.finish()?;
+
+    if let Some(total) = total {
+        if out.downloaded != total {
+            return Err(incomplete_error(url, out.downloaded, total));
+        }
+    }
+
+    Ok(())
+}
+
+fn incomplete_error(url: &str, downloaded: u64, total: u64) -> DownloaderError {
+    DownloaderError::Io {
+        context: format!("Download of {url:?} ended after {downloaded} of {total} bytes"),
+        source: io::ErrorKind::UnexpectedEof.into(),
+    }
+}
+
+/// Pick the extension for a download.
+///
+/// Trusts the `Content-Type` header unless it's missing or generic, then tries to
+/// recognize the start of the file, and then goes by the name the file had.
+fn file_extension(res: &Response, head: &[u8], name: Option<&Path>) -> String {
+    let mime_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.split(';').next())
+        .map(str::trim)
+        .filter(|x| !GENERIC_MIME_TYPES.iter().any(|y| x.eq_ignore_ascii_case(y)));
+    app_logger::debug!("Got mime type: {:?}", mime_type);
+
+    if let Some(extension) = mime_type.and_then(mime2ext::mime2ext) {
+        return extension.to_string();
+    }
+
+    if let Some(kind) = infer::get(head) {
+        app_logger::debug!("Recognized file as {:?}", kind.mime_type());
+        return kind.extension().to_string();
+    }
+
+    name_extension(name).unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The extension of a file name, if it looks like one.
+///
+/// Anything past the last `.` of names like `some.sentence.that.goes.on` is not an extension.
+fn name_extension(name: Option<&Path>) -> Option<String> {
+    name.and_then(Path::extension)
+        .map(|x| x.to_string_lossy().to_string())
+        .filter(|x| {
+            !x.is_empty() && x.len() <= MAX_EXTENSION_LENGTH && x.chars().all(char::is_alphanumeric)
+        })
+}
+
+/// Get where a range starts from a `Content-Range: bytes <start>-<end>/<size>` header.
+fn content_range_start(value: &str) -> Option<u64> {
+    value
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// A file being downloaded, reporting [`Progress::Downloading`] as it's written.
+struct PartFile {
+    file: File,
+    path: PathBuf,
+    downloaded: u64,
+    total: Option<u64>,
+    last_report: Instant,
+}
+
+impl PartFile {
+    fn create(path: &Path, total: Option<u64>) -> Result<Self, DownloaderError> {
+        let file = File::create(path)
+            .map_err(DownloaderError::io(format!("Failed to create {path:?}")))?;
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            downloaded: 0,
+            total,
+            last_report: Instant::now(),
+        })
+    }
+
+    /// Append everything that can be read from `reader`.
+    ///
+    /// Reading errors are returned in the inner result, as those can be resumed from.
+    fn copy_from<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<Result<(), DownloaderError>, DownloaderError> {
+        let mut buf = vec![0; BUFFER_SIZE];
+
+        loop {
+            let read = match reader.read(&mut buf) {
+                Ok(0) => return Ok(Ok(())),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Ok(Err(read_error(e))),
+            };
+
+            self.file
+                .write_all(&buf[..read])
+                .map_err(DownloaderError::io(format!(
+                    "Failed to write to {:?}",
+                    self.path
+                )))?;
+            self.downloaded += read as u64;
+
+            if self.last_report.elapsed() >= PROGRESS_INTERVAL {
+                self.report();
+            }
+        }
+    }
+
+    /// Throw away what was written so far.
+    fn restart(&mut self) -> Result<(), DownloaderError> {
+        self.file
+            .set_len(0)
+            .and_then(|()| self.file.rewind())
+            .map_err(DownloaderError::io(format!(
+                "Failed to truncate {:?}",
+                self.path
+            )))?;
+        self.downloaded = 0;
+        self.report();
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), DownloaderError> {
+        self.report();
+        self.file.sync_all().map_err(DownloaderError::io(format!(
+            "Failed to write {:?}",
+            self.path
+        )))
+    }
+
+    fn report(&mut self) {
+        progress::report(Progress::Downloading {
+            downloaded: self.downloaded,
+            total: self.total,
+        });
+        self.last_report = Instant::now();
+    }
+}
+
+/// Keep network errors as such, so they count as transient.
+fn read_error(e: io::Error) -> DownloaderError {
+    match e.downcast::<reqwest::Error>() {
+        Ok(e) => DownloaderError::Network(e),
+        Err(e) => DownloaderError::Io {
+            context: "Failed to read response".to_string(),
+            source: e,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    use super::*;
+
+    /// What the test server does with one request.
+    struct Reply {
+        head: String,
+        body: Vec<u8>,
+    }
+
+    /// Serve one reply per connection, closing each connection after its body
+    /// so a body shorter than its `Content-Length` looks like a broken download.
+    ///
+    /// Returns the URL to request and the request heads the server received.
+    fn serve(replies: Vec<Reply>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind test server");
+        let url = format!(
+            "http://{}/file",
+            listener.local_addr().expect("Test server has no address")
+        );
+
+        let server = thread::spawn(move || {
+            replies
+                .into_iter()
+                .map(|reply| {
+                    let (mut stream, _) = listener.accept().expect("Failed to accept request");
+
+                    let mut request = String::new();
+                    let mut reader = BufReader::new(&mut stream);
+                    while reader.read_line(&mut request).is_ok_and(|x| x > 2) {}
+
+                    // The client may already be gone if it gave up on the response
+                    let _ = stream.write_all(reply.head.as_bytes());
+                    let _ = stream.write_all(&reply.body);
+
+                    request
+                })
+                .collect()
+        });
+
+        (url, server)
+    }
+
+    fn body() -> Vec<u8> {
+        (0..20_000_u32)
+            .map(|x| u8::try_from(x % 251).unwrap_or_default())
+            .collect()
+    }
+
+    fn download_to_file(url: &str, path: &Path) -> Result<(), DownloaderError> {
+        let client = Client::streaming()?;
+        let mut res = client.get(url).send()?.error_for_status()?;
+        let total = res.content_length();
+        let head = read_head(&mut res);
+
+        stream_to_file(&client, url, std::convert::identity, head, res, total, path)
+    }
+
+    fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+        request.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    #[test]
+    fn resumes_interrupted_download_with_range_request() {
+        let body = body();
+        let cut = 12_000;
+        let (url, server) = serve(vec![
+            Reply {
+                head: format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n\
+                     ETag: \"v1\"\r\nConnection: close\r\n\r\n",
+                    body.len()
+                ),
+                body: body[..cut].to_vec(),
+            },
+            Reply {
+                head: format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\
+                     Content-Range: bytes {cut}-{}/{}\r\nConnection: close\r\n\r\n",
+                    body.len() - cut,
+                    body.len() - 1,
+                    body.len()
+                ),
+                body: body[cut..].to_vec(),
+            },
+        ]);
+        let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("file.part");
+
+        download_to_file(&url, &path).expect("Download failed");
+
+        let requests = server.join().expect("Test server panicked");
+        assert_eq!(header(&requests[0], "Range"), None);
+        assert_eq!(header(&requests[1], "Range"), Some("bytes=12000-"));
+        assert_eq!(header(&requests[1], "If-Range"), Some("\"v1\""));
+        assert_eq!(fs::read(&path).expect("Failed to read download"), body);
+    }
+
+    #[test]
+    fn starts_over_when_server_ignores_range() {
+        let body = body();
+        // Cut inside the part that is read to recognize the file type
+        let cut = 3_000;
+        let full_head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n\
+             Last-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        let (url, server) = serve(vec![
+            Reply {
+                head: full_head.clone(),
+                body: body[..cut].to_vec(),
+            },
+            Reply {
+                head: full_head,
+                body: body.clone(),
+            },
+        ]);
+        let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = dir.path().join("file.part");
+
+        download_to_file(&url, &path).expect("Download failed");
+
+        let requests = server.join().expect("Test server panicked");
+        assert_eq!(header(&requests[1], "Range"), Some("bytes=3000-"));
+        assert_eq!(
+            header(&requests[1], "If-Range"),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+        assert_eq!(fs::read(&path).expect("Failed to read download"), body);
+    }
+
+    #[test]
+    fn fails_without_range_support() {
+        let body = body();
+        let (url, server) = serve(vec![Reply {
+            head: format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            ),
+            body: body[..12_000].to_vec(),
+        }]);
+        let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+
+        let res = download_to_file(&url, &dir.path().join("file.part"));
+
+        assert!(res.is_err());
+        assert_eq!(server.join().expect("Test server panicked").len(), 1);
+    }
+
+    #[test]
+    fn takes_only_short_extensions_from_names() {
+        let extension = |name: &str| name_extension(Some(Path::new(name)));
+
+        assert_eq!(extension("/media/cat.webp"), Some("webp".to_string()));
+        assert_eq!(extension("/media/cat"), None);
+        assert_eq!(extension("/media/cat.j-p-g"), None);
+        assert_eq!(extension(&format!("/media/cat.{}", "a".repeat(11))), None);
+    }
+
+    #[test]
+    fn parses_content_range_start() {
+        assert_eq!(content_range_start("bytes 12000-19999/20000"), Some(12_000));
+        assert_eq!(content_range_start("bytes 0-0/*"), Some(0));
+        assert_eq!(content_range_start("bytes */20000"), None);
+        assert_eq!(content_range_start("items 1-2/3"), None);
+    }
 }
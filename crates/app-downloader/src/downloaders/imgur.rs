@@ -1,54 +1,302 @@
-use std::{path::PathBuf, string::ToString};
+use std::{io, path::Path, string::ToString};
 
+use app_config::CONFIG;
+use app_helpers::metadata::PostMetadata;
+use app_logger::{debug, trace};
+use once_cell::sync::Lazy;
 use rayon::prelude::*;
-use reqwest::blocking::Response;
+use regex::Regex;
+use reqwest::{blocking::Response, header};
 use serde::Deserialize;
 
-use super::DownloaderReturn;
-use crate::downloaders::{common::request::Client, generic};
+use super::{collect_downloads, Downloader, DownloaderReturn};
+use crate::{
+    downloaders::{
+        common::request::{Client, WithCookies},
+        generic,
+    },
+    DownloaderError,
+};
 
-pub fn is_imgur_direct_media_url(url: &str) -> bool {
-    url.starts_with("https://i.imgur.com/")
+/// Links to posts, albums and galleries.
+///
+/// Newer links have the title in front of the ID, eg. `/gallery/funny-cat-AbC12de`.
+pub static POST_URL_MATCH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^https?://(?:(?:www|m)\.)?imgur\.com/(?:(?P<kind>a|gallery|t/[^/]+)/)?(?:[^/?#]*-)?(?P<id>[a-zA-Z0-9]{5,})/?(?:[?#].*)?$",
+    )
+    .expect("Invalid regex")
+});
+
+pub static MEDIA_URL_MATCH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^https?://i\.imgur\.com/(?P<id>[a-zA-Z0-9]+)(?P<extension>\.[a-zA-Z0-9]+)?")
+        .expect("Invalid regex")
+});
+
+static API_BASE_URL: &str = "https://api.imgur.com/3";
+
+/// How many files of an album to download at once, so Imgur doesn't rate limit us.
+const MAX_PARALLEL_DOWNLOADS: usize = 4;
+
+pub struct ImgurMediaDownloader;
+
+impl Downloader for ImgurMediaDownloader {
+    fn name(&self) -> &'static str {
+        "imgur-media"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        is_imgur_direct_media_url(url)
+    }
+
+    fn priority(&self) -> i32 {
+        40
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        generic::download(download_dir, &media_url(url))
+    }
 }
 
-pub fn is_imgur_url(url: &str) -> bool {
-    url.starts_with("https://imgur.com/") || url.starts_with("http://imgur.com/")
+pub struct ImgurPostDownloader;
+
+impl Downloader for ImgurPostDownloader {
+    fn name(&self) -> &'static str {
+        "imgur"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        is_imgur_url(url)
+    }
+
+    fn priority(&self) -> i32 {
+        30
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download(download_dir, url)
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct ImgurPostData {
-    pub media: Vec<ImgurPostMedia>,
+pub fn is_imgur_direct_media_url(url: &str) -> bool {
+    MEDIA_URL_MATCH.is_match(url)
 }
 
-#[derive(Debug, Deserialize)]
-struct ImgurPostMedia {
-    url: String,
+pub fn is_imgur_url(url: &str) -> bool {
+    POST_URL_MATCH.is_match(url)
+}
+
+/// `.gifv` links are pages with a video player, the video itself is the `.mp4`.
+fn media_url(url: &str) -> String {
+    match MEDIA_URL_MATCH.captures(url) {
+        Some(captures) if captures.name("extension").map(|x| x.as_str()) == Some(".gifv") => {
+            format!("https://i.imgur.com/{}.mp4", &captures["id"])
+        }
+        _ => url.to_string(),
+    }
 }
 
-pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
     app_logger::info!(
         "Downloading imgur post {:?} media to {:?}",
         url,
         download_dir
     );
 
+    let post = fetch_post_from_api(url).or_else(|e| {
+        debug!("Failed to get imgur post from the API ({e}), scraping the page instead...");
+        scrape_post(url)
+    })?;
+    trace!("Imgur post: {post:?}");
+
+    if post.media.is_empty() {
+        return Err(DownloaderError::extractor("Imgur post has no media"));
+    }
+
+    let post_metadata = PostMetadata {
+        author: post.author.clone(),
+        title: post.title.clone().filter(|x| !x.is_empty()),
+        description: post.description.clone().filter(|x| !x.is_empty()),
+        post_id: post.id.clone(),
+        alt_text: None,
+    };
+
+    let thread_pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(rayon::current_num_threads().min(MAX_PARALLEL_DOWNLOADS))
+        .build()
+        .map_err(|e| DownloaderError::Io {
+            context: "Failed to create thread pool".to_string(),
+            source: io::Error::other(e),
+        })?;
+
+    let res = thread_pool.install(|| {
+        post.media
+            .par_iter()
+            .map(|media| {
+                generic::download(download_dir, &media.url).map(|files| {
+                    let media_metadata = PostMetadata {
+                        title: media.title.clone().filter(|x| !x.is_empty()),
+                        description: media.description.clone().filter(|x| !x.is_empty()),
+                        ..PostMetadata::default()
+                    };
+
+                    files
+                        .into_iter()
+                        .map(|mut file| {
+                            file.metadata = post_metadata
+                                .clone()
+                                .merge_missing(&media_metadata)
+                                .merge_missing(&file.metadata)
+                                .clone();
+                            file
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>()
+    });
+
+    collect_downloads(res)
+}
+
+/// A post, album or single image, with its media in the order it's shown.
+#[derive(Debug, Clone, Default)]
+struct ImgurPost {
+    id: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    author: Option<String>,
+    media: Vec<ImgurMedia>,
+}
+
+#[derive(Debug, Clone)]
+struct ImgurMedia {
+    url: String,
+    title: Option<String>,
+    description: Option<String>,
+}
+
+/// Look up a post through the Imgur API.
+///
+/// Which endpoint knows about an ID depends on how it was shared,
+/// so the ones that fit the link are tried in turn.
+fn fetch_post_from_api(url: &str) -> Result<ImgurPost, DownloaderError> {
+    let captures = POST_URL_MATCH
+        .captures(url)
+        .ok_or_else(|| DownloaderError::UnsupportedUrl(url.to_string()))?;
+    let id = &captures["id"];
+
+    let endpoints: &[&str] = match captures.name("kind").map(|x| x.as_str()) {
+        Some("a") => &["album"],
+        Some(_) => &["gallery", "album"],
+        None => &["image", "gallery", "album"],
+    };
+
+    let client = Client::default()?;
+    let mut last_error = None;
+
+    for endpoint in endpoints {
+        let api_url = format!("{API_BASE_URL}/{endpoint}/{id}");
+        debug!("Fetching imgur post from {api_url:?}");
+
+        let res = client
+            .get(&api_url)
+            .with_cookies()
+            .header(
+                header::AUTHORIZATION,
+                format!("Client-ID {}", CONFIG.endpoints.imgur_client_id()),
+            )
+            .send()
+            .and_then(Response::error_for_status)
+            .and_then(Response::json::<ApiResponse>);
+
+        match res {
+            Ok(res) => return Ok(res.data.into_post()),
+            Err(e) => {
+                trace!("Imgur {endpoint} endpoint failed: {e}");
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(last_error.map_or_else(
+        || DownloaderError::UnsupportedUrl(url.to_string()),
+        DownloaderError::from,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    data: ApiItem,
+}
+
+/// An image or an album. Albums have their images inline.
+#[derive(Debug, Deserialize)]
+struct ApiItem {
+    id: String,
+    title: Option<String>,
+    description: Option<String>,
+    account_url: Option<String>,
+    link: Option<String>,
+    /// Only set for animated images.
+    mp4: Option<String>,
+    #[serde(default)]
+    images: Vec<Self>,
+}
+
+impl ApiItem {
+    fn into_post(self) -> ImgurPost {
+        let media = if self.images.is_empty() {
+            self.media().into_iter().collect()
+        } else {
+            self.images.iter().filter_map(Self::media).collect()
+        };
+
+        ImgurPost {
+            id: Some(self.id),
+            title: self.title,
+            description: self.description,
+            author: self.account_url,
+            media,
+        }
+    }
+
+    fn media(&self) -> Option<ImgurMedia> {
+        let url = self
+            .mp4
+            .as_deref()
+            .filter(|x| !x.is_empty())
+            .or(self.link.as_deref())?;
+
+        Some(ImgurMedia {
+            url: media_url(url),
+            title: self.title.clone(),
+            description: self.description.clone(),
+        })
+    }
+}
+
+/// Get the post from the data the Imgur website starts with.
+fn scrape_post(url: &str) -> Result<ImgurPost, DownloaderError> {
     let resp = Client::default()?
         .get(url)
+        .with_cookies()
         .send()
-        .and_then(Response::text)
-        .map_err(|e| format!("Failed to send request to imgur: {:?}", e))?;
+        .and_then(Response::error_for_status)
+        .and_then(Response::text)?;
 
-    app_logger::trace!("Got response from imgur");
+    trace!("Got response from imgur");
 
-    let dom = tl::parse(&resp, tl::ParserOptions::default())
-        .map_err(|e| format!("Failed to parse html from imgur: {:?}", e))?;
+    let dom = tl::parse(&resp, tl::ParserOptions::default()).map_err(
+        DownloaderError::extractor_with("Failed to parse html from imgur"),
+    )?;
     let parser = dom.parser();
 
-    app_logger::trace!("Parsed html from imgur");
+    trace!("Parsed html from imgur");
 
     let script_data = dom
         .query_selector("script")
-        .expect("Failed parse query selector")
+        .ok_or_else(|| DownloaderError::extractor("Failed to parse query selector"))?
         .filter_map(|x| x.get(parser))
         .filter_map(|x| x.as_tag())
         .find_map(|x| {
@@ -58,46 +306,49 @@ pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
         })
         .and_then(|x| serde_json::from_str::<String>(&x).ok())
         .and_then(|x| serde_json::from_str::<ImgurPostData>(&x).ok())
-        .ok_or_else(|| "Failed to get script data".to_string())?;
-
-    app_logger::trace!("Got script data from imgur: {:?}", &script_data);
-
-    let (downloaded, failed) = {
-        let thread_pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(rayon::current_num_threads().min(4))
-            .build()
-            .map_err(|e| format!("Failed to create thread pool: {:?}", e))?;
-
-        let downloaded_items = thread_pool.install(|| {
-            script_data
-                .media
-                .par_iter()
-                .map(|x| (&x.url, generic::download(download_dir, &x.url)))
-                .collect::<Vec<_>>()
-        });
+        .ok_or_else(|| DownloaderError::extractor("Failed to get script data from imgur"))?;
 
-        app_logger::trace!("Downloaded items from imgur: {:?}", &downloaded_items);
+    trace!("Got script data from imgur: {:?}", &script_data);
 
-        let (success, failed): (Vec<_>, Vec<_>) =
-            downloaded_items.into_iter().partition(|x| x.1.is_ok());
-
-        let success = success
+    Ok(ImgurPost {
+        id: script_data.id,
+        title: script_data.title,
+        description: script_data.description,
+        author: script_data.account.and_then(|x| x.username),
+        media: script_data
+            .media
             .into_iter()
-            .filter_map(|x| x.1.ok())
-            .flatten()
-            .collect::<Vec<_>>();
+            .map(|x| ImgurMedia {
+                url: media_url(&x.url),
+                title: x.metadata.as_ref().and_then(|x| x.title.clone()),
+                description: x.metadata.and_then(|x| x.description),
+            })
+            .collect(),
+    })
+}
 
-        let failed = failed
-            .into_iter()
-            .filter_map(|x| x.1.err().map(|e| (x.0, e)))
-            .collect::<Vec<_>>();
+#[derive(Debug, Deserialize)]
+struct ImgurPostData {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub account: Option<ImgurAccount>,
+    pub media: Vec<ImgurPostMedia>,
+}
 
-        (success, failed)
-    };
+#[derive(Debug, Deserialize)]
+struct ImgurAccount {
+    username: Option<String>,
+}
 
-    if !failed.is_empty() {
-        app_logger::warn!("Failed to download: {:?}", failed);
-    }
+#[derive(Debug, Deserialize)]
+struct ImgurPostMedia {
+    url: String,
+    metadata: Option<ImgurPostMediaMetadata>,
+}
 
-    Ok(downloaded)
+#[derive(Debug, Deserialize)]
+struct ImgurPostMediaMetadata {
+    title: Option<String>,
+    description: Option<String>,
 }
@@ -1,126 +1,615 @@
-use std::{path::PathBuf, result::Result, string};
+use std::path::Path;
 
+use app_config::CONFIG;
+use app_helpers::metadata::PostMetadata;
 use app_logger::{debug, trace};
 use once_cell::sync::Lazy;
 use rayon::prelude::*;
 use regex::Regex;
+use reqwest::{
+    blocking::{Client as ReqwestClient, RequestBuilder, Response},
+    header,
+};
+use serde::Deserialize;
 
-use super::DownloaderReturn;
-use crate::downloaders::{common::request::Client, yt_dlp};
+use super::{collect_downloads, generic, yt_dlp, Downloader, DownloaderReturn};
+use crate::{
+    downloaders::common::request::{Client, WithCookies},
+    DownloaderError,
+};
 
+/// Posts, reels and IGTV videos, optionally with the author in front, eg. `/someone/reel/AbC12dE/`.
 pub static URL_MATCH: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^https?://(www\.)?instagram.com/p/(?P<post_id>[^/?]+)").expect("Invalid regex")
+    Regex::new(
+        r"^https?://(?:www\.|m\.)?instagram\.com/(?:[^/?#]+/)?(?P<kind>p|reels?|tv)/(?P<post_id>[a-zA-Z0-9_-]+)",
+    )
+    .expect("Invalid regex")
 });
 
-pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
-    let instagram_urls = fetch_instagram_urls(url)?;
-    debug!("Instagram URLs: {:?}", &instagram_urls);
+/// Stories don't have a shortcode that can be looked up, so only `yt-dlp` handles them.
+pub static STORIES_URL_MATCH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^https?://(?:www\.|m\.)?instagram\.com/stories/(?P<user>[^/?#]+)")
+        .expect("Invalid regex")
+});
 
-    let res: Vec<Result<Vec<PathBuf>, String>> = instagram_urls
-        .par_iter()
-        .map(|url| yt_dlp::download(download_dir, url))
-        .collect();
+static BASE_URL: &str = "https://www.instagram.com";
 
-    let (success, errs): (Vec<_>, Vec<_>) = res
-        .into_iter()
-        .partition(|x| x.as_ref().map_or(false, |x| !x.is_empty()));
+/// The app ID the Instagram website sends along with API requests.
+static WEB_APP_ID: &str = "936619743392459";
 
-    if !errs.is_empty() {
-        return Err(errs
-            .into_iter()
-            .filter_map(std::result::Result::err)
-            .collect::<Vec<_>>()
-            .join(", "));
-    }
-
-    Ok(success.into_iter().flatten().flatten().collect())
-}
-
-fn fetch_instagram_urls(url: &str) -> Result<Vec<String>, String> {
-    fn get_api_response(post_id: &str) -> Result<serde_json::Value, String> {
-        let query_hash = "2efa04f61586458cef44441f474eee7c";
-        let query_args = serde_json::json!({
-            "shortcode": post_id,
-            "child_comment_count": 0,
-            "fetch_comment_count": 0,
-            "parent_comment_count": 0,
-            "has_threaded_comments": true,
-        });
-        trace!("Query args: {:?}", &query_args);
-        let query_args =
-            serde_json::to_string(&query_args).map_err(|_e| "Failed to stringify json")?;
-
-        let api_url = format!(
-            "https://www.instagram.com/graphql/query/?query_hash={}&variables={}",
-            &query_hash, &query_args,
-        );
+pub struct InstagramDownloader;
 
-        debug!("Fetching from instagram API url: {:?}", &api_url);
-
-        Client::default()?
-            .get(&api_url)
-            .send()
-            .map_err(|e| format!("Failed to send request to instagram API: {e:?}"))?
-            .text()
-            .map_err(|e| format!("Failed to parse response from instagram API: {e:?}"))
-            .and_then(|res_text| {
-                serde_json::from_str::<serde_json::Value>(&res_text)
-                    .map_err(|e| format!("Failed to parse response from instagram API: {e:?}"))
-            })
+impl Downloader for InstagramDownloader {
+    fn name(&self) -> &'static str {
+        "instagram"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        URL_MATCH.is_match(url) || STORIES_URL_MATCH.is_match(url)
+    }
+
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download(download_dir, url)
+    }
+}
+
+/// Download the media of a post, trying the GraphQL API first,
+/// then the embed page and finally `yt-dlp`.
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
+    let Some(shortcode) = URL_MATCH.captures(url).map(|x| x["post_id"].to_string()) else {
+        debug!("Instagram URL has no post shortcode, downloading with yt-dlp...");
+        return yt_dlp::download(download_dir, url);
+    };
+    debug!("Instagram post shortcode: {shortcode:?}");
+
+    download_with_fallback(
+        || fetch_post(&shortcode),
+        |post| download_post(download_dir, post),
+        || yt_dlp::download(download_dir, url),
+    )
+}
+
+/// Download a post with `download_post` if `fetch_post` finds it, otherwise with `fallback`.
+///
+/// Also falls back if none of the media could be downloaded, since the CDN URLs expire
+/// and `yt-dlp` may still get them. If only some of the media failed,
+/// the rest is kept and the failures are reported in [`DownloaderError::Partial`].
+fn download_with_fallback<F, D, B>(fetch_post: F, download_post: D, fallback: B) -> DownloaderReturn
+where
+    F: FnOnce() -> Result<InstagramPost, DownloaderError>,
+    D: FnOnce(&InstagramPost) -> DownloaderReturn,
+    B: FnOnce() -> DownloaderReturn,
+{
+    let post = match fetch_post() {
+        Ok(post) => post,
+        Err(e) => {
+            debug!("Failed to look up instagram post ({e}), downloading with yt-dlp...");
+            return fallback();
+        }
+    };
+
+    match download_post(&post) {
+        Err(e @ DownloaderError::Partial { .. }) => Err(e),
+        Err(e) => {
+            debug!("Failed to download instagram post media ({e}), downloading with yt-dlp...");
+            fallback().map_err(|fallback_error| DownloaderError::Multiple(vec![e, fallback_error]))
+        }
+        Ok(files) => Ok(files),
     }
+}
+
+fn download_post(download_dir: &Path, post: &InstagramPost) -> DownloaderReturn {
+    trace!("Instagram post: {post:?}");
 
-    trace!("Fetching instagram media URLs for: {}", &url);
+    let post_metadata = PostMetadata {
+        author: post.author.clone(),
+        title: None,
+        description: post.caption.clone(),
+        post_id: Some(post.shortcode.clone()),
+        alt_text: None,
+    };
+
+    let res = post
+        .media
+        .par_iter()
+        .map(|media| {
+            let metadata = PostMetadata {
+                alt_text: media.alt_text.clone(),
+                ..post_metadata.clone()
+            };
 
-    let post_id = URL_MATCH
-        .captures(url)
-        .and_then(|x| x.name("post_id"))
-        .map(|x| x.as_str())
-        .ok_or_else(|| "URL is not a valid Instagram post".to_string())?;
-    debug!("Instagram post ID: {:?}", &post_id);
+            generic::download(download_dir, &media.url).map(|files| {
+                files
+                    .into_iter()
+                    .map(|mut file| {
+                        file.metadata = metadata.clone().merge_missing(&file.metadata).clone();
+                        file
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    collect_downloads(res)
+}
 
-    let json_response = get_api_response(post_id)?;
-    let edges = json_response
+fn fetch_post(shortcode: &str) -> Result<InstagramPost, DownloaderError> {
+    let client = Client::default()?;
+
+    look_up_post(
+        || fetch_post_from_graphql(&client, shortcode),
+        || fetch_post_from_embed(&client, shortcode),
+    )
+}
+
+fn look_up_post<G, E>(graphql: G, embed: E) -> Result<InstagramPost, DownloaderError>
+where
+    G: FnOnce() -> Result<InstagramPost, DownloaderError>,
+    E: FnOnce() -> Result<InstagramPost, DownloaderError>,
+{
+    graphql().or_else(|e| {
+        debug!("Failed to get instagram post from GraphQL ({e}), trying the embed page...");
+        embed()
+    })
+}
+
+fn fetch_post_from_graphql(
+    client: &ReqwestClient,
+    shortcode: &str,
+) -> Result<InstagramPost, DownloaderError> {
+    let api_url = format!("{BASE_URL}/graphql/query");
+    let variables = serde_json::json!({
+        "shortcode": shortcode,
+        "fetch_tagged_user_count": null,
+        "hoisted_comment_id": null,
+        "hoisted_reply_id": null,
+    })
+    .to_string();
+    debug!("Fetching instagram post {shortcode:?} from {api_url:?}");
+
+    let res = with_session(client.post(&api_url))
+        .header("X-IG-App-ID", WEB_APP_ID)
+        .header(header::REFERER, format!("{BASE_URL}/p/{shortcode}/"))
+        .form(&[
+            ("doc_id", CONFIG.instagram.graphql_doc_id()),
+            ("variables", variables),
+        ])
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::json::<serde_json::Value>)?;
+
+    parse_graphql_response(&res)
+}
+
+/// Get the post out of a GraphQL response, which has it under a different key
+/// depending on the query used.
+fn parse_graphql_response(res: &serde_json::Value) -> Result<InstagramPost, DownloaderError> {
+    let media = res
         .get("data")
+        .and_then(|x| {
+            x.get("xdt_shortcode_media")
+                .or_else(|| x.get("shortcode_media"))
+        })
+        .filter(|x| !x.is_null())
+        .ok_or_else(|| {
+            DownloaderError::extractor("Instagram did not return the post, it may need a login")
+        })?;
+
+    ShortcodeMedia::deserialize(media)
+        .map_err(DownloaderError::extractor_with(
+            "Failed to parse post from instagram GraphQL response",
+        ))?
+        .into_post()
+}
+
+fn fetch_post_from_embed(
+    client: &ReqwestClient,
+    shortcode: &str,
+) -> Result<InstagramPost, DownloaderError> {
+    let embed_url = format!("{BASE_URL}/p/{shortcode}/embed/captioned/");
+    debug!("Fetching instagram embed page {embed_url:?}");
+
+    let html = with_session(client.get(&embed_url))
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::text)?;
+
+    parse_embed_page(shortcode, &html)
+}
+
+/// Get the post out of the embed page.
+///
+/// The page usually has the same data as the GraphQL API in a script,
+/// otherwise only the first image and the author are in the markup.
+fn parse_embed_page(shortcode: &str, html: &str) -> Result<InstagramPost, DownloaderError> {
+    if let Some(media) = embedded_shortcode_media(html) {
+        trace!("Found shortcode media in instagram embed page");
+
+        match ShortcodeMedia::deserialize(&media) {
+            Ok(media) => return media.into_post(),
+            Err(e) => debug!("Failed to parse shortcode media from instagram embed page: {e}"),
+        }
+    }
+
+    let dom = tl::parse(html, tl::ParserOptions::default()).map_err(
+        DownloaderError::extractor_with("Failed to parse html from instagram"),
+    )?;
+    let parser = dom.parser();
+
+    let find_tag = |class: &str| {
+        dom.get_elements_by_class_name(class)
+            .filter_map(|x| x.get(parser))
+            .find_map(|x| x.as_tag())
+    };
+
+    let image = find_tag("EmbeddedMediaImage");
+    let attribute = |name: &str| {
+        image
+            .and_then(|x| x.attributes().get(name).flatten())
+            .map(|x| decode_html_entities(&x.as_utf8_str()))
+    };
+
+    let image_url = attribute("src").ok_or_else(|| {
+        DownloaderError::extractor("Failed to find media in instagram embed page")
+    })?;
+    let alt_text = attribute("alt").filter(|x| !x.is_empty());
+    let author = find_tag("UsernameText")
+        .map(|x| x.inner_text(parser).trim().to_string())
+        .filter(|x| !x.is_empty());
+
+    Ok(InstagramPost {
+        shortcode: shortcode.to_string(),
+        author,
+        caption: None,
+        media: vec![InstagramMedia {
+            url: image_url,
+            alt_text,
+        }],
+    })
+}
+
+/// The `shortcode_media` object the embed page keeps in a JSON string in a script.
+fn embedded_shortcode_media(html: &str) -> Option<serde_json::Value> {
+    let start = html.find(r#""contextJSON":"#)? + r#""contextJSON":"#.len();
+
+    let context = serde_json::Deserializer::from_str(&html[start..])
+        .into_iter::<String>()
+        .next()?
+        .ok()?;
+    let context = serde_json::from_str::<serde_json::Value>(&context).ok()?;
+
+    context
+        .get("gql_data")
         .and_then(|x| x.get("shortcode_media"))
-        .and_then(serde_json::Value::as_object)
-        .ok_or("Failed to get edges from response")?;
+        .filter(|x| !x.is_null())
+        .cloned()
+}
 
-    if !edges.contains_key("edge_sidecar_to_children") {
-        let url = edges
-            .get("video_url")
-            .or_else(|| edges.get("display_url"))
-            .and_then(serde_json::Value::as_str)
-            .map(string::ToString::to_string)
-            .ok_or("Failed to get `display_url' on edges")?;
+fn decode_html_entities(text: &str) -> String {
+    // `&amp;` goes last, so escaped entities like `&amp;lt;` aren't decoded twice
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
 
-        debug!("Fetched Instagram media and got single image");
+/// Send the configured Instagram session along with a request.
+///
+/// Configured cookies take precedence over the session ID.
+fn with_session(request: RequestBuilder) -> RequestBuilder {
+    let session_id = CONFIG
+        .instagram
+        .session_id
+        .as_ref()
+        .filter(|_| CONFIG.cookies.for_host("www.instagram.com").is_none());
 
-        return Ok(vec![url]);
+    match session_id {
+        Some(session_id) => request.header(header::COOKIE, format!("sessionid={session_id}")),
+        None => request.with_cookies(),
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InstagramPost {
+    shortcode: String,
+    author: Option<String>,
+    caption: Option<String>,
+    media: Vec<InstagramMedia>,
+}
 
-    debug!("Fetched Instagram media and got multiple images");
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InstagramMedia {
+    url: String,
+    alt_text: Option<String>,
+}
 
-    let edges = &edges["edge_sidecar_to_children"]["edges"]
-        .as_array()
-        .expect("Failed to get edges on response")[..]
-        .to_vec();
+/// A post as the GraphQL API and the embed page describe it.
+/// Carousels have their items as children in the same shape.
+#[derive(Debug, Clone, Deserialize)]
+struct ShortcodeMedia {
+    shortcode: Option<String>,
+    owner: Option<Owner>,
+    edge_media_to_caption: Option<Edges<Caption>>,
+    display_url: Option<String>,
+    video_url: Option<String>,
+    accessibility_caption: Option<String>,
+    edge_sidecar_to_children: Option<Edges<Self>>,
+}
 
-    let urls = edges
-        .iter()
-        .filter_map(|entry| {
-            let node = entry.get("node").and_then(serde_json::Value::as_object)?;
+impl ShortcodeMedia {
+    fn into_post(self) -> Result<InstagramPost, DownloaderError> {
+        let media = self.edge_sidecar_to_children.as_ref().map_or_else(
+            || self.media().into_iter().collect::<Vec<_>>(),
+            |children| {
+                children
+                    .edges
+                    .iter()
+                    .filter_map(|x| x.node.media())
+                    .collect()
+            },
+        );
 
-            if node.contains_key("video_url") {
-                debug!("Found video in post: {}", node["id"]);
-                return node.get("video_url").and_then(serde_json::Value::as_str);
-            }
+        if media.is_empty() {
+            return Err(DownloaderError::extractor("Instagram post has no media"));
+        }
 
-            debug!("Found image in post: {}", node["id"]);
-            return node.get("display_url").and_then(serde_json::Value::as_str);
+        Ok(InstagramPost {
+            shortcode: self.shortcode.unwrap_or_default(),
+            author: self.owner.and_then(|x| x.username),
+            caption: self
+                .edge_media_to_caption
+                .and_then(|x| x.edges.into_iter().next())
+                .map(|x| x.node.text)
+                .filter(|x| !x.is_empty()),
+            media,
         })
-        .map(string::ToString::to_string)
-        .collect::<Vec<String>>();
+    }
+
+    fn media(&self) -> Option<InstagramMedia> {
+        Some(InstagramMedia {
+            url: self
+                .video_url
+                .clone()
+                .or_else(|| self.display_url.clone())?,
+            alt_text: self.accessibility_caption.clone().filter(|x| !x.is_empty()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Owner {
+    username: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Edges<T> {
+    #[serde(default = "Vec::new")]
+    edges: Vec<Edge<T>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Edge<T> {
+    node: T,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Caption {
+    text: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, path::PathBuf};
+
+    use super::*;
+    use crate::DownloadedFile;
+
+    fn media(url: &str, alt_text: Option<&str>) -> InstagramMedia {
+        InstagramMedia {
+            url: url.to_string(),
+            alt_text: alt_text.map(ToString::to_string),
+        }
+    }
+
+    fn post() -> InstagramPost {
+        InstagramPost {
+            shortcode: "C3xAmPlE1zQ".to_string(),
+            author: None,
+            caption: None,
+            media: vec![media("https://cdn.example.com/1.jpg", None)],
+        }
+    }
+
+    fn files(names: &[&str]) -> Vec<DownloadedFile> {
+        names
+            .iter()
+            .map(|x| DownloadedFile::new(PathBuf::from(x)))
+            .collect()
+    }
 
-    debug!("Found multiple Instagram media");
-    Ok(urls)
+    fn paths(res: DownloaderReturn) -> Vec<PathBuf> {
+        res.expect("Download failed")
+            .into_iter()
+            .map(|x| x.path)
+            .collect()
+    }
+
+    #[test]
+    fn graphql_carousel() {
+        let post = parse_graphql_response(&fixture!(json "instagram/graphql_carousel.json"))
+            .expect("Failed to parse carousel");
+
+        assert_eq!(
+            post,
+            InstagramPost {
+                shortcode: "C3xAmPlE1zQ".to_string(),
+                author: Some("daily.memes".to_string()),
+                caption: Some("Swipe for the plot twist 👀 #memes".to_string()),
+                media: vec![
+                    media(
+                        "https://scontent-fra3-1.cdninstagram.com/v/t51.29350-15/431111111_n.jpg?stp=dst-jpg_e35&_nc_ht=scontent-fra3-1.cdninstagram.com&oh=00_AfB1&oe=65F0A1B2",
+                        Some("May be an image of text that says 'me at 9am'."),
+                    ),
+                    media(
+                        "https://scontent-fra3-1.cdninstagram.com/v/t51.29350-15/432222222_n.jpg?stp=dst-jpg_e35&_nc_ht=scontent-fra3-1.cdninstagram.com&oh=00_AfB2&oe=65F0A1B2",
+                        None,
+                    ),
+                    media(
+                        "https://scontent-fra3-1.cdninstagram.com/o1/v/t16/f1/m82/433333333_video.mp4?efg=eyJ2ZW5jb2RlX3RhZyI6In0&_nc_ht=scontent-fra3-1.cdninstagram.com&oh=00_AfB4&oe=65F0A1B2",
+                        None,
+                    ),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn graphql_reel() {
+        let post = parse_graphql_response(&fixture!(json "instagram/graphql_reel.json"))
+            .expect("Failed to parse reel");
+
+        assert_eq!(post.author.as_deref(), Some("cat.reels"));
+        assert_eq!(post.caption, None);
+        assert_eq!(
+            post.media,
+            [media(
+                "https://scontent-fra5-2.cdninstagram.com/o1/v/t16/f1/m86/435555555_video.mp4?efg=eyJ2ZW5jb2RlX3RhZyI6In0&_nc_ht=scontent-fra5-2.cdninstagram.com&oh=00_AfC2&oe=65F2B3C4",
+                None,
+            )]
+        );
+    }
+
+    #[test]
+    fn graphql_login_wall() {
+        let res = parse_graphql_response(&fixture!(json "instagram/graphql_login_wall.json"));
+
+        assert!(matches!(res, Err(DownloaderError::Extractor { .. })));
+    }
+
+    #[test]
+    fn embed_page_with_context() {
+        let post = parse_embed_page("C3xAmPlE1zQ", fixture!("instagram/embed_with_context.html"))
+            .expect("Failed to parse embed page");
+
+        assert_eq!(post.author.as_deref(), Some("daily.memes"));
+        assert_eq!(
+            post.caption.as_deref(),
+            Some("Swipe for the plot twist 👀 #memes")
+        );
+        assert_eq!(
+            post.media
+                .iter()
+                .map(|x| x.url.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "https://scontent-fra3-1.cdninstagram.com/v/t51.29350-15/431111111_n.jpg?stp=dst-jpg_e35&_nc_ht=scontent-fra3-1.cdninstagram.com&oh=00_AfB1&oe=65F0A1B2",
+                "https://scontent-fra3-1.cdninstagram.com/o1/v/t16/f1/m82/433333333_video.mp4?_nc_ht=scontent-fra3-1.cdninstagram.com&oh=00_AfB4&oe=65F0A1B2",
+            ]
+        );
+    }
+
+    #[test]
+    fn embed_page_without_context() {
+        let post = parse_embed_page(
+            "C4rEeLxYz9A",
+            fixture!("instagram/embed_without_context.html"),
+        )
+        .expect("Failed to parse embed page");
+
+        assert_eq!(
+            post,
+            InstagramPost {
+                shortcode: "C4rEeLxYz9A".to_string(),
+                author: Some("cat.reels".to_string()),
+                caption: None,
+                media: vec![media(
+                    "https://scontent-fra5-2.cdninstagram.com/v/t51.29350-15/435555555_n.jpg?stp=dst-jpg_e15_s640x640&_nc_ht=scontent-fra5-2.cdninstagram.com&oh=00_AfC1&oe=65F2B3C4",
+                    Some("Cat & printer, \"round 2\" &lt;3"),
+                )],
+            }
+        );
+    }
+
+    #[test]
+    fn embed_page_without_media() {
+        let res = parse_embed_page("C4rEeLxYz9A", "<html><body>Not found</body></html>");
+
+        assert!(matches!(res, Err(DownloaderError::Extractor { .. })));
+    }
+
+    #[test]
+    fn look_up_falls_through_to_embed_page() {
+        let post = look_up_post(
+            || Err(DownloaderError::extractor("login wall")),
+            || Ok(post()),
+        );
+
+        assert_eq!(post.expect("Lookup failed"), self::post());
+    }
+
+    #[test]
+    fn look_up_stops_at_graphql() {
+        let post = look_up_post(|| Ok(post()), || panic!("Embed page should not be fetched"));
+
+        assert_eq!(post.expect("Lookup failed"), self::post());
+    }
+
+    #[test]
+    fn falls_back_to_yt_dlp_when_lookup_fails() {
+        let res = download_with_fallback(
+            || Err(DownloaderError::extractor("login wall")),
+            |_| panic!("Nothing should be downloaded from the CDN"),
+            || Ok(files(&["yt-dlp.mp4"])),
+        );
+
+        assert_eq!(paths(res), [PathBuf::from("yt-dlp.mp4")]);
+    }
+
+    #[test]
+    fn falls_back_to_yt_dlp_when_cdn_fails() {
+        let res = download_with_fallback(
+            || Ok(post()),
+            |_| Err(DownloaderError::extractor("URL signature expired")),
+            || Ok(files(&["yt-dlp.mp4"])),
+        );
+
+        assert_eq!(paths(res), [PathBuf::from("yt-dlp.mp4")]);
+    }
+
+    #[test]
+    fn reports_both_errors_when_fallback_fails() {
+        let res = download_with_fallback(
+            || Ok(post()),
+            |_| Err(DownloaderError::extractor("URL signature expired")),
+            || Err(DownloaderError::extractor("yt-dlp failed")),
+        );
+
+        assert!(matches!(res, Err(DownloaderError::Multiple(errors)) if errors.len() == 2));
+    }
+
+    #[test]
+    fn keeps_partial_cdn_downloads() {
+        let fell_back = Cell::new(false);
+        let res = download_with_fallback(
+            || Ok(post()),
+            |_| {
+                Err(DownloaderError::Partial {
+                    files: vec![DownloadedFile::new(PathBuf::from("1.jpg"))],
+                    errors: vec![DownloaderError::extractor("URL signature expired")],
+                })
+            },
+            || {
+                fell_back.set(true);
+                Ok(files(&["yt-dlp.mp4"]))
+            },
+        );
+
+        assert!(matches!(res, Err(DownloaderError::Partial { files, .. }) if files.len() == 1));
+        assert!(!fell_back.get());
+    }
 }
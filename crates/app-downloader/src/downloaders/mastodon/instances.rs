@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use app_config::CONFIG;
+use app_logger::{debug, trace, warn};
+use once_cell::sync::Lazy;
+use reqwest::blocking::Response;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    downloaders::common::request::{Client, WithCookies},
+    DownloaderError,
+};
+
+pub static INSTANCES_FILE_NAME: &str = "fediverse-instances.json";
+
+/// Prefix of the `rel` of links to `NodeInfo` documents, followed by the schema version.
+static NODEINFO_SCHEMA: &str = "http://nodeinfo.diaspora.software/ns/schema/";
+
+/// How long to remember that a host is an instance.
+const INSTANCE_TTL: Duration = Duration::from_hours(30 * 24);
+
+/// How long to remember that a host isn't an instance.
+const NOT_INSTANCE_TTL: Duration = Duration::from_hours(7 * 24);
+
+/// How long to remember that a host couldn't be reached or had a server error.
+const UNREACHABLE_TTL: Duration = Duration::from_hours(1);
+
+static CACHE: Lazy<Mutex<InstanceCache>> = Lazy::new(|| {
+    Mutex::new(InstanceCache::load(
+        CONFIG.cache_dir().join(INSTANCES_FILE_NAME),
+    ))
+});
+
+/// What is known about a host.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub is_instance: bool,
+    /// Name of the server software, eg. `mastodon` or `misskey`, if the host said.
+    pub software: Option<String>,
+    /// Seconds since the Unix epoch after which the host should be checked again.
+    expires_at: u64,
+}
+
+/// Whether `host` is a Mastodon or other fediverse instance.
+///
+/// The configured lists of instances are checked first, then hosts that
+/// were checked before. Other hosts are asked for their `NodeInfo` once
+/// and the answer is remembered across runs.
+pub fn is_instance(host: &str) -> bool {
+    lookup(host).is_some_and(|x| x.is_instance)
+}
+
+/// What is known about `host`, checking it if it hasn't been yet.
+///
+/// Returns `None` for hosts on the list of hosts that aren't instances.
+pub fn lookup(host: &str) -> Option<HostInfo> {
+    let host = host.to_lowercase();
+
+    let is_listed = |list: &[String]| list.iter().any(|x| x.eq_ignore_ascii_case(&host));
+    if is_listed(&CONFIG.app.not_fediverse_instances) {
+        trace!("{host:?} is configured to not be a fediverse instance");
+        return None;
+    }
+
+    let cached = CACHE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&host);
+
+    if is_listed(&CONFIG.app.fediverse_instances) {
+        trace!("{host:?} is configured to be a fediverse instance");
+        return Some(HostInfo {
+            is_instance: true,
+            software: cached.and_then(|x| x.software),
+            expires_at: u64::MAX,
+        });
+    }
+
+    if let Some(cached) = cached {
+        trace!("Found {host:?} in the instance cache: {cached:?}");
+        return Some(cached);
+    }
+
+    let info = check_host(&host);
+    debug!("Checked whether {host:?} is a fediverse instance: {info:?}");
+
+    CACHE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(host, info.clone());
+
+    Some(info)
+}
+
+fn check_host(host: &str) -> HostInfo {
+    match fetch_node_info(host) {
+        Ok(node_info) => {
+            let is_instance = node_info
+                .protocols
+                .into_vec()
+                .iter()
+                .any(|x| x.eq_ignore_ascii_case("activitypub"));
+
+            HostInfo {
+                is_instance,
+                software: node_info.software.map(|x| x.name.to_lowercase()),
+                expires_at: expires_after(if is_instance {
+                    INSTANCE_TTL
+                } else {
+                    NOT_INSTANCE_TTL
+                }),
+            }
+        }
+        Err(e) => {
+            debug!("Failed to get NodeInfo of {host:?}: {e:?}");
+
+            // Only servers that answered properly are known not to be instances
+            let ttl = if e.is_transient() {
+                UNREACHABLE_TTL
+            } else {
+                NOT_INSTANCE_TTL
+            };
+
+            HostInfo {
+                is_instance: false,
+                software: None,
+                expires_at: expires_after(ttl),
+            }
+        }
+    }
+}
+
+/// Get the newest version of the `NodeInfo` document the host links to.
+fn fetch_node_info(host: &str) -> Result<NodeInfo, DownloaderError> {
+    let client = Client::default()?;
+
+    let index = client
+        .get(format!("https://{host}/.well-known/nodeinfo"))
+        .with_cookies()
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::json::<NodeInfoIndex>)?;
+    trace!("NodeInfo links of {host:?}: {index:?}");
+
+    let link = index
+        .links
+        .into_iter()
+        .filter(|x| x.rel.starts_with(NODEINFO_SCHEMA))
+        .max_by(|a, b| a.rel.cmp(&b.rel))
+        .ok_or_else(|| DownloaderError::extractor("Host has no NodeInfo document"))?;
+
+    Ok(client
+        .get(&link.href)
+        .with_cookies()
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::json::<NodeInfo>)?)
+}
+
+fn expires_after(ttl: Duration) -> u64 {
+    now() + ttl.as_secs()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Hosts that were checked before, saved in the cache directory.
+#[derive(Debug)]
+struct InstanceCache {
+    path: PathBuf,
+    hosts: HashMap<String, HostInfo>,
+}
+
+impl InstanceCache {
+    fn load(path: PathBuf) -> Self {
+        trace!("Loading fediverse instance cache from {path:?}");
+
+        let hosts = read_hosts(&path).unwrap_or_else(|e| {
+            debug!("Failed to load fediverse instance cache {path:?}: {e:?}");
+            HashMap::new()
+        });
+
+        Self { path, hosts }
+    }
+
+    fn get(&self, host: &str) -> Option<HostInfo> {
+        self.hosts
+            .get(host)
+            .filter(|x| x.expires_at > now())
+            .cloned()
+    }
+
+    fn insert(&mut self, host: String, info: HostInfo) {
+        self.hosts.insert(host, info);
+
+        if let Err(e) = self.save() {
+            warn!(
+                "Failed to save fediverse instance cache {path:?}: {e:?}",
+                path = self.path
+            );
+        }
+    }
+
+    fn save(&mut self) -> io::Result<()> {
+        // Keep what other runs found out in the meantime
+        if let Ok(saved) = read_hosts(&self.path) {
+            for (host, info) in saved {
+                self.hosts.entry(host).or_insert(info);
+            }
+        }
+
+        let now = now();
+        self.hosts.retain(|_, x| x.expires_at > now);
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write next to the cache first so other runs never read half of it
+        let tmp_path = tmp_path_for(&self.path);
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&self.hosts)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+fn read_hosts(path: &Path) -> io::Result<HashMap<String, HostInfo>> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+fn tmp_path_for(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    path.with_file_name(format!(".{file_name}.{pid}", pid = std::process::id()))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NodeInfoIndex {
+    #[serde(default)]
+    links: Vec<NodeInfoLink>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NodeInfoLink {
+    rel: String,
+    href: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NodeInfo {
+    software: Option<NodeInfoSoftware>,
+    #[serde(default)]
+    protocols: Protocols,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NodeInfoSoftware {
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Protocols {
+    /// `NodeInfo` 2.0 and later.
+    List(Vec<String>),
+    /// `NodeInfo` 1.x lists protocols by direction.
+    Directions {
+        #[serde(default)]
+        inbound: Vec<String>,
+        #[serde(default)]
+        outbound: Vec<String>,
+    },
+}
+
+impl Default for Protocols {
+    fn default() -> Self {
+        Self::List(vec![])
+    }
+}
+
+impl Protocols {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::List(x) => x,
+            Self::Directions { inbound, outbound } => inbound.into_iter().chain(outbound).collect(),
+        }
+    }
+}
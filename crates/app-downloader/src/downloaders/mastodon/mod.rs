@@ -0,0 +1,429 @@
+use std::{path::Path, time::Duration};
+
+use app_helpers::metadata::PostMetadata;
+use app_logger::{debug, trace, warn};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use regex::Regex;
+use reqwest::{blocking::Response, header};
+use serde::Deserialize;
+
+use super::{collect_downloads, generic, twitter, Downloader, DownloaderReturn};
+use crate::{
+    downloaders::common::request::{Client, WithCookies},
+    DownloaderError,
+};
+
+mod instances;
+
+/// Paths of posts on Mastodon, Pleroma, Akkoma and Misskey.
+///
+/// - Mastodon: `/@user/123`, `/@user@other.instance/123`, `/users/user/statuses/123`,
+///   `/web/@user/123` and `/web/statuses/123`
+/// - Pleroma and Akkoma: `/notice/AbC123` and `/objects/<uuid>`
+/// - Misskey: `/notes/9abc123`
+pub static POST_URL_MATCH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^https?://(?P<host>[^/]+)/(?:(?:(?:web/)?@[^/]+|users/[^/]+/statuses|web/statuses)/(?P<status_id>[0-9]+)|(?P<object>notice|notes|objects)/(?P<object_id>[a-zA-Z0-9_-]+))/?(?:[?#].*)?$",
+    )
+    .expect("Invalid regex")
+});
+
+/// Server software that doesn't implement the Mastodon API.
+static NO_MASTODON_API: &[&str] = &[
+    "misskey",
+    "sharkey",
+    "firefish",
+    "calckey",
+    "iceshrimp",
+    "foundkey",
+    "cherrypick",
+];
+
+/// Asks servers for the `ActivityStreams` representation of a page.
+static ACTIVITY_JSON: &str = r#"application/activity+json, application/ld+json; profile="https://www.w3.org/ns/activitystreams""#;
+
+static HTML_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").expect("Invalid regex"));
+
+static HTML_LINE_BREAK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)<br\s*/?>|</p>\s*<p[^>]*>").expect("Invalid regex"));
+
+pub struct MastodonDownloader;
+
+impl Downloader for MastodonDownloader {
+    fn name(&self) -> &'static str {
+        "mastodon"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        is_mastodon_toot(url)
+    }
+
+    fn priority(&self) -> i32 {
+        70
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download(download_dir, url)
+    }
+}
+
+/// Whether the URL is of a post on a Mastodon or other `ActivityPub` server.
+///
+/// Only URLs shaped like posts are checked, and whether their host is an
+/// instance is remembered, so this rarely has to make a request.
+pub fn is_mastodon_toot(toot_url: &str) -> bool {
+    trace!("Checking whether {toot_url:?} is a Mastodon toot");
+
+    POST_URL_MATCH
+        .captures(toot_url)
+        .is_some_and(|x| instances::is_instance(&x["host"]))
+}
+
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
+    let post = fetch_post(url)?;
+    debug!("Fediverse post: {post:?}");
+
+    if post.attachments.is_empty() {
+        debug!("Post has no media, taking a screenshot...");
+        return screenshot_toot(download_dir, url);
+    }
+
+    let post_metadata = PostMetadata {
+        author: post.author.clone(),
+        title: post.summary.clone(),
+        description: post.text.clone(),
+        post_id: Some(post.id.clone()),
+        alt_text: None,
+    };
+
+    let res = post
+        .attachments
+        .par_iter()
+        .map(|attachment| {
+            generic::download(download_dir, &attachment.url).map(|files| {
+                files
+                    .into_iter()
+                    .map(|mut file| {
+                        file.metadata = PostMetadata {
+                            alt_text: attachment.alt_text.clone(),
+                            ..post_metadata.clone()
+                        };
+                        file
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    collect_downloads(res)
+}
+
+/// Presumes that the URL is of a Mastodon toot
+pub fn screenshot_toot(download_dir: &Path, url: &str) -> DownloaderReturn {
+    twitter::download(download_dir, url)
+}
+
+/// A post on any fediverse server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FediversePost {
+    id: String,
+    author: Option<String>,
+    /// The content warning, if there is one.
+    summary: Option<String>,
+    text: Option<String>,
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Attachment {
+    url: String,
+    alt_text: Option<String>,
+}
+
+/// Look up a post through the Mastodon API, which Pleroma and Akkoma implement too,
+/// and fall back to asking for its `ActivityStreams` object, which every server has.
+fn fetch_post(url: &str) -> Result<FediversePost, DownloaderError> {
+    let captures = POST_URL_MATCH
+        .captures(url)
+        .ok_or_else(|| DownloaderError::UnsupportedUrl(url.to_string()))?;
+    let host = &captures["host"];
+    let post_id = captures
+        .name("status_id")
+        .or_else(|| captures.name("object_id"))
+        .map(|x| x.as_str())
+        .ok_or_else(|| DownloaderError::UnsupportedUrl(url.to_string()))?;
+    let client = Client::builder().timeout(Duration::from_secs(5)).build()?;
+
+    // Misskey has no Mastodon API and ActivityPub objects aren't statuses
+    let is_status = captures
+        .name("object")
+        .is_none_or(|x| x.as_str() == "notice");
+    let has_api = instances::lookup(host)
+        .and_then(|x| x.software)
+        .is_none_or(|x| !NO_MASTODON_API.contains(&x.as_str()));
+
+    if is_status && has_api {
+        let api_url = format!("https://{host}/api/v1/statuses/{post_id}");
+        trace!("Making request to instance {host:?} for status info for {post_id:?}");
+
+        let status = client
+            .get(&api_url)
+            .with_cookies()
+            .send()
+            .and_then(Response::error_for_status)
+            .and_then(Response::json::<MastodonStatus>);
+
+        match status {
+            Ok(status) => return Ok(status.into_post()),
+            Err(e) => debug!("Failed to get status from Mastodon API: {e:?}"),
+        }
+    }
+
+    trace!("Asking {host:?} for the ActivityStreams object of {url:?}");
+    let object = client
+        .get(url)
+        .with_cookies()
+        .header(header::ACCEPT, ACTIVITY_JSON)
+        .send()?
+        .error_for_status()?
+        .json::<ActivityObject>()
+        .map_err(DownloaderError::extractor_with(
+            "Failed to parse ActivityStreams object",
+        ))?;
+
+    if !matches!(
+        object.kind.as_str(),
+        "Note" | "Article" | "Page" | "Question"
+    ) {
+        return Err(DownloaderError::extractor(format!(
+            "ActivityStreams object is a {:?}, not a post",
+            object.kind
+        )));
+    }
+
+    let author = object.attributed_to.as_ref().and_then(|x| match x {
+        AttributedTo::Actor(actor) => actor.handle(),
+        AttributedTo::Link(url) => fetch_actor(&client, url),
+    });
+
+    Ok(object.into_post(author))
+}
+
+/// Get the `user@instance` handle of the actor at `url`.
+fn fetch_actor(client: &reqwest::blocking::Client, url: &str) -> Option<String> {
+    client
+        .get(url)
+        .with_cookies()
+        .header(header::ACCEPT, ACTIVITY_JSON)
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::json::<Actor>)
+        .map_err(|e| debug!("Failed to get actor {url:?}: {e:?}"))
+        .ok()?
+        .handle()
+}
+
+/// Make plain text out of the HTML of a post.
+fn html_to_text(html: &str) -> Option<String> {
+    let text = HTML_LINE_BREAK.replace_all(html, "\n");
+    let text = HTML_TAG.replace_all(&text, "");
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    let text = text.trim();
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// A status from the Mastodon API.
+#[derive(Debug, Clone, Deserialize)]
+struct MastodonStatus {
+    id: String,
+    account: Option<MastodonAccount>,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    spoiler_text: String,
+    #[serde(default)]
+    media_attachments: Vec<MastodonAttachment>,
+    /// The boosted status, if this is a boost.
+    reblog: Option<Box<Self>>,
+}
+
+impl MastodonStatus {
+    fn into_post(self) -> FediversePost {
+        if let Some(reblog) = self.reblog {
+            return reblog.into_post();
+        }
+
+        FediversePost {
+            id: self.id,
+            author: self.account.map(|x| x.acct),
+            summary: Some(self.spoiler_text).filter(|x| !x.is_empty()),
+            text: html_to_text(&self.content),
+            attachments: self
+                .media_attachments
+                .into_iter()
+                .filter_map(|x| {
+                    // Media the instance didn't cache is only on the origin server
+                    let url = if x.kind == "unknown" {
+                        x.remote_url.or(x.url)
+                    } else {
+                        x.url.or(x.remote_url)
+                    };
+
+                    if url.is_none() {
+                        warn!("Skipping attachment {id:?} without a URL", id = x.id);
+                    }
+
+                    Some(Attachment {
+                        url: url?,
+                        alt_text: x.description.filter(|x| !x.is_empty()),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MastodonAccount {
+    /// `user` for local and `user@instance` for remote accounts.
+    acct: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MastodonAttachment {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    url: Option<String>,
+    remote_url: Option<String>,
+    description: Option<String>,
+}
+
+/// A post as an `ActivityStreams` object.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActivityObject {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    attributed_to: Option<AttributedTo>,
+    content: Option<String>,
+    summary: Option<String>,
+    #[serde(default)]
+    attachment: OneOrMany<ActivityAttachment>,
+}
+
+impl ActivityObject {
+    fn into_post(self, author: Option<String>) -> FediversePost {
+        FediversePost {
+            // The ID of an object is its URL, the post ID is the last part of it
+            id: self
+                .id
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or(&self.id)
+                .to_string(),
+            author,
+            summary: self.summary.filter(|x| !x.is_empty()),
+            text: self.content.as_deref().and_then(html_to_text),
+            attachments: self
+                .attachment
+                .into_vec()
+                .into_iter()
+                .filter_map(|x| {
+                    Some(Attachment {
+                        url: x.url.into_vec().into_iter().find_map(ActivityLink::href)?,
+                        alt_text: x.name.filter(|x| !x.is_empty()),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum AttributedTo {
+    Link(String),
+    Actor(Actor),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Actor {
+    id: Option<String>,
+    preferred_username: Option<String>,
+}
+
+impl Actor {
+    fn handle(&self) -> Option<String> {
+        let username = self.preferred_username.as_deref()?;
+        let host = self
+            .id
+            .as_deref()
+            .and_then(|x| url::Url::parse(x).ok())
+            .and_then(|x| x.host_str().map(ToString::to_string));
+
+        Some(host.map_or_else(|| username.to_string(), |host| format!("{username}@{host}")))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ActivityAttachment {
+    #[serde(default)]
+    url: OneOrMany<ActivityLink>,
+    /// The alt text.
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum ActivityLink {
+    Url(String),
+    Link { href: String },
+}
+
+impl ActivityLink {
+    fn href(self) -> Option<String> {
+        match self {
+            Self::Url(x) | Self::Link { href: x } => Some(x).filter(|x| !x.is_empty()),
+        }
+    }
+}
+
+/// `ActivityStreams` properties can hold a single value or a list of them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        Self::Many(vec![])
+    }
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            Self::One(x) => vec![x],
+            Self::Many(x) => x,
+        }
+    }
+}
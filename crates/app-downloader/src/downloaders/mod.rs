@@ -1,5 +1,26 @@
-use std::{path::PathBuf, result::Result};
+use std::{
+    path::{Path, PathBuf},
+    result::Result,
+};
 
+use app_helpers::metadata::PostMetadata;
+
+use crate::DownloaderError;
+
+/// A recorded response from `fixtures/`, eg. `fixture!("reddit/gallery.json")`.
+///
+/// `fixture!(json "reddit/gallery.json")` parses it as JSON into whatever type is expected.
+#[cfg(test)]
+macro_rules! fixture {
+    ($path:literal) => {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/", $path))
+    };
+    (json $path:literal) => {
+        serde_json::from_str(fixture!($path)).expect("Invalid fixture")
+    };
+}
+
+pub mod bluesky;
 pub mod generic;
 pub mod imgur;
 pub mod instagram;
@@ -11,4 +32,179 @@ pub mod yt_dlp;
 
 mod common;
 
-type DownloaderReturn = Result<Vec<PathBuf>, String>;
+pub type DownloaderReturn = Result<Vec<DownloadedFile>, DownloaderError>;
+
+/// A file saved by a [`Downloader`], along with what is known about its post.
+#[derive(Debug, Clone)]
+pub struct DownloadedFile {
+    pub path: PathBuf,
+    pub metadata: PostMetadata,
+    /// Name of the file at its source without the extension, eg. from its URL.
+    pub name: Option<String>,
+}
+
+impl DownloadedFile {
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            metadata: PostMetadata::default(),
+            name: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_metadata(mut self, metadata: PostMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+}
+
+/// A source of media that can be plugged into the [`DownloaderRegistry`].
+///
+/// [`DownloaderRegistry`]: crate::DownloaderRegistry
+pub trait Downloader: Send + Sync {
+    /// Short, unique name of the downloader. Used for logging and reporting.
+    fn name(&self) -> &'static str;
+
+    /// Whether this downloader knows how to handle the given URL.
+    ///
+    /// Called in order of descending [`priority`](Self::priority),
+    /// so expensive checks should go into downloaders with a low priority.
+    fn can_download(&self, url: &str) -> bool;
+
+    /// Downloaders with a higher priority get the first chance to claim a URL.
+    ///
+    /// Downloaders with equal priority are tried in registration order.
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// Download all media from `url` into `download_dir`
+    /// and return the downloaded files.
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn;
+}
+
+/// Combine the results of downloading the items of a post, in order.
+///
+/// One failed item doesn't throw away the others: if any files were downloaded,
+/// they are returned in [`DownloaderError::Partial`] along with the errors.
+pub fn collect_downloads<I>(results: I) -> DownloaderReturn
+where
+    I: IntoIterator<Item = DownloaderReturn>,
+{
+    let mut files = vec![];
+    let mut errors = vec![];
+
+    for res in results {
+        match res {
+            Ok(x) => files.extend(x),
+            Err(DownloaderError::Partial {
+                files: x,
+                errors: e,
+            }) => {
+                files.extend(x);
+                errors.extend(e);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(files),
+        1 if files.is_empty() => Err(errors.remove(0)),
+        _ if files.is_empty() => Err(DownloaderError::Multiple(errors)),
+        _ => Err(DownloaderError::Partial { files, errors }),
+    }
+}
+
+/// Apply `f` to every downloaded file, including those of a partial download.
+pub fn map_files<F>(res: DownloaderReturn, f: F) -> DownloaderReturn
+where
+    F: FnMut(DownloadedFile) -> DownloadedFile,
+{
+    match res {
+        Ok(files) => Ok(files.into_iter().map(f).collect()),
+        Err(DownloaderError::Partial { files, errors }) => Err(DownloaderError::Partial {
+            files: files.into_iter().map(f).collect(),
+            errors,
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str) -> DownloadedFile {
+        DownloadedFile::new(PathBuf::from(name))
+    }
+
+    fn paths(files: &[DownloadedFile]) -> Vec<&Path> {
+        files.iter().map(|x| x.path.as_path()).collect()
+    }
+
+    #[test]
+    fn collect_downloads_keeps_files_of_failed_items() {
+        let res = collect_downloads([
+            Ok(vec![file("a")]),
+            Err(DownloaderError::extractor("b failed")),
+            Err(DownloaderError::Partial {
+                files: vec![file("c")],
+                errors: vec![DownloaderError::extractor("d failed")],
+            }),
+        ]);
+
+        let Err(DownloaderError::Partial { files, errors }) = res else {
+            panic!("Expected a partial download, got {res:?}");
+        };
+        assert_eq!(paths(&files), [Path::new("a"), Path::new("c")]);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["b failed", "d failed"]
+        );
+    }
+
+    #[test]
+    fn collect_downloads_fails_without_files() {
+        assert!(matches!(
+            collect_downloads([Err(DownloaderError::extractor("a failed"))]),
+            Err(DownloaderError::Extractor { .. })
+        ));
+        assert!(matches!(
+            collect_downloads([
+                Err(DownloaderError::extractor("a failed")),
+                Err(DownloaderError::extractor("b failed")),
+            ]),
+            Err(DownloaderError::Multiple(errors)) if errors.len() == 2
+        ));
+    }
+
+    #[test]
+    fn map_files_maps_partial_downloads() {
+        let res = map_files(
+            Err(DownloaderError::Partial {
+                files: vec![file("a")],
+                errors: vec![DownloaderError::extractor("b failed")],
+            }),
+            |x| {
+                x.with_metadata(PostMetadata {
+                    post_id: Some("1".to_string()),
+                    ..PostMetadata::default()
+                })
+            },
+        );
+
+        let Err(DownloaderError::Partial { files, .. }) = res else {
+            panic!("Expected a partial download, got {res:?}");
+        };
+        assert_eq!(files[0].metadata.post_id.as_deref(), Some("1"));
+    }
+}
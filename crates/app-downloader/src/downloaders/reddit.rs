@@ -1,3 +1,620 @@
+use std::{collections::HashMap, fs, io, path::Path, process, sync::PoisonError};
+
+use app_config::CONFIGURATION;
+use app_helpers::metadata::PostMetadata;
+use app_logger::{debug, trace, warn};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use regex::Regex;
+use reqwest::blocking::Response;
+use serde::Deserialize;
+
+use super::{collect_downloads, generic, map_files, DownloadedFile, Downloader, DownloaderReturn};
+use crate::{
+    downloaders::common::request::{Client, WithCookies},
+    DownloaderError, DOWNLOADERS,
+};
+
+pub static POST_URL_MATCH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^https?://(?:(?:www|old|new|np|m)\.)?reddit\.com/(?:(?:r|u|user)/[^/]+/)?(?:comments|gallery)/(?P<post_id>[a-z0-9]+)",
+    )
+    .expect("Invalid regex")
+});
+
+/// Links that redirect to a post.
+pub static SHORT_URL_MATCH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^https?://(?:redd\.it/[a-z0-9]+|v\.redd\.it/[a-zA-Z0-9]+/?$|(?:(?:www|old|new|m)\.)?reddit\.com/r/[^/]+/s/[a-zA-Z0-9]+)",
+    )
+    .expect("Invalid regex")
+});
+
+pub static PREVIEW_URL_MATCH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^https?://preview\.redd\.it/(?P<file_name>[^/?#]+)").expect("Invalid regex")
+});
+
+static DASH_BASE_URL_MATCH: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<BaseURL>\s*([^<]+?)\s*</BaseURL>").expect("Invalid regex"));
+
+/// Names of the audio track next to a DASH video, for when the manifest can't be read.
+/// Older videos use the shorter names.
+const AUDIO_FILE_NAMES: &[&str] = &[
+    "DASH_AUDIO_128.mp4",
+    "DASH_AUDIO_64.mp4",
+    "CMAF_AUDIO_128.mp4",
+    "CMAF_AUDIO_64.mp4",
+    "DASH_audio.mp4",
+    "audio",
+];
+
+pub struct RedditDownloader;
+
+impl Downloader for RedditDownloader {
+    fn name(&self) -> &'static str {
+        "reddit"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        is_reddit_image_url(url)
+            || PREVIEW_URL_MATCH.is_match(url)
+            || POST_URL_MATCH.is_match(url)
+            || SHORT_URL_MATCH.is_match(url)
+    }
+
+    fn priority(&self) -> i32 {
+        50
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download(download_dir, url)
+    }
+}
+
 pub fn is_reddit_image_url(url: &str) -> bool {
     url.starts_with("https://i.redd.it/")
 }
+
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
+    if is_reddit_image_url(url) {
+        return generic::download(download_dir, url);
+    }
+
+    if PREVIEW_URL_MATCH.is_match(url) {
+        return download_media(download_dir, &RedditMedia::File(url.to_string()));
+    }
+
+    let post = fetch_post(url)?;
+    let post = post.original();
+    trace!("Reddit post: {post:?}");
+
+    let media = post_media(post)?;
+    debug!("Reddit post media: {media:?}");
+
+    let post_metadata = post.metadata();
+
+    let res = media
+        .par_iter()
+        .map(|x| download_media(download_dir, x))
+        .collect::<Vec<_>>();
+
+    map_files(collect_downloads(res), |mut file| {
+        file.metadata = post_metadata.clone().merge_missing(&file.metadata).clone();
+        file
+    })
+}
+
+/// Something a post links to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RedditMedia {
+    /// An image or animation that can be downloaded as is.
+    File(String),
+    /// A video hosted on `v.redd.it`, with the audio in a separate track.
+    Video(RedditVideo),
+    /// Media hosted elsewhere.
+    Link(String),
+}
+
+/// Figure out which media a post consists of, in the order it's shown.
+fn post_media(post: &RedditPost) -> Result<Vec<RedditMedia>, DownloaderError> {
+    if let Some(gallery) = &post.gallery_data {
+        let metadata = post.media_metadata.as_ref().ok_or_else(|| {
+            DownloaderError::extractor("Reddit gallery post has no media metadata")
+        })?;
+
+        let media = gallery
+            .items
+            .iter()
+            .filter_map(|item| {
+                let media = metadata.get(&item.media_id)?;
+                if media.status.as_deref() != Some("valid") {
+                    warn!(
+                        "Skipping gallery item {id:?} with status {status:?}",
+                        id = item.media_id,
+                        status = media.status
+                    );
+                    return None;
+                }
+
+                media.url(&item.media_id).map(RedditMedia::File)
+            })
+            .collect::<Vec<_>>();
+
+        if media.is_empty() {
+            return Err(DownloaderError::extractor(
+                "Reddit gallery has no downloadable items",
+            ));
+        }
+
+        return Ok(media);
+    }
+
+    if let Some(video) = post
+        .secure_media
+        .as_ref()
+        .or(post.media.as_ref())
+        .and_then(|x| x.reddit_video.clone())
+    {
+        return Ok(vec![RedditMedia::Video(video)]);
+    }
+
+    let link = post
+        .url_overridden_by_dest
+        .as_deref()
+        .or(post.url.as_deref())
+        .filter(|x| !x.is_empty())
+        .ok_or_else(|| DownloaderError::extractor("Reddit post has no media"))?;
+
+    if is_reddit_image_url(link) || PREVIEW_URL_MATCH.is_match(link) {
+        Ok(vec![RedditMedia::File(link.to_owned())])
+    } else if POST_URL_MATCH.is_match(link) {
+        // Text posts link to themselves
+        Err(DownloaderError::extractor("Reddit post has no media"))
+    } else {
+        Ok(vec![RedditMedia::Link(link.to_owned())])
+    }
+}
+
+fn download_media(download_dir: &Path, media: &RedditMedia) -> DownloaderReturn {
+    match media {
+        RedditMedia::File(url) => {
+            // Previews are resized and recompressed, the original is on i.redd.it
+            let original = PREVIEW_URL_MATCH
+                .captures(url)
+                .and_then(|x| x.name("file_name"))
+                .map(|x| format!("https://i.redd.it/{}", x.as_str()));
+
+            let Some(original) = original else {
+                return generic::download(download_dir, url);
+            };
+
+            generic::download(download_dir, &original).or_else(|e| {
+                debug!("Failed to download original {original:?}, using preview: {e}");
+                generic::download(download_dir, url)
+            })
+        }
+        RedditMedia::Video(video) => download_video(download_dir, video).map(|x| vec![x]),
+        RedditMedia::Link(url) => {
+            let downloader = DOWNLOADERS
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .find(url)
+                .filter(|x| x.name() != RedditDownloader.name())
+                .ok_or_else(|| DownloaderError::UnsupportedUrl(url.clone()))?;
+            debug!(
+                "Reddit post links to {url:?}, downloading with {name:?}",
+                name = downloader.name()
+            );
+
+            downloader.download(download_dir, url)
+        }
+    }
+}
+
+/// Download a `v.redd.it` video and its audio track and mux them together.
+///
+/// If there is no audio, or it can't be added, the video is kept without it.
+fn download_video(
+    download_dir: &Path,
+    video: &RedditVideo,
+) -> Result<DownloadedFile, DownloaderError> {
+    let mut video_file = generic::download(download_dir, &video.fallback_url)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| DownloaderError::extractor("Reddit video download produced no file"))?;
+
+    if video.is_gif || video.has_audio == Some(false) {
+        trace!("Reddit video has no audio");
+        return Ok(video_file);
+    }
+
+    let Some(audio_file) =
+        audio_urls(video)
+            .into_iter()
+            .find_map(|url| match generic::download(download_dir, &url) {
+                Ok(files) => files.into_iter().next(),
+                Err(e) => {
+                    trace!("No audio at {url:?}: {e}");
+                    None
+                }
+            })
+    else {
+        debug!("Could not find audio for {:?}", video.fallback_url);
+        return Ok(video_file);
+    };
+
+    let muxed_path = video_file.path.with_extension("muxed.mp4");
+    let muxed = mux(&video_file.path, &audio_file.path, &muxed_path);
+    let _ = fs::remove_file(&audio_file.path);
+
+    match muxed {
+        Ok(()) => {
+            let path = video_file.path.with_extension("mp4");
+            fs::rename(&muxed_path, &path).map_err(DownloaderError::io(format!(
+                "Failed to rename {muxed_path:?} to {path:?}"
+            )))?;
+            if path != video_file.path {
+                let _ = fs::remove_file(&video_file.path);
+            }
+            video_file.path = path;
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&muxed_path);
+            warn!(
+                "Failed to add audio to {:?}, keeping it without: {e}",
+                video_file.path
+            );
+        }
+    }
+
+    Ok(video_file)
+}
+
+/// Where the audio track of a video might be, most likely first.
+fn audio_urls(video: &RedditVideo) -> Vec<String> {
+    let Some((base_url, _)) = video.fallback_url.rsplit_once('/') else {
+        return vec![];
+    };
+
+    let mut names = video
+        .dash_url
+        .as_deref()
+        .and_then(|url| {
+            Client::default()
+                .ok()?
+                .get(url)
+                .with_cookies()
+                .send()
+                .and_then(Response::error_for_status)
+                .and_then(Response::text)
+                .map_err(|e| debug!("Failed to get DASH manifest {url:?}: {e}"))
+                .ok()
+        })
+        .map(|manifest| dash_audio_file_names(&manifest))
+        .unwrap_or_default();
+
+    for name in AUDIO_FILE_NAMES {
+        if !names.iter().any(|x| x == name) {
+            names.push((*name).to_string());
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| format!("{base_url}/{name}"))
+        .collect()
+}
+
+/// Get the audio tracks listed in a DASH manifest, best quality first.
+fn dash_audio_file_names(manifest: &str) -> Vec<String> {
+    let mut names = DASH_BASE_URL_MATCH
+        .captures_iter(manifest)
+        .filter_map(|x| x.get(1))
+        .map(|x| x.as_str().to_string())
+        .filter(|x| x.to_lowercase().contains("audio"))
+        .collect::<Vec<_>>();
+
+    // The bitrate is part of the name, eg. `DASH_AUDIO_128.mp4`
+    names.sort_by_cached_key(|x| {
+        std::cmp::Reverse(
+            x.chars()
+                .filter(char::is_ascii_digit)
+                .collect::<String>()
+                .parse::<u32>()
+                .unwrap_or_default(),
+        )
+    });
+
+    names
+}
+
+fn mux(video: &Path, audio: &Path, output: &Path) -> Result<(), DownloaderError> {
+    let mut cmd = process::Command::new(&CONFIGURATION.ffmpeg_path);
+    if let Some(dir) = output.parent().filter(|x| !x.as_os_str().is_empty()) {
+        cmd.current_dir(dir);
+    }
+    let cmd = cmd
+        .arg("-y")
+        .args(["-loglevel", "error"])
+        .arg("-i")
+        .arg(video)
+        .arg("-i")
+        .arg(audio)
+        .args(["-map", "0:v:0", "-map", "1:a:0", "-c", "copy"])
+        .arg(output);
+    debug!("Running cmd: {cmd:?}");
+
+    let output = cmd.output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => DownloaderError::MissingBinary("ffmpeg".to_string()),
+        _ => DownloaderError::Io {
+            context: format!("Failed to run {cmd:?}"),
+            source: e,
+        },
+    })?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(DownloaderError::extractor(format!(
+            "ffmpeg exited with {status}: {stderr}",
+            status = output.status,
+            stderr = String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// Get the post a URL points to from the Reddit JSON API.
+fn fetch_post(url: &str) -> Result<RedditPost, DownloaderError> {
+    let client = Client::default()?;
+
+    let post_id = match POST_URL_MATCH.captures(url) {
+        Some(x) => x["post_id"].to_string(),
+        None => {
+            // Short links only redirect to the post
+            let res = client.get(url).with_cookies().send()?.error_for_status()?;
+            trace!("{url:?} redirected to {:?}", res.url().as_str());
+
+            POST_URL_MATCH
+                .captures(res.url().as_str())
+                .map(|x| x["post_id"].to_string())
+                .ok_or_else(|| DownloaderError::UnsupportedUrl(url.to_string()))?
+        }
+    };
+    debug!("Reddit post ID: {post_id:?}");
+
+    let api_url = format!("https://www.reddit.com/comments/{post_id}.json?raw_json=1");
+    debug!("Fetching from reddit API url: {api_url:?}");
+
+    let listings = client
+        .get(&api_url)
+        .with_cookies()
+        .send()?
+        .error_for_status()?
+        .json::<Vec<serde_json::Value>>()?;
+
+    parse_post(listings)
+}
+
+/// Get the post out of the response of the `.json` endpoint,
+/// which is a listing with the post followed by one with the comments.
+fn parse_post(listings: Vec<serde_json::Value>) -> Result<RedditPost, DownloaderError> {
+    let listing = listings
+        .into_iter()
+        .next()
+        .ok_or_else(|| DownloaderError::extractor("Reddit response has no post listing"))?;
+
+    serde_json::from_value::<RedditListing>(listing)
+        .map_err(DownloaderError::extractor_with(
+            "Failed to parse reddit post",
+        ))?
+        .data
+        .children
+        .into_iter()
+        .next()
+        .map(|x| x.data)
+        .ok_or_else(|| DownloaderError::extractor("Reddit post listing is empty"))
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditListing {
+    data: RedditListingData,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditListingData {
+    children: Vec<RedditThing>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedditThing {
+    data: RedditPost,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RedditPost {
+    id: String,
+    title: Option<String>,
+    author: Option<String>,
+    #[serde(default)]
+    selftext: String,
+    url: Option<String>,
+    url_overridden_by_dest: Option<String>,
+    gallery_data: Option<RedditGallery>,
+    media_metadata: Option<HashMap<String, RedditMediaMetadata>>,
+    media: Option<RedditPostMedia>,
+    secure_media: Option<RedditPostMedia>,
+    /// The post this one is a crosspost of, and so on.
+    #[serde(default)]
+    crosspost_parent_list: Vec<Self>,
+}
+
+impl RedditPost {
+    /// Follow crossposts to the post that has the media.
+    fn original(&self) -> &Self {
+        let mut post = self;
+        while let Some(parent) = post.crosspost_parent_list.first() {
+            trace!("Post {} is a crosspost of {}", post.id, parent.id);
+            post = parent;
+        }
+        post
+    }
+
+    fn metadata(&self) -> PostMetadata {
+        PostMetadata {
+            author: self.author.clone().filter(|x| x != "[deleted]"),
+            title: self.title.clone().filter(|x| !x.is_empty()),
+            description: Some(self.selftext.clone()).filter(|x| !x.is_empty()),
+            post_id: Some(self.id.clone()),
+            alt_text: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RedditGallery {
+    items: Vec<RedditGalleryItem>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RedditGalleryItem {
+    media_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RedditMediaMetadata {
+    status: Option<String>,
+    /// The mime type, eg. `image/jpg`.
+    m: Option<String>,
+    /// The biggest available version.
+    s: Option<RedditMediaSource>,
+}
+
+impl RedditMediaMetadata {
+    fn url(&self, media_id: &str) -> Option<String> {
+        let source = self.s.as_ref();
+
+        // Animations are only available as previews
+        if let Some(url) = source.and_then(|x| x.mp4.clone().or_else(|| x.gif.clone())) {
+            return Some(url);
+        }
+
+        self.m
+            .as_deref()
+            .and_then(|x| x.strip_prefix("image/"))
+            .map(|extension| format!("https://i.redd.it/{media_id}.{extension}"))
+            .or_else(|| source.and_then(|x| x.u.clone()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RedditMediaSource {
+    u: Option<String>,
+    gif: Option<String>,
+    mp4: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RedditPostMedia {
+    reddit_video: Option<RedditVideo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct RedditVideo {
+    /// The video track without audio.
+    fallback_url: String,
+    dash_url: Option<String>,
+    has_audio: Option<bool>,
+    #[serde(default)]
+    is_gif: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gallery_skips_invalid_items() {
+        let post = parse_post(fixture!(json "reddit/gallery.json")).expect("Failed to parse post");
+
+        assert_eq!(post.id, "1c8g2vz");
+        assert_eq!(
+            post_media(post.original()).expect("Gallery has no media"),
+            [
+                RedditMedia::File("https://i.redd.it/k2m1x9a0zjvc1.jpg".to_string()),
+                RedditMedia::File(
+                    "https://preview.redd.it/p4c6t1n2zjvc1.gif?format=mp4&s=0c1d2e3f4a5b"
+                        .to_string()
+                ),
+                RedditMedia::File("https://i.redd.it/d9e8r7h5zjvc1.png".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn video_post_has_reddit_video() {
+        let post = parse_post(fixture!(json "reddit/video.json")).expect("Failed to parse post");
+
+        assert_eq!(
+            post_media(&post).expect("Video post has no media"),
+            [RedditMedia::Video(RedditVideo {
+                fallback_url: "https://v.redd.it/x8pq3m2k1wmc1/DASH_720.mp4?source=fallback"
+                    .to_string(),
+                dash_url: Some(
+                    "https://v.redd.it/x8pq3m2k1wmc1/DASHPlaylist.mpd?a=1712345678&v=1&f=sd"
+                        .to_string()
+                ),
+                has_audio: Some(true),
+                is_gif: false,
+            })]
+        );
+    }
+
+    #[test]
+    fn crosspost_chain_leads_to_original() {
+        let post =
+            parse_post(fixture!(json "reddit/crosspost.json")).expect("Failed to parse post");
+        let original = post.original();
+
+        assert_eq!(original.id, "1d0orig");
+        assert_eq!(
+            post_media(original).expect("Original post has no media"),
+            [RedditMedia::File(
+                "https://i.redd.it/7h3k2m9q0x1d1.jpeg".to_string()
+            )]
+        );
+        assert_eq!(
+            original.metadata(),
+            PostMetadata {
+                author: None,
+                title: Some("When the code compiles on the first try".to_string()),
+                description: None,
+                post_id: Some("1d0orig".to_string()),
+                alt_text: None,
+            }
+        );
+    }
+
+    #[test]
+    fn text_post_has_no_media() {
+        let post = parse_post(fixture!(json "reddit/text.json")).expect("Failed to parse post");
+
+        assert!(matches!(
+            post_media(&post),
+            Err(DownloaderError::Extractor { .. })
+        ));
+        assert_eq!(
+            post.metadata().description.as_deref(),
+            Some("Which template is this? Someone posted it yesterday & it's been deleted since.")
+        );
+    }
+
+    #[test]
+    fn dash_manifest_lists_audio_best_first() {
+        let manifest = fixture!("reddit/DASHPlaylist.mpd");
+
+        assert_eq!(
+            dash_audio_file_names(manifest),
+            ["DASH_AUDIO_128.mp4", "DASH_AUDIO_64.mp4"]
+        );
+    }
+}
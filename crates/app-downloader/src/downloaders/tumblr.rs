@@ -1,16 +1,354 @@
-use std::path::PathBuf;
+use std::path::Path;
 
+use app_helpers::metadata::PostMetadata;
+use app_logger::{debug, trace};
 use once_cell::sync::Lazy;
+use rayon::prelude::*;
 use regex::Regex;
+use reqwest::blocking::Response;
+use serde::Deserialize;
 
-use super::DownloaderReturn;
-use crate::downloaders::twitter;
+use super::{collect_downloads, map_files, Downloader, DownloaderReturn};
+use crate::{
+    downloaders::{
+        common::request::{Client, WithCookies},
+        generic, twitter, yt_dlp,
+    },
+    DownloaderError,
+};
 
 pub static URL_MATCH: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^https?://(www\.)?tumblr\.com/(?P<username>[^/]+)/(?P<post_id>[0-9]+)(/|/[^/]+)?")
-        .expect("Invalid regex")
+    Regex::new(
+        r"^https?://(?:(?:www\.)?tumblr\.com/(?:blog/view/)?(?P<username>[^/]+)|(?P<subdomain>[a-zA-Z0-9-]+)\.tumblr\.com/post)/(?P<post_id>[0-9]+)(/|/[^/]+)?",
+    )
+    .expect("Invalid regex")
 });
 
-pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
-    twitter::download(download_dir, url)
+/// Name of the script with the data the Tumblr web app starts with.
+static INITIAL_STATE: &str = "___INITIAL_STATE___";
+
+pub struct TumblrDownloader;
+
+impl Downloader for TumblrDownloader {
+    fn name(&self) -> &'static str {
+        "tumblr"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        URL_MATCH.is_match(url)
+    }
+
+    fn priority(&self) -> i32 {
+        60
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download(download_dir, url)
+    }
+}
+
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
+    let post = match fetch_post(url) {
+        Ok(post) => post,
+        Err(e) => {
+            debug!("Failed to get tumblr post data ({e}), trying yt-dlp and a screenshot...");
+            return twitter::download(download_dir, url);
+        }
+    };
+    trace!("Tumblr post: {post:?}");
+
+    let media = post.media();
+    debug!("Tumblr post media: {media:?}");
+
+    if media.is_empty() {
+        debug!("Tumblr post has no media, taking a screenshot...");
+        return twitter::download(download_dir, url);
+    }
+
+    let post_metadata = post.metadata();
+
+    let res = media
+        .par_iter()
+        .map(|x| match x {
+            TumblrMedia::File(url) => generic::download(download_dir, url),
+            TumblrMedia::External(url) => yt_dlp::download(download_dir, url),
+        })
+        .collect::<Vec<_>>();
+
+    map_files(collect_downloads(res), |mut file| {
+        file.metadata = post_metadata.clone().merge_missing(&file.metadata).clone();
+        file
+    })
+}
+
+/// Get the post a URL points to from the data embedded in its page.
+fn fetch_post(url: &str) -> Result<TumblrPost, DownloaderError> {
+    let captures = URL_MATCH
+        .captures(url)
+        .ok_or_else(|| DownloaderError::UnsupportedUrl(url.to_string()))?;
+    let username = captures
+        .name("username")
+        .or_else(|| captures.name("subdomain"))
+        .map(|x| x.as_str())
+        .ok_or_else(|| DownloaderError::UnsupportedUrl(url.to_string()))?;
+    let post_id = &captures["post_id"];
+
+    let page_url = format!("https://www.tumblr.com/{username}/{post_id}");
+    debug!("Fetching tumblr post page {page_url:?}");
+
+    let html = Client::default()?
+        .get(&page_url)
+        .with_cookies()
+        .send()
+        .and_then(Response::error_for_status)
+        .and_then(Response::text)?;
+
+    let state = initial_state(&html)
+        .ok_or_else(|| DownloaderError::extractor("Failed to find post data in tumblr page"))?;
+
+    let post = find_post(&state, post_id)
+        .ok_or_else(|| DownloaderError::extractor("Failed to find post in tumblr page data"))?;
+
+    let mut post = serde_json::from_value::<TumblrPost>(post.clone()).map_err(
+        DownloaderError::extractor_with("Failed to parse tumblr post"),
+    )?;
+    post.id_string.get_or_insert_with(|| post_id.to_string());
+
+    Ok(post)
+}
+
+fn initial_state(html: &str) -> Option<serde_json::Value> {
+    let dom = tl::parse(html, tl::ParserOptions::default()).ok()?;
+    let parser = dom.parser();
+
+    dom.query_selector("script")?
+        .filter_map(|x| x.get(parser))
+        .filter_map(|x| x.as_tag())
+        .find_map(|tag| {
+            let text = tag.inner_text(parser);
+            let text = text.trim();
+
+            let is_state_script = tag
+                .attributes()
+                .id()
+                .is_some_and(|x| x.as_utf8_str() == INITIAL_STATE);
+            let json = if is_state_script {
+                text
+            } else {
+                // `window['___INITIAL_STATE___'] = {...};`
+                let (name, json) = text.split_once('=')?;
+                if !name.contains(INITIAL_STATE) {
+                    return None;
+                }
+                json.trim().trim_end_matches(';')
+            };
+
+            serde_json::from_str(json).ok()
+        })
+}
+
+/// Look for the post with `post_id` anywhere in the page data.
+///
+/// Where exactly it is changes with the layout of the page.
+fn find_post<'a>(value: &'a serde_json::Value, post_id: &str) -> Option<&'a serde_json::Value> {
+    match value {
+        serde_json::Value::Object(object) => {
+            let id_matches = ["idString", "id"].iter().any(|key| match object.get(*key) {
+                Some(serde_json::Value::String(x)) => x == post_id,
+                Some(serde_json::Value::Number(x)) => x.to_string() == post_id,
+                _ => false,
+            });
+
+            if id_matches && object.contains_key("content") {
+                return Some(value);
+            }
+
+            object.values().find_map(|x| find_post(x, post_id))
+        }
+        serde_json::Value::Array(array) => array.iter().find_map(|x| find_post(x, post_id)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TumblrMedia {
+    /// An image, GIF or video hosted on Tumblr.
+    File(String),
+    /// A video embedded from another site.
+    External(String),
+}
+
+/// A post in the Neue Post Format.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TumblrPost {
+    id_string: Option<String>,
+    blog_name: Option<String>,
+    summary: Option<String>,
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    layout: Vec<LayoutBlock>,
+    /// The posts this one reblogs, oldest first.
+    #[serde(default)]
+    trail: Vec<TrailItem>,
+}
+
+impl TumblrPost {
+    /// All media of the reblog trail and the post itself, in the order it's shown.
+    fn media(&self) -> Vec<TumblrMedia> {
+        self.trail
+            .iter()
+            .flat_map(|x| ordered_blocks(&x.content, &x.layout))
+            .chain(ordered_blocks(&self.content, &self.layout))
+            .filter_map(ContentBlock::media)
+            .collect()
+    }
+
+    fn metadata(&self) -> PostMetadata {
+        let text = self
+            .trail
+            .iter()
+            .flat_map(|x| &x.content)
+            .chain(&self.content)
+            .filter_map(|x| match x {
+                ContentBlock::Text { text } if !text.trim().is_empty() => Some(text.trim()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        PostMetadata {
+            author: self
+                .trail
+                .first()
+                .and_then(|x| x.blog.as_ref())
+                .and_then(|x| x.name.clone())
+                .or_else(|| self.blog_name.clone()),
+            title: self.summary.clone().filter(|x| !x.is_empty()),
+            description: Some(text).filter(|x| !x.is_empty()),
+            post_id: self.id_string.clone(),
+            alt_text: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TrailItem {
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    layout: Vec<LayoutBlock>,
+    blog: Option<TrailBlog>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TrailBlog {
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ContentBlock {
+    Image {
+        #[serde(default)]
+        media: Vec<MediaObject>,
+    },
+    Video {
+        media: Option<MediaObjects>,
+        url: Option<String>,
+        provider: Option<String>,
+    },
+    Text {
+        #[serde(default)]
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl ContentBlock {
+    fn media(&self) -> Option<TumblrMedia> {
+        match self {
+            Self::Image { media } => best_version(media).map(TumblrMedia::File),
+            Self::Video {
+                media,
+                url,
+                provider,
+            } => {
+                let hosted = media.as_ref().and_then(|x| match x {
+                    MediaObjects::One(x) => Some(x.url.clone()),
+                    MediaObjects::Many(x) => best_version(x),
+                });
+
+                match (hosted, provider.as_deref()) {
+                    (Some(hosted), Some("tumblr") | None) => Some(TumblrMedia::File(hosted)),
+                    _ => url.clone().map(TumblrMedia::External),
+                }
+            }
+            Self::Text { .. } | Self::Other => None,
+        }
+    }
+}
+
+/// The biggest version of an image, preferring the original.
+fn best_version(media: &[MediaObject]) -> Option<String> {
+    media
+        .iter()
+        .max_by_key(|x| (x.has_original_dimensions, x.width.unwrap_or_default()))
+        .map(|x| x.url.clone())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum MediaObjects {
+    One(MediaObject),
+    Many(Vec<MediaObject>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaObject {
+    url: String,
+    width: Option<u64>,
+    #[serde(default)]
+    has_original_dimensions: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LayoutBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    display: Vec<LayoutRow>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LayoutRow {
+    #[serde(default)]
+    blocks: Vec<usize>,
+}
+
+/// Content blocks in the order the `rows` layout shows them,
+/// followed by any blocks the layout leaves out.
+fn ordered_blocks<'a>(
+    content: &'a [ContentBlock],
+    layout: &[LayoutBlock],
+) -> impl Iterator<Item = &'a ContentBlock> {
+    let mut order = layout
+        .iter()
+        .filter(|x| x.kind == "rows")
+        .flat_map(|x| &x.display)
+        .flat_map(|x| &x.blocks)
+        .copied()
+        .filter(|x| *x < content.len())
+        .collect::<Vec<_>>();
+
+    for i in 0..content.len() {
+        if !order.contains(&i) {
+            order.push(i);
+        }
+    }
+
+    order.into_iter().map(|i| &content[i])
 }
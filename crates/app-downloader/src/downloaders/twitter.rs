@@ -1,12 +1,12 @@
-use std::path::PathBuf;
+use std::path::Path;
 
 use app_config::CONFIG;
 use app_logger::{debug, trace};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use super::DownloaderReturn;
-use crate::downloaders::yt_dlp;
+use super::{Downloader, DownloaderReturn};
+use crate::{downloaders::yt_dlp, DownloaderError};
 
 pub static URL_MATCH: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
@@ -20,19 +20,59 @@ pub static MEDIA_URL_MATCH: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^https?://pbs\.twimg\.com/media/").expect("Invalid regex")
 });
 
-pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
+pub struct TwitterDownloader;
+
+impl Downloader for TwitterDownloader {
+    fn name(&self) -> &'static str {
+        "twitter"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        URL_MATCH.is_match(url)
+    }
+
+    fn priority(&self) -> i32 {
+        90
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download(download_dir, url)
+    }
+}
+
+pub struct TwitterMediaDownloader;
+
+impl Downloader for TwitterMediaDownloader {
+    fn name(&self) -> &'static str {
+        "twitter-media"
+    }
+
+    fn can_download(&self, url: &str) -> bool {
+        MEDIA_URL_MATCH.is_match(url)
+    }
+
+    fn priority(&self) -> i32 {
+        80
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download_media_url(download_dir, url)
+    }
+}
+
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
     debug!("Trying to download tweet media from: {:?}", &url);
 
-    yt_dlp::download(download_dir, url).or_else(|_e| {
-        debug!("Failed to download with yt-dlp. Trying to screenshot...");
+    yt_dlp::download(download_dir, url).or_else(|e| {
+        debug!("Failed to download with yt-dlp ({e}). Trying to screenshot...");
 
         screenshot_tweet(download_dir, url)
     })
 }
 
-pub fn download_media_url(download_dir: &PathBuf, twitter_media_url: &str) -> DownloaderReturn {
+pub fn download_media_url(download_dir: &Path, twitter_media_url: &str) -> DownloaderReturn {
     let mut parsed = url::Url::parse(twitter_media_url)
-        .map_err(|x| format!("Failed to parse twitter media URL: {x:?}"))?;
+        .map_err(|_| DownloaderError::UnsupportedUrl(twitter_media_url.to_string()))?;
 
     let url_without_name = {
         let params = parsed.query_pairs().filter(|(key, _)| key != "name");
@@ -49,7 +89,7 @@ pub fn download_media_url(download_dir: &PathBuf, twitter_media_url: &str) -> Do
     yt_dlp::download(download_dir, url_without_name)
 }
 
-fn screenshot_tweet(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
+fn screenshot_tweet(download_dir: &Path, url: &str) -> DownloaderReturn {
     debug!("Trying to screenshot tweet: {:?}", &url);
 
     let endpoint = CONFIG.endpoints.twitter_screenshot_base_url();
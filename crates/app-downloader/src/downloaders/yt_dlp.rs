@@ -1,19 +1,60 @@
-use std::{path::PathBuf, process};
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    iter,
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    thread,
+};
 
-use app_config::CONFIGURATION;
-use app_helpers::id::time_id;
-use app_logger::{debug, trace};
+use app_config::{YtDlpProfile, CONFIG, CONFIGURATION};
+use app_helpers::{
+    id::short_id,
+    metadata::PostMetadata,
+    progress::{self, Progress},
+};
+use app_logger::{debug, trace, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
 
-use super::DownloaderReturn;
-use crate::downloaders::{common::USER_AGENT, generic};
+use super::{collect_downloads, DownloadedFile, Downloader, DownloaderReturn};
+use crate::{
+    downloaders::{
+        common::{cookies, USER_AGENT},
+        generic,
+    },
+    DownloaderError,
+};
 
-pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
+/// Catch-all downloader that hands any URL over to `yt-dlp`.
+pub struct YtDlpDownloader;
+
+impl Downloader for YtDlpDownloader {
+    fn name(&self) -> &'static str {
+        "yt-dlp"
+    }
+
+    fn can_download(&self, _url: &str) -> bool {
+        true
+    }
+
+    fn priority(&self) -> i32 {
+        i32::MIN
+    }
+
+    fn download(&self, download_dir: &Path, url: &str) -> DownloaderReturn {
+        download(download_dir, url)
+    }
+}
+
+pub fn download(download_dir: &Path, url: &str) -> DownloaderReturn {
     let yt_dlp = &CONFIGURATION.yt_dlp_path;
     trace!("`yt-dlp' binary: {:?}", &yt_dlp);
     let output_template = get_output_template(download_dir);
     debug!("template: {:?}", &output_template);
     let mut cmd = process::Command::new(yt_dlp);
     let cmd = cmd
+        .current_dir(download_dir)
         .arg("--no-check-certificate")
         .args(["--socket-timeout", "120"])
         .arg("--no-part")
@@ -23,64 +64,467 @@ pub fn download(download_dir: &PathBuf, url: &str) -> DownloaderReturn {
             "--output",
             output_template
                 .to_str()
-                .ok_or_else(|| "Failed to convert path to string".to_string())?,
+                .ok_or_else(|| DownloaderError::Io {
+                    context: format!("Failed to convert {output_template:?} to string"),
+                    source: io::ErrorKind::InvalidInput.into(),
+                })?,
         ])
         .args(["--user-agent", USER_AGENT])
-        .args(["--no-simulate", "--print", "after_move:filepath"])
+        .args(["--no-simulate", "--print", PRINT_TEMPLATE])
+        .args([
+            "--progress",
+            "--newline",
+            "--progress-template",
+            PROGRESS_TEMPLATE,
+        ])
         // .arg("--verbose")
+        .args(cookies::yt_dlp_args(url))
+        .args(max_items_args())
+        .args(profile_args(url))
         .arg(url);
     debug!("Running cmd: {:?}", &cmd);
-    let cmd_output = cmd.output();
+    let cmd_output = output_with_progress(cmd);
     trace!("Cmd output: {:?}", &cmd_output);
-    let mut err = String::new();
-    let new_file_path = match cmd_output {
-        Ok(process::Output {
-            stdout,
-            stderr: _,
-            status,
-        }) if status.success() => {
-            let output = String::from_utf8(stdout)
-                .map_err(|e| format!("Failed to convert output to UTF-8: {e:?}"))?;
-            let output_path = PathBuf::from(output.trim());
-
-            if output_path.exists() {
-                debug!("yt-dlp successful download to file: {:?}", output_path);
-                Ok(output_path)
-            } else {
-                Err("yt-dlp finished but file does not exist.")
-            }
+    let output = match cmd_output {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(DownloaderError::MissingBinary("yt-dlp".to_string()));
         }
-        Ok(process::Output {
-            stdout: _,
-            stderr,
-            status: _,
-        }) if is_image_error(stderr.clone()) => return generic::download(download_dir, url),
-        _ => {
-            let msg = format!("yt-dlp failed downloading meme: {cmd_output:?}");
-            err.push_str(msg.as_str());
-            Err(err.as_str())
+        Err(e) => {
+            return Err(DownloaderError::Io {
+                context: format!("Failed to run {cmd:?}"),
+                source: e,
+            });
+        }
+    };
+
+    let entries = parse_entries(&output.stdout);
+
+    if !output.status.success() && entries.is_empty() {
+        return match classify_error(&output.stderr) {
+            YtDlpErrorKind::Image => generic::download(download_dir, url),
+            YtDlpErrorKind::UnsupportedUrl => Err(DownloaderError::UnsupportedUrl(url.to_string())),
+            YtDlpErrorKind::Other => Err(DownloaderError::YtDlp(output)),
+        };
+    }
+
+    // Some entries of a playlist failing shouldn't throw away the others
+    let error = (!output.status.success()).then(|| DownloaderError::YtDlp(output));
+
+    downloaded_files(entries, error)
+}
+
+/// The files yt-dlp downloaded, with `error` and files that don't exist reported as failed items.
+fn downloaded_files(entries: Vec<YtDlpInfo>, error: Option<DownloaderError>) -> DownloaderReturn {
+    if entries.is_empty() && error.is_none() {
+        return Err(DownloaderError::extractor(
+            "yt-dlp finished without downloading any files",
+        ));
+    }
+
+    let (entries, missing): (Vec<_>, Vec<_>) =
+        entries.into_iter().partition(|x| x.filepath.exists());
+
+    debug!(
+        "yt-dlp successful download to files: {:?}",
+        entries.iter().map(|x| &x.filepath).collect::<Vec<_>>()
+    );
+
+    let missing = missing.into_iter().map(|x| {
+        Err(DownloaderError::extractor(format!(
+            "yt-dlp finished but file {:?} does not exist.",
+            x.filepath
+        )))
+    });
+
+    collect_downloads(
+        iter::once(Ok(entries.into_iter().map(Into::into).collect()))
+            .chain(missing)
+            .chain(error.map(Err)),
+    )
+}
+
+/// The entries yt-dlp printed with [`PRINT_TEMPLATE`], one line each.
+///
+/// Playlists and posts with several videos print a line for every file.
+fn parse_entries(stdout: &[u8]) -> Vec<YtDlpInfo> {
+    stdout
+        .split(|x| *x == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .filter_map(|line| {
+            serde_json::from_slice::<YtDlpInfo>(line)
+                .map_err(|e| {
+                    trace!(
+                        "Skipping yt-dlp output line {:?}: {e}",
+                        String::from_utf8_lossy(line)
+                    );
+                })
+                .ok()
+        })
+        .collect()
+}
+
+/// Limit how many items of a playlist are downloaded.
+fn max_items_args() -> Vec<String> {
+    CONFIG
+        .yt_dlp
+        .max_items()
+        .map(|max_items| vec!["--playlist-end".to_string(), max_items.to_string()])
+        .unwrap_or_default()
+}
+
+/// The configured profiles, with their URL patterns compiled.
+static PROFILES: Lazy<Vec<(Regex, &'static YtDlpProfile)>> = Lazy::new(|| {
+    CONFIG
+        .yt_dlp
+        .profiles
+        .iter()
+        .filter_map(|profile| match Regex::new(&profile.url_pattern) {
+            Ok(pattern) => Some((pattern, profile)),
+            Err(e) => {
+                warn!(
+                    "Ignoring yt-dlp profile with invalid URL pattern {:?}: {e}",
+                    profile.url_pattern
+                );
+                None
+            }
+        })
+        .collect()
+});
+
+/// Arguments of the first configured profile that matches `url`.
+fn profile_args(url: &str) -> Vec<String> {
+    let Some((_, profile)) = PROFILES.iter().find(|(pattern, _)| pattern.is_match(url)) else {
+        return vec![];
+    };
+    debug!(
+        "Using yt-dlp profile {:?} for {url:?}",
+        profile.name.as_deref().unwrap_or(&profile.url_pattern)
+    );
+
+    args_for_profile(profile)
+}
+
+fn args_for_profile(profile: &YtDlpProfile) -> Vec<String> {
+    let mut args = vec![];
+    let mut push = |flag: &str, value: &str| args.extend([flag.to_string(), value.to_string()]);
+
+    if let Some(format) = &profile.format {
+        push("--format", format);
+    }
+
+    if let Some(format_sort) = &profile.format_sort {
+        push("--format-sort", format_sort);
+    }
+
+    if let Some(max_filesize) = &profile.max_filesize {
+        push("--max-filesize", max_filesize);
+    }
+
+    if let Some(playlist_items) = &profile.playlist_items {
+        push("--playlist-items", playlist_items);
+    }
+
+    if profile.embed_subtitles {
+        if let Some(subtitle_langs) = &profile.subtitle_langs {
+            push("--sub-langs", &subtitle_langs.join(","));
         }
-    }?;
 
-    if !new_file_path.exists() {
-        return Err("yt-dlp finished but file does not exist.".to_string());
+        args.extend(["--write-subs".to_string(), "--embed-subs".to_string()]);
+
+        if profile.auto_subtitles {
+            args.push("--write-auto-subs".to_string());
+        }
     }
 
-    Ok(vec![new_file_path])
+    args.extend(profile.args.iter().cloned());
+
+    args
 }
 
+/// Makes yt-dlp print the fields of [`YtDlpInfo`] as JSON once the file is in place.
+static PRINT_TEMPLATE: &str =
+    "after_move:%(.{filepath,id,title,description,uploader,uploader_id,channel})j";
+
+/// Makes yt-dlp print download progress on lines of their own that [`parse_progress`] picks up.
+static PROGRESS_TEMPLATE: &str = "download:[progress] %(progress.downloaded_bytes)s \
+                                  %(progress.total_bytes)s %(progress.total_bytes_estimate)s";
+
+#[derive(Debug, Deserialize)]
+struct YtDlpInfo {
+    filepath: PathBuf,
+    id: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    uploader: Option<String>,
+    uploader_id: Option<String>,
+    channel: Option<String>,
+}
+
+impl From<YtDlpInfo> for DownloadedFile {
+    fn from(value: YtDlpInfo) -> Self {
+        Self::new(value.filepath).with_metadata(PostMetadata {
+            author: value.uploader.or(value.channel).or(value.uploader_id),
+            title: value.title,
+            description: value.description.filter(|x| !x.is_empty()),
+            post_id: value.id,
+            alt_text: None,
+        })
+    }
+}
+
+/// Where yt-dlp should save the file.
+///
+/// The file gets its final name from the configured filename template once it's fixed,
+/// this only has to be unique.
 fn get_output_template<S: Into<PathBuf>>(download_dir: S) -> PathBuf {
-    let file_identifier = time_id();
+    let file_identifier = short_id();
     let file_name = format!("{file_identifier}.%(id).64s.%(ext)s");
 
     download_dir.into().join(file_name)
 }
 
-fn is_image_error(output: Vec<u8>) -> bool {
-    let output = String::from_utf8(output).unwrap_or_default();
+/// Like [`process::Command::output`], but reports the progress lines yt-dlp prints
+/// instead of including them in the output.
+fn output_with_progress(cmd: &mut process::Command) -> io::Result<process::Output> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stderr = child.stderr.take();
+    let sink = progress::current();
+    let stderr = thread::spawn(move || progress::with_progress(sink, || read_output(stderr)));
+    let stdout = read_output(child.stdout.take());
+    let stderr = stderr.join().unwrap_or_default();
+
+    Ok(process::Output {
+        status: child.wait()?,
+        stdout,
+        stderr,
+    })
+}
+
+fn read_output<R: Read>(reader: Option<R>) -> Vec<u8> {
+    let mut output = vec![];
+    let Some(reader) = reader else {
+        return output;
+    };
+
+    for line in BufReader::new(reader).split(b'\n') {
+        let Ok(line) = line else {
+            break;
+        };
+
+        if let Some(progress) = parse_progress(&line) {
+            progress::report(progress);
+        } else {
+            output.extend(line);
+            output.push(b'\n');
+        }
+    }
+
+    output
+}
+
+fn parse_progress(line: &[u8]) -> Option<Progress> {
+    let line = std::str::from_utf8(line).ok()?;
+    let mut fields = line.trim().strip_prefix("[progress] ")?.split(' ');
+    // yt-dlp prints "NA" for unknown values, and estimates can have a fractional part
+    let mut next_number = || {
+        fields
+            .next()
+            .and_then(|x| x.parse::<f64>().ok())
+            .filter(|x| x.is_finite() && *x >= 0.0)
+    };
+
+    let downloaded = next_number()?;
+    let total = next_number().or_else(next_number);
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Some(Progress::Downloading {
+        downloaded: downloaded as u64,
+        total: total.map(|x| x as u64),
+    })
+}
+
+enum YtDlpErrorKind {
+    /// The URL points directly to an image, which yt-dlp does not handle.
+    Image,
+    /// No yt-dlp extractor matches the URL.
+    UnsupportedUrl,
+    Other,
+}
+
+fn classify_error(stderr: &[u8]) -> YtDlpErrorKind {
+    let output = String::from_utf8_lossy(stderr);
     let output = output.trim();
 
     trace!("yt-dlp output: {output}");
 
-    output.ends_with(". Maybe an image?")
+    if output.ends_with(". Maybe an image?") {
+        YtDlpErrorKind::Image
+    } else if output.contains("Unsupported URL: ") {
+        YtDlpErrorKind::UnsupportedUrl
+    } else {
+        YtDlpErrorKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn entry(filepath: PathBuf) -> YtDlpInfo {
+        YtDlpInfo {
+            filepath,
+            id: None,
+            title: None,
+            description: None,
+            uploader: None,
+            uploader_id: None,
+            channel: None,
+        }
+    }
+
+    #[test]
+    fn parses_playlist_entries() {
+        let files = parse_entries(fixture!("yt_dlp/playlist.stdout").as_bytes())
+            .into_iter()
+            .map(DownloadedFile::from)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            files.iter().map(|x| x.path.as_path()).collect::<Vec<_>>(),
+            [
+                Path::new("/tmp/memes/x7Kq2.1789012345678901234.mp4"),
+                Path::new("/tmp/memes/x7Kq2.1789012345678901235.mp4"),
+            ]
+        );
+        assert_eq!(
+            files[0].metadata,
+            PostMetadata {
+                author: Some("Meme Archive".to_string()),
+                title: Some("cat vs printer (1/3)".to_string()),
+                description: None,
+                post_id: Some("1789012345678901234".to_string()),
+                alt_text: None,
+            }
+        );
+        assert_eq!(files[1].metadata.author.as_deref(), Some("memearchive"));
+        assert_eq!(files[1].metadata.description.as_deref(), Some("round 2"));
+    }
+
+    #[test]
+    fn parses_progress_lines() {
+        let progress = fixture!("yt_dlp/progress.stderr")
+            .lines()
+            .map(|x| parse_progress(x.as_bytes()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            progress,
+            [
+                Some(Progress::Downloading {
+                    downloaded: 1_048_576,
+                    total: Some(5_242_880),
+                }),
+                Some(Progress::Downloading {
+                    downloaded: 2048,
+                    total: Some(4096),
+                }),
+                Some(Progress::Downloading {
+                    downloaded: 512,
+                    total: None,
+                }),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_errors() {
+        assert!(matches!(
+            classify_error(fixture!("yt_dlp/image.stderr").as_bytes()),
+            YtDlpErrorKind::Image
+        ));
+        assert!(matches!(
+            classify_error(fixture!("yt_dlp/unsupported.stderr").as_bytes()),
+            YtDlpErrorKind::UnsupportedUrl
+        ));
+        assert!(matches!(
+            classify_error(fixture!("yt_dlp/partial.stderr").as_bytes()),
+            YtDlpErrorKind::Other
+        ));
+    }
+
+    #[test]
+    fn keeps_files_when_some_entries_fail() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let existing = dir.path().join("a.mp4");
+        fs::write(&existing, b"video").expect("Failed to write file");
+
+        let res = downloaded_files(
+            vec![entry(existing.clone()), entry(dir.path().join("b.mp4"))],
+            Some(DownloaderError::extractor(
+                "No video could be found in this tweet",
+            )),
+        );
+
+        let Err(DownloaderError::Partial { files, errors }) = res else {
+            panic!("Expected a partial download, got {res:?}");
+        };
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, existing);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn fails_without_files() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+
+        assert!(matches!(
+            downloaded_files(vec![], None),
+            Err(DownloaderError::Extractor { .. })
+        ));
+        assert!(matches!(
+            downloaded_files(vec![entry(dir.path().join("a.mp4"))], None),
+            Err(DownloaderError::Extractor { .. })
+        ));
+    }
+
+    #[test]
+    fn builds_profile_args() {
+        let profile = YtDlpProfile {
+            url_pattern: "youtube\\.com".to_string(),
+            format: Some("bv*[height<=1080]+ba/b".to_string()),
+            max_filesize: Some("200M".to_string()),
+            embed_subtitles: true,
+            auto_subtitles: true,
+            subtitle_langs: Some(vec!["en.*".to_string(), "de".to_string()]),
+            args: vec!["--no-playlist".to_string()],
+            ..YtDlpProfile::default()
+        };
+
+        assert_eq!(
+            args_for_profile(&profile),
+            [
+                "--format",
+                "bv*[height<=1080]+ba/b",
+                "--max-filesize",
+                "200M",
+                "--sub-langs",
+                "en.*,de",
+                "--write-subs",
+                "--embed-subs",
+                "--write-auto-subs",
+                "--no-playlist",
+            ]
+        );
+        assert_eq!(args_for_profile(&YtDlpProfile::default()), [] as [&str; 0]);
+    }
 }
@@ -0,0 +1,172 @@
+use std::{error, fmt, io, process};
+
+use app_fixers::FixerError;
+
+use crate::DownloadedFile;
+
+type BoxedError = Box<dyn error::Error + Send + Sync>;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DownloaderError {
+    /// Sending a request or reading the response failed.
+    Network(reqwest::Error),
+    /// The URL is not something any downloader can get media from.
+    UnsupportedUrl(String),
+    /// The site responded, but not in the shape the extractor expected.
+    ///
+    /// Usually means the site changed and the downloader needs updating.
+    Extractor {
+        message: String,
+        source: Option<BoxedError>,
+    },
+    /// A required external program is not configured or could not be found.
+    MissingBinary(String),
+    /// `yt-dlp` ran but exited unsuccessfully.
+    YtDlp(process::Output),
+    /// A filesystem operation or spawning a process failed.
+    Io { context: String, source: io::Error },
+    /// Post-processing the downloaded files failed.
+    Fixer(FixerError),
+    /// Several independent downloads failed.
+    Multiple(Vec<Self>),
+    /// Some items of a post failed, but the rest were downloaded.
+    Partial {
+        files: Vec<DownloadedFile>,
+        errors: Vec<Self>,
+    },
+}
+
+impl DownloaderError {
+    pub fn extractor<S>(message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self::Extractor {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Wrap an error from parsing a site response with a description.
+    ///
+    /// Meant to be used with [`Result::map_err`].
+    pub fn extractor_with<S, E>(message: S) -> impl FnOnce(E) -> Self
+    where
+        S: Into<String>,
+        E: Into<BoxedError>,
+    {
+        move |source| Self::Extractor {
+            message: message.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// Wrap an [`io::Error`] with a description of what was being done.
+    ///
+    /// Meant to be used with [`Result::map_err`].
+    pub fn io<S>(context: S) -> impl FnOnce(io::Error) -> Self
+    where
+        S: Into<String>,
+    {
+        move |source| Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Whether retrying the same download later has a reasonable chance of succeeding.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Network(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.is_body()
+                    || e.status()
+                        .is_some_and(|status| status.is_server_error() || status.as_u16() == 429)
+            }
+            Self::Multiple(errors) | Self::Partial { errors, .. } => {
+                errors.iter().any(Self::is_transient)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for DownloaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(e) => write!(f, "Network error: {e}"),
+            Self::UnsupportedUrl(url) => write!(f, "Unsupported URL: {url}"),
+            Self::Extractor {
+                message,
+                source: Some(source),
+            } => write!(f, "{message}: {source}"),
+            Self::Extractor {
+                message,
+                source: None,
+            } => f.write_str(message),
+            Self::MissingBinary(name) => write!(f, "Missing binary: {name}"),
+            Self::YtDlp(o) => write!(
+                f,
+                "yt-dlp exited with status code {}: {}",
+                o.status,
+                String::from_utf8_lossy(&o.stderr).trim()
+            ),
+            Self::Io { context, source } => write!(f, "{context}: {source}"),
+            Self::Fixer(e) => write!(f, "Failed to fix files: {e}"),
+            Self::Multiple(errors) => {
+                f.write_str("Multiple downloads failed: ")?;
+                write_errors(f, errors)
+            }
+            Self::Partial { files, errors } => {
+                write!(
+                    f,
+                    "{} of {} downloads failed: ",
+                    errors.len(),
+                    files.len() + errors.len()
+                )?;
+                write_errors(f, errors)
+            }
+        }
+    }
+}
+
+fn write_errors(f: &mut fmt::Formatter<'_>, errors: &[DownloaderError]) -> fmt::Result {
+    for (i, e) in errors.iter().enumerate() {
+        if i > 0 {
+            f.write_str("; ")?;
+        }
+        write!(f, "{e}")?;
+    }
+
+    Ok(())
+}
+
+impl error::Error for DownloaderError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Network(e) => Some(e),
+            Self::Extractor {
+                source: Some(source),
+                ..
+            } => Some(source.as_ref()),
+            Self::Io { source, .. } => Some(source),
+            Self::Fixer(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for DownloaderError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Network(value)
+    }
+}
+
+impl From<FixerError> for DownloaderError {
+    fn from(value: FixerError) -> Self {
+        Self::Fixer(value)
+    }
+}
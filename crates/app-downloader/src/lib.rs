@@ -1,59 +1,178 @@
-use std::{env, path::PathBuf};
+use std::{
+    io,
+    path::{self, Path, PathBuf},
+    sync::{Arc, PoisonError},
+};
 
-use app_logger::{debug, info};
-use downloaders::{instagram, mastodon, reddit, tumblr, twitter, yt_dlp};
-
-use crate::downloaders::{generic, imgur};
+use app_config::CONFIG;
+pub use app_helpers::metadata::PostMetadata;
+use app_helpers::{
+    layout::{layout_directory, move_file},
+    metadata::Sidecar,
+    template::{render_file_name, TemplateContext, DEFAULT_FILENAME_TEMPLATE},
+};
+use app_logger::{debug, info, warn};
+pub use downloaders::{DownloadedFile, Downloader, DownloaderReturn};
+pub use error::DownloaderError;
+pub use registry::{register_downloader, DownloaderRegistry, DOWNLOADERS};
 
 mod downloaders;
+mod error;
+mod registry;
+
+/// The files of a download, along with the downloader that handled it.
+#[derive(Debug)]
+pub struct Download {
+    pub downloader: &'static str,
+    pub files: Vec<DownloadedFile>,
+    /// Why some items of the post could not be downloaded, if only some of them were.
+    pub errors: Vec<DownloaderError>,
+}
+
+pub fn download_file(url: &str, download_dir: &Path) -> DownloaderReturn {
+    let download = download_file_detailed(url, download_dir)?;
+
+    if download.errors.is_empty() {
+        Ok(download.files)
+    } else {
+        Err(DownloaderError::Partial {
+            files: download.files,
+            errors: download.errors,
+        })
+    }
+}
+
+/// Like [`download_file`], but also says which downloader handled the URL.
+pub fn download_file_detailed(url: &str, download_dir: &Path) -> Result<Download, DownloaderError> {
+    // Only hold the lock while looking, downloads can take any amount of time
+    let downloader = DOWNLOADERS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .find(url);
+
+    download_file_using(downloader, url, download_dir)
+}
+
+pub fn download_file_with(
+    registry: &DownloaderRegistry,
+    url: &str,
+    download_dir: &Path,
+) -> Result<Download, DownloaderError> {
+    download_file_using(registry.find(url), url, download_dir)
+}
 
-pub fn download_file(url: &str, download_dir: &PathBuf) -> Result<Vec<PathBuf>, String> {
+fn download_file_using(
+    downloader: Option<Arc<dyn Downloader>>,
+    url: &str,
+    download_dir: &Path,
+) -> Result<Download, DownloaderError> {
     info!("Downloading {url:?} into {download_dir:?}");
 
-    env::set_current_dir(download_dir).map_err(|e| format!("{e:?}"))?;
+    // Several downloads can run at once, so the working directory of the process is left alone
+    // and downloaders that run programs point them at the download directory instead
+    let download_dir = &path::absolute(download_dir).map_err(DownloaderError::io(format!(
+        "Failed to resolve download directory {download_dir:?}"
+    )))?;
 
-    let new_file_paths = match url {
-        instagram_url if instagram::URL_MATCH.is_match(url) => {
-            debug!("Found URL is instagram url. Downloading all post media.");
-            instagram::download(download_dir, instagram_url)?
-        }
-        twitter_url if twitter::URL_MATCH.is_match(url) => {
-            debug!("Found URL is twitter status. Trying to download post media...");
-            twitter::download(download_dir, twitter_url)?
-        }
-        twitter_media_url if twitter::MEDIA_URL_MATCH.is_match(url) => {
-            debug!("Found URL is twitter media. Downloading...");
-            twitter::download_media_url(download_dir, twitter_media_url)?
-        }
-        mastodon_url if mastodon::is_mastodon_toot(url) => {
-            debug!("Found URL is mastodon toot. Downloading...");
-            mastodon::screenshot_toot(download_dir, mastodon_url)?
-        }
-        tumblr_url if tumblr::URL_MATCH.is_match(url) => {
-            debug!("Found URL is tumblr post. Downloading...");
-            tumblr::download(download_dir, tumblr_url)?
-        }
-        reddit_image_url if reddit::is_reddit_image_url(url) => {
-            debug!("Found URL is reddit image. Downloading...");
-            generic::download(download_dir, reddit_image_url)?
-        }
-        imgur_media_url if imgur::is_imgur_direct_media_url(url) => {
-            debug!("Found URL is imgur direct media. Downloading...");
-            generic::download(download_dir, imgur_media_url)?
-        }
-        imgur_post_url if imgur::is_imgur_url(url) => {
-            debug!("Found URL is imgur post. Downloading...");
-            imgur::download(download_dir, imgur_post_url)?
-        }
-        _ => {
-            debug!("Trying to download with yt-dlp...");
-            yt_dlp::download(download_dir, url)?
+    let downloader = downloader.ok_or_else(|| DownloaderError::UnsupportedUrl(url.to_string()))?;
+
+    debug!(
+        "Found URL is handled by the {name:?} downloader. Downloading...",
+        name = downloader.name()
+    );
+
+    // Keep whatever was downloaded if only some items of a post failed
+    let (files, errors) = match downloader.download(download_dir, url) {
+        Ok(files) => (files, vec![]),
+        Err(DownloaderError::Partial { files, errors }) => {
+            for e in &errors {
+                warn!("Failed to download part of {url:?}: {e}");
+            }
+            (files, errors)
         }
+        Err(e) => return Err(e),
     };
 
-    debug!("Downloaded files: {:?}", &new_file_paths);
+    debug!("Downloaded files: {:?}", &files);
+
+    let fixed =
+        app_fixers::fix_files_detailed(&files.iter().map(|x| x.path.clone()).collect::<Vec<_>>())?;
+
+    let is_gallery = files.len() > 1;
+    let files = files
+        .into_iter()
+        .zip(fixed)
+        .enumerate()
+        .map(|(i, (file, mut fixed))| {
+            let context = TemplateContext::new(url, &file.metadata)
+                .with_index(is_gallery.then_some(i + 1))
+                .with_name(file.name.as_deref());
+            match move_to_template(&fixed.path, &context) {
+                Ok(path) => fixed.path = path,
+                Err(e) => warn!(
+                    "Failed to move {:?} according to the templates: {e:?}",
+                    fixed.path
+                ),
+            }
+
+            if CONFIG.app.embed_metadata {
+                if let Err(e) =
+                    app_fixers::embed_metadata::embed_metadata(&fixed.path, url, &file.metadata)
+                {
+                    warn!("Failed to embed metadata into {:?}: {e:?}", fixed.path);
+                }
+            }
+
+            if CONFIG.app.sidecar_metadata {
+                let mut sidecar = Sidecar::new(url, downloader.name(), file.metadata.clone());
+                sidecar.fixers = fixed
+                    .applied_fixers
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+
+                match sidecar.write(&fixed.path) {
+                    Ok(sidecar_path) => debug!("Wrote sidecar file {sidecar_path:?}"),
+                    Err(e) => warn!("Failed to write sidecar for {:?}: {e:?}", fixed.path),
+                }
+            }
+
+            DownloadedFile {
+                path: fixed.path,
+                metadata: file.metadata,
+                name: file.name,
+            }
+        })
+        .collect();
+
+    Ok(Download {
+        downloader: downloader.name(),
+        files,
+        errors,
+    })
+}
+
+/// Move a downloaded file to where the configured directory template says it belongs
+/// inside the download directory, and rename it according to the filename template.
+fn move_to_template(path: &Path, context: &TemplateContext) -> io::Result<PathBuf> {
+    let template = CONFIG
+        .app
+        .filename_template
+        .as_deref()
+        .unwrap_or(DEFAULT_FILENAME_TEMPLATE);
+    let extension = path
+        .extension()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let file_name = render_file_name(template, context, &extension);
+    let download_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let dir = download_dir.join(layout_directory(context));
 
-    let new_file_paths = app_fixers::fix_files(&new_file_paths)?;
+    let new_path = move_file(path, &dir, &file_name)?;
+    if new_path != path {
+        debug!("Moved {path:?} to {new_path:?}");
+    }
 
-    Ok(new_file_paths)
+    Ok(new_path)
 }
@@ -0,0 +1,212 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+use once_cell::sync::Lazy;
+
+use crate::downloaders::{
+    bluesky::BlueskyDownloader,
+    imgur::{ImgurMediaDownloader, ImgurPostDownloader},
+    instagram::InstagramDownloader,
+    mastodon::MastodonDownloader,
+    reddit::RedditDownloader,
+    tumblr::TumblrDownloader,
+    twitter::{TwitterDownloader, TwitterMediaDownloader},
+    yt_dlp::YtDlpDownloader,
+    Downloader,
+};
+
+/// The registry used by [`download_file`](crate::download_file).
+///
+/// Starts out with the built-in downloaders.
+/// Use [`register_downloader`] to add your own.
+pub static DOWNLOADERS: Lazy<RwLock<DownloaderRegistry>> =
+    Lazy::new(|| RwLock::new(DownloaderRegistry::default()));
+
+/// Add a downloader to the global [`DOWNLOADERS`] registry.
+pub fn register_downloader<D>(downloader: D)
+where
+    D: Downloader + 'static,
+{
+    DOWNLOADERS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .register(downloader);
+}
+
+/// An ordered collection of [`Downloader`]s.
+///
+/// Downloaders are kept sorted by descending [`Downloader::priority`].
+/// Downloaders with the same priority keep their registration order.
+pub struct DownloaderRegistry {
+    downloaders: Vec<Arc<dyn Downloader>>,
+}
+
+impl DownloaderRegistry {
+    /// A registry without any downloaders.
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self {
+            downloaders: vec![],
+        }
+    }
+
+    /// Add a downloader, placing it after all downloaders
+    /// with the same or higher priority.
+    pub fn register<D>(&mut self, downloader: D) -> &mut Self
+    where
+        D: Downloader + 'static,
+    {
+        let priority = downloader.priority();
+        let position = self
+            .downloaders
+            .iter()
+            .position(|x| x.priority() < priority)
+            .unwrap_or(self.downloaders.len());
+
+        self.downloaders.insert(position, Arc::new(downloader));
+
+        self
+    }
+
+    /// All registered downloaders in the order they are tried.
+    pub fn downloaders(&self) -> impl Iterator<Item = &dyn Downloader> {
+        self.downloaders.iter().map(AsRef::as_ref)
+    }
+
+    /// The first downloader that claims the URL.
+    ///
+    /// The downloader is shared, so it can be used after a lock
+    /// around the registry has been released.
+    #[must_use]
+    pub fn find(&self, url: &str) -> Option<Arc<dyn Downloader>> {
+        self.downloaders
+            .iter()
+            .find(|x| x.can_download(url))
+            .map(Arc::clone)
+    }
+}
+
+impl Default for DownloaderRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+
+        registry
+            .register(InstagramDownloader)
+            .register(TwitterDownloader)
+            .register(TwitterMediaDownloader)
+            .register(MastodonDownloader)
+            .register(BlueskyDownloader)
+            .register(TumblrDownloader)
+            .register(RedditDownloader)
+            .register(ImgurMediaDownloader)
+            .register(ImgurPostDownloader)
+            .register(YtDlpDownloader);
+
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::DownloaderReturn;
+
+    struct TestDownloader {
+        name: &'static str,
+        priority: i32,
+        prefix: &'static str,
+    }
+
+    impl Downloader for TestDownloader {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn can_download(&self, url: &str) -> bool {
+            url.starts_with(self.prefix)
+        }
+
+        fn priority(&self) -> i32 {
+            self.priority
+        }
+
+        fn download(&self, _download_dir: &Path, _url: &str) -> DownloaderReturn {
+            Ok(vec![])
+        }
+    }
+
+    fn registry(downloaders: Vec<TestDownloader>) -> DownloaderRegistry {
+        let mut registry = DownloaderRegistry::empty();
+        for downloader in downloaders {
+            registry.register(downloader);
+        }
+
+        registry
+    }
+
+    #[test]
+    fn find_prefers_higher_priority() {
+        let registry = registry(vec![
+            TestDownloader {
+                name: "catch-all",
+                priority: i32::MIN,
+                prefix: "",
+            },
+            TestDownloader {
+                name: "low",
+                priority: 10,
+                prefix: "https://",
+            },
+            TestDownloader {
+                name: "high",
+                priority: 100,
+                prefix: "https://example.com/",
+            },
+        ]);
+
+        let name = |url| registry.find(url).map(|x| x.name());
+        assert_eq!(name("https://example.com/post"), Some("high"));
+        assert_eq!(name("https://other.com/post"), Some("low"));
+        assert_eq!(name("ftp://example.com/post"), Some("catch-all"));
+    }
+
+    #[test]
+    fn find_keeps_registration_order_for_equal_priority() {
+        let registry = registry(vec![
+            TestDownloader {
+                name: "first",
+                priority: 10,
+                prefix: "https://",
+            },
+            TestDownloader {
+                name: "second",
+                priority: 10,
+                prefix: "https://",
+            },
+        ]);
+
+        assert_eq!(
+            registry
+                .downloaders()
+                .map(Downloader::name)
+                .collect::<Vec<_>>(),
+            ["first", "second"]
+        );
+        assert_eq!(
+            registry.find("https://example.com").map(|x| x.name()),
+            Some("first")
+        );
+    }
+
+    #[test]
+    fn find_returns_none_without_match() {
+        let registry = registry(vec![TestDownloader {
+            name: "https",
+            priority: 0,
+            prefix: "https://",
+        }]);
+
+        assert!(registry.find("http://example.com").is_none());
+    }
+}
@@ -1,20 +1,20 @@
-use std::{ffi::OsStr, fmt::Display, path::PathBuf, process};
+use std::{ffi::OsStr, fmt::Display, fs, path::PathBuf, process};
 
 use app_config::CONFIGURATION;
 use app_helpers::{ffprobe, results::option_contains, trash::move_to_trash};
 use app_logger::{debug, trace};
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
-use super::FixerReturn;
-use crate::util::transfer_file_times;
+use super::{FixerError, FixerReturn};
+use crate::util::{ffmpeg_output_with_progress, transfer_file_times};
 
 pub fn auto_crop_video(file_path: &PathBuf) -> FixerReturn {
     debug!("Auto cropping video {file_path:?}");
 
-    let file_path_str = file_path
-        .to_str()
-        .ok_or_else(|| format!("Failed to convert {file_path:?} to string"))?;
-    let media_info = ffprobe::ffprobe(file_path).map_err(|e| format!("{e:?}"))?;
+    let file_path_str = file_path.to_str().ok_or_else(|| {
+        FixerError::UnsupportedMedia(format!("Failed to convert {file_path:?} to string"))
+    })?;
+    let media_info = ffprobe::ffprobe(file_path)?;
     let video_stream = media_info
         .streams
         .iter()
@@ -33,9 +33,9 @@ pub fn auto_crop_video(file_path: &PathBuf) -> FixerReturn {
             trace!("Video width: {w}, height: {h}");
             (w, h)
         } else {
-            return Err(format!(
+            return Err(FixerError::UnsupportedMedia(format!(
                 "Failed to get video width and height for {file_path:?}"
-            ));
+            )));
         }
     };
 
@@ -54,8 +54,9 @@ pub fn auto_crop_video(file_path: &PathBuf) -> FixerReturn {
         }
     };
 
-    let final_crop_filter = CropFilter::intersect_all(crop_filters)
-        .ok_or_else(|| "Failed to intersect crop filters".to_string())?;
+    let final_crop_filter = CropFilter::intersect_all(crop_filters).ok_or_else(|| {
+        FixerError::UnexpectedOutput("Failed to intersect crop filters".to_string())
+    })?;
 
     debug!("Final crop filter: {final_crop_filter:?}");
 
@@ -68,20 +69,29 @@ pub fn auto_crop_video(file_path: &PathBuf) -> FixerReturn {
         let file_name = file_path
             .file_stem()
             .and_then(OsStr::to_str)
-            .ok_or_else(|| format!("Failed to get file stem from {file_path:?}"))?;
+            .ok_or_else(|| {
+                FixerError::UnsupportedMedia(format!("Failed to get file stem from {file_path:?}"))
+            })?;
 
         let file_extension = file_path
             .extension()
             .and_then(OsStr::to_str)
-            .ok_or_else(|| format!("Failed to get file extension from {file_path:?}"))?;
-
-        file_path.with_file_name(format!("{file_name}.ac.{file_extension}"))
+            .ok_or_else(|| {
+                FixerError::UnsupportedMedia(format!(
+                    "Failed to get file extension from {file_path:?}"
+                ))
+            })?;
+
+        // Hidden so it isn't picked up while it's being written.
+        // It replaces the original once cropped so the file keeps its name.
+        file_path.with_file_name(format!(".{file_name}.ac.{file_extension}"))
     };
 
     let mut cmd = process::Command::new(&CONFIGURATION.ffmpeg_path);
     let cmd = cmd
         .arg("-y")
         .args(["-loglevel", "panic"])
+        .args(["-progress", "pipe:1", "-nostats"])
         .args(["-i", file_path_str])
         .args(["-vf", &final_crop_filter.to_string()])
         .args(["-map_metadata", "0", "-movflags", "use_metadata_tags"])
@@ -89,27 +99,25 @@ pub fn auto_crop_video(file_path: &PathBuf) -> FixerReturn {
         .arg(&new_filename);
     debug!("Running command {cmd:?}");
 
-    let cmd_output = cmd
-        .output()
-        .map_err(|e| format!("Failed to run command {cmd:?}: {e:?}"))?;
+    let cmd_output = ffmpeg_output_with_progress(cmd, file_path)
+        .map_err(FixerError::io(format!("Failed to run command {cmd:?}")))?;
 
     if !cmd_output.status.success() {
-        let stderr = String::from_utf8(cmd_output.stderr)
-            .map_err(|e| format!("Failed to convert command output to UTF-8: {e:?}"))?;
-
-        return Err(format!(
-            "Command {cmd:?} failed with status {status:?} and output {output:?}",
-            cmd = cmd,
-            status = cmd_output.status,
-            output = stderr
-        ));
+        let _ = fs::remove_file(&new_filename);
+        return Err(FixerError::Ffmpeg(cmd_output));
     }
 
     transfer_file_times(file_path, &new_filename)?;
 
-    move_to_trash(file_path).map_err(|e| format!("Failed to move file to trash: {e:?}"))?;
+    move_to_trash(file_path).map_err(FixerError::io(format!(
+        "Failed to move {file_path:?} to trash"
+    )))?;
+
+    fs::rename(&new_filename, file_path).map_err(FixerError::io(format!(
+        "Failed to rename {new_filename:?} to {file_path:?}"
+    )))?;
 
-    Ok(new_filename)
+    Ok(file_path.clone())
 }
 
 #[derive(Debug, Clone)]
@@ -196,7 +204,7 @@ enum BorderColor {
 fn get_crop_filter(
     file_path: &str,
     border_color: &BorderColor,
-) -> Result<Option<CropFilter>, String> {
+) -> Result<Option<CropFilter>, FixerError> {
     let cropdetect_filter = {
         let mut filters = vec![];
 
@@ -224,19 +232,25 @@ fn get_crop_filter(
 
     let cmd_output = cmd
         .output()
-        .map_err(|e| format!("Failed to run command {cmd:?}: {e:?}"))?;
+        .map_err(FixerError::io(format!("Failed to run command {cmd:?}")))?;
     trace!("Command output: {:?}", &cmd_output);
-    let stderr = String::from_utf8(cmd_output.stderr)
-        .map_err(|e| format!("Failed to convert command output to UTF-8: {e:?}"))?;
+    if !cmd_output.status.success() {
+        return Err(FixerError::Ffmpeg(cmd_output));
+    }
+    let stderr = String::from_utf8(cmd_output.stderr).map_err(|e| {
+        FixerError::UnexpectedOutput(format!("Failed to convert command output to UTF-8: {e:?}"))
+    })?;
 
     let mut res = stderr
         .split('\n')
         .filter(|s| s.starts_with("[Parsed_cropdetect") && s.contains("crop="))
         .map(str::trim)
         .map(|s| {
-            s.split("crop=")
-                .nth(1)
-                .ok_or_else(|| format!("Failed to parse cropdetect output from {s:?}"))
+            s.split("crop=").nth(1).ok_or_else(|| {
+                FixerError::UnexpectedOutput(format!(
+                    "Failed to parse cropdetect output from {s:?}"
+                ))
+            })
         })
         .collect::<Result<Vec<_>, _>>()?;
 
@@ -250,7 +264,9 @@ fn get_crop_filter(
             let mut next_s = || {
                 s.next()
                     .and_then(|x| x.to_string().parse::<i64>().ok())
-                    .ok_or_else(|| format!("Failed to parse width from {s:?}"))
+                    .ok_or_else(|| {
+                        FixerError::UnexpectedOutput(format!("Failed to parse width from {s:?}"))
+                    })
             };
 
             Ok(CropFilter {
@@ -260,7 +276,7 @@ fn get_crop_filter(
                 y: next_s()?,
             })
         })
-        .collect::<Result<Vec<_>, String>>()?;
+        .collect::<Result<Vec<_>, FixerError>>()?;
 
     Ok(CropFilter::union_all(res))
 }
@@ -0,0 +1,360 @@
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use app_config::CONFIGURATION;
+use app_helpers::{id::time_thread_id, metadata::PostMetadata};
+use app_logger::{debug, trace};
+
+use crate::{util::transferable_file_times, FixerError, FixerReturn};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_SOI: &[u8] = &[0xFF, 0xD8];
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Longest payload a JPEG segment can hold, minus the XMP namespace header.
+const MAX_XMP_PACKET_LENGTH: usize = 65533 - XMP_NAMESPACE.len();
+
+struct Tags<'a> {
+    source_url: &'a str,
+    title: Option<&'a str>,
+    author: Option<&'a str>,
+    description: Option<&'a str>,
+    alt_text: Option<&'a str>,
+}
+
+/// Write the source URL, title, author, caption and alt text of a post into the file itself.
+///
+/// Videos get container tags through `ffmpeg`, PNGs get `iTXt` chunks
+/// and JPEGs get an XMP packet. Other formats are left alone.
+pub fn embed_metadata(file_path: &Path, source_url: &str, post: &PostMetadata) -> FixerReturn {
+    debug!("Embedding metadata into {file_path:?}");
+
+    let tags = Tags {
+        source_url,
+        title: post.title.as_deref(),
+        author: post.author.as_deref(),
+        description: post.description.as_deref(),
+        alt_text: post.alt_text.as_deref(),
+    };
+
+    let mime_type = infer::get_from_path(file_path)
+        .map_err(FixerError::io(format!("Failed to read {file_path:?}")))?
+        .map(|x| x.mime_type());
+    trace!("Mime type of {file_path:?}: {mime_type:?}");
+
+    match mime_type {
+        Some("image/png") => rewrite_file(file_path, |bytes| embed_png(bytes, &tags))?,
+        Some("image/jpeg") => rewrite_file(file_path, |bytes| embed_jpeg(bytes, &tags))?,
+        Some(x) if x.starts_with("video/") => embed_video(file_path, &tags)?,
+        _ => {
+            debug!("Don't know how to embed metadata into {file_path:?}, skipping");
+        }
+    }
+
+    Ok(file_path.to_path_buf())
+}
+
+fn embed_video(file_path: &Path, tags: &Tags) -> Result<(), FixerError> {
+    let tmp_path = tmp_path_for(file_path);
+
+    let mut cmd = process::Command::new(&CONFIGURATION.ffmpeg_path);
+    cmd.arg("-y")
+        .args(["-loglevel", "error"])
+        .arg("-i")
+        .arg(file_path)
+        .args(["-map", "0", "-c", "copy", "-map_metadata", "0"])
+        .args(["-metadata", &format!("comment={}", tags.source_url)]);
+
+    for (key, value) in [
+        ("title", tags.title),
+        ("artist", tags.author),
+        ("description", tags.description),
+    ] {
+        if let Some(value) = value {
+            cmd.args(["-metadata", &format!("{key}={value}")]);
+        }
+    }
+
+    cmd.arg(&tmp_path);
+    debug!("Running `ffmpeg' command: {cmd:?}");
+
+    let output = cmd
+        .output()
+        .map_err(FixerError::io(format!("Failed to run command {cmd:?}")))?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(FixerError::Ffmpeg(output));
+    }
+
+    replace_file(file_path, &tmp_path)
+}
+
+fn embed_png(bytes: &[u8], tags: &Tags) -> Result<Vec<u8>, FixerError> {
+    let not_png = || FixerError::UnsupportedMedia("File is not a valid PNG".to_string());
+
+    let mut out = Vec::with_capacity(bytes.len() + 1024);
+    out.extend_from_slice(bytes.get(..PNG_SIGNATURE.len()).ok_or_else(not_png)?);
+    if out != PNG_SIGNATURE {
+        return Err(not_png());
+    }
+
+    let mut rest = &bytes[PNG_SIGNATURE.len()..];
+    while !rest.is_empty() {
+        let length = rest
+            .get(..4)
+            .and_then(|x| x.try_into().ok())
+            .map(u32::from_be_bytes)
+            .ok_or_else(not_png)? as usize;
+        let chunk = rest.get(..12 + length).ok_or_else(not_png)?;
+        let chunk_type = &chunk[4..8];
+
+        if chunk_type == b"IEND" {
+            for (keyword, value) in [
+                ("Source", Some(tags.source_url)),
+                ("Title", tags.title),
+                ("Author", tags.author),
+                ("Description", tags.description),
+                ("Alt Text", tags.alt_text),
+            ] {
+                if let Some(value) = value {
+                    write_png_itxt(&mut out, keyword, value);
+                }
+            }
+        }
+
+        out.extend_from_slice(chunk);
+        rest = &rest[chunk.len()..];
+    }
+
+    Ok(out)
+}
+
+fn write_png_itxt(out: &mut Vec<u8>, keyword: &str, text: &str) {
+    // Keyword, no compression, empty language tag and translated keyword
+    let mut data = Vec::with_capacity(keyword.len() + text.len() + 5);
+    data.extend_from_slice(keyword.as_bytes());
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(b"iTXt");
+    crc.update(&data);
+
+    out.extend_from_slice(&u32::try_from(data.len()).unwrap_or(u32::MAX).to_be_bytes());
+    out.extend_from_slice(b"iTXt");
+    out.extend_from_slice(&data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+fn embed_jpeg(bytes: &[u8], tags: &Tags) -> Result<Vec<u8>, FixerError> {
+    let not_jpeg = || FixerError::UnsupportedMedia("File is not a valid JPEG".to_string());
+
+    if !bytes.starts_with(JPEG_SOI) {
+        return Err(not_jpeg());
+    }
+
+    let mut out = Vec::with_capacity(bytes.len() + 4096);
+    out.extend_from_slice(JPEG_SOI);
+
+    // Keep the leading APPn segments (JFIF, EXIF, ...) in place, except old XMP packets
+    let mut rest = &bytes[JPEG_SOI.len()..];
+    while let [0xFF, marker @ 0xE0..=0xEF, ..] = rest {
+        // The length includes its own two bytes
+        let length = rest
+            .get(2..4)
+            .and_then(|x| x.try_into().ok())
+            .map(u16::from_be_bytes)
+            .filter(|x| *x >= 2)
+            .ok_or_else(not_jpeg)? as usize;
+        let segment = rest.get(..2 + length).ok_or_else(not_jpeg)?;
+
+        let is_xmp = *marker == 0xE1
+            && segment
+                .get(4..)
+                .is_some_and(|x| x.starts_with(XMP_NAMESPACE));
+        if !is_xmp {
+            out.extend_from_slice(segment);
+        }
+
+        rest = &rest[segment.len()..];
+    }
+
+    let packet = xmp_packet(tags);
+    let segment_length = u16::try_from(2 + XMP_NAMESPACE.len() + packet.len())
+        .map_err(|_| FixerError::UnsupportedMedia("Metadata is too long".to_string()))?;
+    out.extend_from_slice(&[0xFF, 0xE1]);
+    out.extend_from_slice(&segment_length.to_be_bytes());
+    out.extend_from_slice(XMP_NAMESPACE);
+    out.extend_from_slice(packet.as_bytes());
+
+    out.extend_from_slice(rest);
+
+    Ok(out)
+}
+
+fn xmp_packet(tags: &Tags) -> String {
+    let mut description = String::new();
+
+    let _ = write!(
+        description,
+        "<dc:source>{}</dc:source>",
+        xml_escape(tags.source_url)
+    );
+    if let Some(title) = tags.title {
+        let _ = write!(
+            description,
+            "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>",
+            xml_escape(title)
+        );
+    }
+    if let Some(author) = tags.author {
+        let _ = write!(
+            description,
+            "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+            xml_escape(author)
+        );
+    }
+    if let Some(alt_text) = tags.alt_text {
+        let _ = write!(
+            description,
+            "<Iptc4xmpCore:AltTextAccessibility><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></Iptc4xmpCore:AltTextAccessibility>",
+            xml_escape(alt_text)
+        );
+    }
+
+    let wrap = |inner: &str| {
+        format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+             <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+             xmlns:Iptc4xmpCore=\"http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/\">\
+             {inner}\
+             </rdf:Description>\
+             </rdf:RDF>\
+             </x:xmpmeta>\
+             <?xpacket end=\"w\"?>"
+        )
+    };
+
+    // The caption is the only field that can get long, so it gets whatever space is left
+    if let Some(caption) = tags.description {
+        let space_left = MAX_XMP_PACKET_LENGTH.saturating_sub(wrap(&description).len() + 128);
+        let mut caption = xml_escape(caption);
+        if caption.len() > space_left {
+            let mut end = space_left;
+            while !caption.is_char_boundary(end) {
+                end -= 1;
+            }
+            caption.truncate(end);
+            // Don't leave half an escape sequence behind
+            if let Some(amp) = caption.rfind('&').filter(|x| !caption[*x..].contains(';')) {
+                caption.truncate(amp);
+            }
+        }
+
+        let _ = write!(
+            description,
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{caption}</rdf:li></rdf:Alt></dc:description>"
+        );
+    }
+
+    wrap(&description)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rewrite_file<F>(file_path: &Path, rewrite: F) -> Result<(), FixerError>
+where
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, FixerError>,
+{
+    let bytes =
+        fs::read(file_path).map_err(FixerError::io(format!("Failed to read {file_path:?}")))?;
+    let new_bytes = rewrite(&bytes)?;
+
+    let tmp_path = tmp_path_for(file_path);
+    fs::write(&tmp_path, new_bytes)
+        .map_err(FixerError::io(format!("Failed to write {tmp_path:?}")))?;
+
+    replace_file(file_path, &tmp_path)
+}
+
+/// Move `tmp_path` over `file_path`, keeping the file times of the original.
+fn replace_file(file_path: &Path, tmp_path: &Path) -> Result<(), FixerError> {
+    let transfer_file_times = transferable_file_times(file_path.to_path_buf())?;
+
+    fs::rename(tmp_path, file_path).map_err(FixerError::io(format!(
+        "Failed to rename {tmp_path:?} to {file_path:?}"
+    )))?;
+
+    if let Err(e) = transfer_file_times(file_path) {
+        debug!("Failed to transfer file times: {e:?}");
+    }
+
+    Ok(())
+}
+
+fn tmp_path_for(file_path: &Path) -> PathBuf {
+    let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+
+    file_path.with_file_name(format!(".{id}.{file_name}", id = time_thread_id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAGS: Tags = Tags {
+        source_url: "https://example.com/post",
+        title: None,
+        author: None,
+        description: None,
+        alt_text: None,
+    };
+
+    #[test]
+    fn embed_jpeg_rejects_short_segment_lengths() {
+        for length in [0, 1] {
+            let bytes = [0xFF, 0xD8, 0xFF, 0xE1, 0x00, length, 0xFF, 0xD9];
+
+            assert!(matches!(
+                embed_jpeg(&bytes, &TAGS),
+                Err(FixerError::UnsupportedMedia(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn embed_jpeg_replaces_old_xmp_packet() {
+        let mut old_xmp = vec![0xFF, 0xE1];
+        old_xmp.extend_from_slice(
+            &u16::try_from(2 + XMP_NAMESPACE.len() + 3)
+                .expect("Segment is too long")
+                .to_be_bytes(),
+        );
+        old_xmp.extend_from_slice(XMP_NAMESPACE);
+        old_xmp.extend_from_slice(b"old");
+
+        let mut bytes = JPEG_SOI.to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x04, 0x4A, 0x46]);
+        bytes.extend_from_slice(&old_xmp);
+        bytes.extend_from_slice(&[0xFF, 0xD9]);
+
+        let out = embed_jpeg(&bytes, &TAGS).expect("Failed to embed metadata");
+
+        assert!(out.starts_with(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x4A, 0x46, 0xFF, 0xE1]));
+        assert!(out.ends_with(&[0xFF, 0xD9]));
+        assert!(!out.windows(old_xmp.len()).any(|x| x == old_xmp));
+        assert!(out
+            .windows(TAGS.source_url.len())
+            .any(|x| x == TAGS.source_url.as_bytes()));
+    }
+}
@@ -0,0 +1,89 @@
+use std::{error, fmt, io, process};
+
+use app_helpers::ffprobe::FfProbeError;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FixerError {
+    /// A required external program is not configured or could not be found.
+    MissingBinary(String),
+    /// `ffprobe` could not analyse the file.
+    FfProbe(FfProbeError),
+    /// `ffmpeg` ran but exited unsuccessfully.
+    Ffmpeg(process::Output),
+    /// `scenedetect` ran but exited unsuccessfully.
+    Scenedetect(process::Output),
+    /// An external program produced output we could not make sense of.
+    UnexpectedOutput(String),
+    /// The file has a format, codec or shape that no fixer knows how to handle.
+    UnsupportedMedia(String),
+    /// Decoding an image failed.
+    Image(image::ImageError),
+    /// A filesystem operation or spawning a process failed.
+    Io { context: String, source: io::Error },
+}
+
+impl FixerError {
+    /// Wrap an [`io::Error`] with a description of what was being done.
+    ///
+    /// Meant to be used with [`Result::map_err`].
+    pub fn io<S>(context: S) -> impl FnOnce(io::Error) -> Self
+    where
+        S: Into<String>,
+    {
+        move |source| Self::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for FixerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingBinary(name) => write!(f, "Missing binary: {name}"),
+            Self::FfProbe(e) => write!(f, "ffprobe failed: {e}"),
+            Self::Ffmpeg(o) => write!(
+                f,
+                "ffmpeg exited with status code {}: {}",
+                o.status,
+                String::from_utf8_lossy(&o.stderr).trim()
+            ),
+            Self::Scenedetect(o) => write!(
+                f,
+                "scenedetect exited with status code {}: {}",
+                o.status,
+                String::from_utf8_lossy(&o.stderr).trim()
+            ),
+            Self::UnexpectedOutput(msg) | Self::UnsupportedMedia(msg) => f.write_str(msg),
+            Self::Image(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Io { context, source } => write!(f, "{context}: {source}"),
+        }
+    }
+}
+
+impl error::Error for FixerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::FfProbe(e) => Some(e),
+            Self::Image(e) => Some(e),
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<FfProbeError> for FixerError {
+    fn from(value: FfProbeError) -> Self {
+        match value {
+            FfProbeError::MissingBinary(name) => Self::MissingBinary(name),
+            e => Self::FfProbe(e),
+        }
+    }
+}
+
+impl From<image::ImageError> for FixerError {
+    fn from(value: image::ImageError) -> Self {
+        Self::Image(value)
+    }
+}
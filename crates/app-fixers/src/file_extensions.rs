@@ -2,7 +2,7 @@ use std::{fs, path::PathBuf};
 
 use app_logger::{debug, trace};
 
-use super::FixerReturn;
+use super::{FixerError, FixerReturn};
 
 pub fn fix_file_extension(file_path: &PathBuf) -> FixerReturn {
     debug!("Checking file extension for {file_path:?}...");
@@ -11,8 +11,17 @@ pub fn fix_file_extension(file_path: &PathBuf) -> FixerReturn {
 
     let file_ext = match infer::get_from_path(file_path) {
         Ok(Some(ext)) => ext.extension(),
-        _ => {
-            return Err(format!("Failed to get extension for file {:?}", &file_path));
+        Ok(None) => {
+            return Err(FixerError::UnsupportedMedia(format!(
+                "Failed to get extension for file {:?}",
+                &file_path
+            )));
+        }
+        Err(e) => {
+            return Err(FixerError::Io {
+                context: format!("Failed to read {:?}", &file_path),
+                source: e,
+            });
         }
     };
     debug!("Inferred file extension: {:?}", file_ext);
@@ -33,8 +42,9 @@ pub fn fix_file_extension(file_path: &PathBuf) -> FixerReturn {
     let new_file_path = file_path.with_extension(file_ext);
 
     debug!("Renaming file from {file_path:?} to {new_file_path:?}");
-    match fs::rename(file_path, &new_file_path) {
-        Ok(()) => Ok(new_file_path),
-        Err(e) => Err(format!("Failed to rename file: {e:?}")),
-    }
+    fs::rename(file_path, &new_file_path).map_err(FixerError::io(format!(
+        "Failed to rename {file_path:?} to {new_file_path:?}"
+    )))?;
+
+    Ok(new_file_path)
 }
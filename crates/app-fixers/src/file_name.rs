@@ -2,7 +2,7 @@ use std::{fs, path::PathBuf};
 
 use app_logger::{debug, trace};
 
-use super::FixerReturn;
+use super::{FixerError, FixerReturn};
 
 pub fn fix_file_name(file_path: &PathBuf) -> FixerReturn {
     debug!("Checking file name for {file_path:?}...");
@@ -14,7 +14,10 @@ pub fn fix_file_name(file_path: &PathBuf) -> FixerReturn {
             name.replace(|c: char| !c.is_ascii(), "")
         }
         None => {
-            return Err(format!("Failed to get name for file {:?}", &file_path));
+            return Err(FixerError::UnsupportedMedia(format!(
+                "Failed to get name for file {:?}",
+                &file_path
+            )));
         }
         Some(name) => {
             debug!("File name for {name:?} is OK. Skipping...");
@@ -26,10 +29,10 @@ pub fn fix_file_name(file_path: &PathBuf) -> FixerReturn {
         .extension()
         .and_then(|x| return x.to_str())
         .ok_or_else(|| {
-            format!(
+            FixerError::UnsupportedMedia(format!(
                 "Failed to get extension for file {:?}",
                 &file_path.file_name()
-            )
+            ))
         })?;
 
     trace!("New file name: {new_name:?} (extension: {extension:?}) for file {file_path:?}");
@@ -39,8 +42,9 @@ pub fn fix_file_name(file_path: &PathBuf) -> FixerReturn {
 
     debug!("Renaming file from {file_path:?} to {new_file_path:?}");
 
-    match fs::rename(file_path, &new_file_path) {
-        Ok(()) => Ok(new_file_path),
-        Err(e) => Err(format!("Failed to rename file: {e:?}")),
-    }
+    fs::rename(file_path, &new_file_path).map_err(FixerError::io(format!(
+        "Failed to rename {file_path:?} to {new_file_path:?}"
+    )))?;
+
+    Ok(new_file_path)
 }
@@ -1,39 +1,82 @@
 #[macro_use(defer)]
 extern crate scopeguard;
 
-use std::path::PathBuf;
+use std::{fs, path::PathBuf};
 
+use app_helpers::progress::{self, Progress};
+pub use error::FixerError;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use resolve_path::PathResolveExt;
 
 pub mod crop;
+pub mod embed_metadata;
+mod error;
 pub mod file_extensions;
 pub mod file_name;
 pub mod media_formats;
 pub mod split_scenes;
 mod util;
 
-pub fn fix_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
-    let fixers: Vec<Fixer> = vec![
-        file_extensions::fix_file_extension,
-        file_name::fix_file_name,
+/// A file after it went through [`fix_files_detailed`].
+#[derive(Debug, Clone)]
+pub struct FixedFile {
+    pub path: PathBuf,
+    /// Names of the fixers that renamed or changed the file, in the order they ran.
+    pub applied_fixers: Vec<&'static str>,
+}
+
+const FIXERS: &[(&str, Fixer)] = &[
+    ("file-extension", file_extensions::fix_file_extension),
+    ("file-name", file_name::fix_file_name),
+    (
+        "media-format",
         media_formats::convert_into_preferred_formats,
-        crop::auto_crop_video,
-    ];
+    ),
+    ("crop", crop::auto_crop_video),
+];
+
+pub fn fix_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, FixerError> {
+    fix_files_detailed(paths).map(|x| x.into_iter().map(|x| x.path).collect())
+}
+
+/// Like [`fix_files`], but also report which fixers did something to each file.
+///
+/// A fixer counts as applied if it produced a different path or changed the file size.
+pub fn fix_files_detailed(paths: &[PathBuf]) -> Result<Vec<FixedFile>, FixerError> {
+    let sink = progress::current();
 
     paths
         .par_iter()
         .map(|path| {
-            let mut p = path.resolve().canonicalize().map_err(|e| {
-                format!("Failed to canonicalize {path:?}: {e:?}", path = path, e = e)
-            })?;
-            for filter in &fixers {
-                p = filter(&p)?;
-            }
-            Ok(p)
+            progress::with_progress(sink.clone(), || {
+                let mut p = path
+                    .resolve()
+                    .canonicalize()
+                    .map_err(FixerError::io(format!("Failed to canonicalize {path:?}")))?;
+                let mut applied_fixers = vec![];
+                for (name, filter) in FIXERS {
+                    progress::report(Progress::Fixing {
+                        file: p.clone(),
+                        fixer: name,
+                    });
+
+                    let size_before = fs::metadata(&p).map(|x| x.len()).ok();
+                    let new_p = filter(&p)?;
+                    let size_after = fs::metadata(&new_p).map(|x| x.len()).ok();
+
+                    if new_p != p || size_before != size_after {
+                        applied_fixers.push(*name);
+                    }
+                    p = new_p;
+                }
+                Ok(FixedFile {
+                    path: p,
+                    applied_fixers,
+                })
+            })
         })
         .collect()
 }
 
-type FixerReturn = Result<PathBuf, String>;
+pub type FixerReturn = Result<PathBuf, FixerError>;
 type Fixer = fn(&PathBuf) -> FixerReturn;
@@ -1,5 +1,5 @@
 use std::{
-    fs,
+    fs, io,
     path::{Path, PathBuf},
     process,
 };
@@ -13,7 +13,10 @@ use app_helpers::{
 use app_logger::{debug, error, trace};
 use image::ColorType;
 
-use crate::{util::transferable_file_times, FixerReturn};
+use crate::{
+    util::{ffmpeg_output_with_progress, transferable_file_times},
+    FixerError, FixerReturn,
+};
 
 pub fn convert_into_preferred_formats(file_path: &PathBuf) -> FixerReturn {
     debug!("Checking if {file_path:?} has unwanted formats");
@@ -24,13 +27,15 @@ pub fn convert_into_preferred_formats(file_path: &PathBuf) -> FixerReturn {
     })
 }
 
-fn check_and_fix_file(file_path: &PathBuf) -> Result<PathBuf, String> {
+fn check_and_fix_file(file_path: &PathBuf) -> FixerReturn {
     if !file_path.exists() {
-        return Err(format!("File {file_path:?} does not exist"));
+        return Err(FixerError::Io {
+            context: format!("File {file_path:?} does not exist"),
+            source: io::ErrorKind::NotFound.into(),
+        });
     }
 
-    let file_format_info = ffprobe::ffprobe(file_path)
-        .map_err(|e| format!("Failed to get ffprobe information of {file_path:?}: {e:?}"))?;
+    let file_format_info = ffprobe::ffprobe(file_path)?;
 
     trace!(
         "File format info: {file_format_info:?}",
@@ -46,15 +51,19 @@ fn check_and_fix_file(file_path: &PathBuf) -> Result<PathBuf, String> {
                     .as_deref()
                     .is_some_and(|codec| matches!(codec, "video" | "image"))
             })
-            .ok_or_else(|| format!("Failed to get image stream of {file_path:?}"));
+            .ok_or_else(|| {
+                FixerError::UnsupportedMedia(format!("Failed to get image stream of {file_path:?}"))
+            });
 
         info?
     };
 
-    let file_stream_codec = file_image_stream
-        .codec_name
-        .as_deref()
-        .ok_or_else(|| format!("Failed to get codec name of {path:?}", path = file_path))?;
+    let file_stream_codec = file_image_stream.codec_name.as_deref().ok_or_else(|| {
+        FixerError::UnsupportedMedia(format!(
+            "Failed to get codec name of {path:?}",
+            path = file_path
+        ))
+    })?;
 
     trace!(
         "File stream codec: {file_stream_codec:?}",
@@ -72,10 +81,10 @@ fn check_and_fix_file(file_path: &PathBuf) -> Result<PathBuf, String> {
 
     error!("File {path:?} has unknown codec", path = file_path);
 
-    Err(format!(
+    Err(FixerError::UnsupportedMedia(format!(
         "File has an unknown codec ({format:?}), please report this issue to the developers.",
         format = file_stream_codec,
-    ))
+    )))
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -115,7 +124,8 @@ impl TranscodeInfo {
     }
 }
 
-fn transcode_media_into(from_path: &PathBuf, to_format: &TranscodeInfo) -> Result<PathBuf, String> {
+#[allow(clippy::too_many_lines)]
+fn transcode_media_into(from_path: &PathBuf, to_format: &TranscodeInfo) -> FixerReturn {
     let to_extension = to_format.extension;
 
     let (cache_folder, cache_from_path) = copy_file_to_cache_folder(from_path)?;
@@ -132,10 +142,10 @@ fn transcode_media_into(from_path: &PathBuf, to_format: &TranscodeInfo) -> Resul
         if path_has_extension(&path, to_extension) {
             let new_file_name =
                 file_name_with_suffix_extension(&path, "transcoded").ok_or_else(|| {
-                    format!(
+                    FixerError::UnsupportedMedia(format!(
                         "Failed to get file name with suffix extension of {path:?}",
                         path = path,
-                    )
+                    ))
                 })?;
 
             path.with_file_name(new_file_name)
@@ -154,18 +164,21 @@ fn transcode_media_into(from_path: &PathBuf, to_format: &TranscodeInfo) -> Resul
         .dependencies
         .ffmpeg_path
         .clone()
-        .ok_or_else(|| "Failed to get `ffmpeg' path from configuration".to_string())?;
+        .ok_or_else(|| FixerError::MissingBinary("ffmpeg".to_string()))?;
     trace!("`ffmpeg' binary: {ffmpeg_path:?}");
     let mut cmd = process::Command::new(ffmpeg_path);
     let mut cmd = cmd
         .arg("-y")
         .arg("-hide_banner")
         .args(["-loglevel", "panic"])
+        .args(["-progress", "pipe:1", "-nostats"])
         .args([
             "-i",
-            (cache_from_path
-                .to_str()
-                .ok_or_else(|| "Failed to convert path to string".to_string())?),
+            (cache_from_path.to_str().ok_or_else(|| {
+                FixerError::UnsupportedMedia(format!(
+                    "Failed to convert {cache_from_path:?} to string"
+                ))
+            })?),
         ])
         .args(["-max_muxing_queue_size", "1024"])
         .args(["-vf", "scale=ceil(iw/2)*2:ceil(ih/2)*2"])
@@ -181,9 +194,10 @@ fn transcode_media_into(from_path: &PathBuf, to_format: &TranscodeInfo) -> Resul
     let cmd = cmd.arg(&cache_to_path);
     debug!("Running `ffmpeg' command: {cmd:?}");
 
-    let cmd_output = cmd.output();
+    let cmd_output = ffmpeg_output_with_progress(cmd, &cache_from_path)
+        .map_err(FixerError::io(format!("Failed to run command {cmd:?}")))?;
     match cmd_output {
-        Ok(process::Output { status, .. }) if status.success() && cache_to_path.exists() => {
+        process::Output { status, .. } if status.success() && cache_to_path.exists() => {
             debug!(
                 "Converted file {from:?} to {to}",
                 from = from_path,
@@ -199,13 +213,11 @@ fn transcode_media_into(from_path: &PathBuf, to_format: &TranscodeInfo) -> Resul
                 cache_path = cache_to_path,
                 new_path = new_file_path
             );
-            if let Err(e) = fs::copy(&cache_to_path, &new_file_path) {
-                return Err(format!(
-                    "Failed to copy {from:?} to {to:?}: {e:?}",
-                    from = cache_to_path,
-                    to = new_file_path,
-                ));
-            }
+            fs::copy(&cache_to_path, &new_file_path).map_err(FixerError::io(format!(
+                "Failed to copy {from:?} to {to:?}",
+                from = cache_to_path,
+                to = new_file_path,
+            )))?;
 
             if &new_file_path != from_path {
                 trace!("Deleting old file {path:?}", path = from_path);
@@ -227,28 +239,37 @@ fn transcode_media_into(from_path: &PathBuf, to_format: &TranscodeInfo) -> Resul
 
             Ok(new_file_path)
         }
-        _ => Err(format!(
-            "Failed transforming {from_path:?} into {to_extension}"
-        )),
+        process::Output { status, .. } if status.success() => {
+            Err(FixerError::UnexpectedOutput(format!(
+                "Failed transforming {from_path:?} into {to_extension}: ffmpeg did not create \
+                 {cache_to_path:?}"
+            )))
+        }
+        output => Err(FixerError::Ffmpeg(output)),
     }
 }
 
-fn copy_file_to_cache_folder(file_path: &Path) -> Result<(PathBuf, PathBuf), String> {
+fn copy_file_to_cache_folder(file_path: &Path) -> Result<(PathBuf, PathBuf), FixerError> {
     let id = time_thread_id();
 
     let cache_folder = CONFIG.cache_dir().join(format!("transcode-{}", id));
 
     if !cache_folder.exists() {
         trace!("Creating {path:?}", path = cache_folder);
-        fs::create_dir_all(&cache_folder)
-            .map_err(|e| format!("Failed to create {path:?}: {e:?}", path = cache_folder))?;
+        fs::create_dir_all(&cache_folder).map_err(FixerError::io(format!(
+            "Failed to create {path:?}",
+            path = cache_folder
+        )))?;
     }
     trace!("Using {path:?} as cache folder", path = cache_folder);
 
     let cache_file_path = {
-        let filename = file_path
-            .file_name()
-            .ok_or_else(|| format!("Failed to get file name of {path:?}", path = file_path))?;
+        let filename = file_path.file_name().ok_or_else(|| {
+            FixerError::UnsupportedMedia(format!(
+                "Failed to get file name of {path:?}",
+                path = file_path
+            ))
+        })?;
 
         cache_folder.join(filename)
     };
@@ -258,13 +279,11 @@ fn copy_file_to_cache_folder(file_path: &Path) -> Result<(PathBuf, PathBuf), Str
         from = file_path,
         to = cache_file_path,
     );
-    fs::copy(file_path, &cache_file_path).map_err(|e| {
-        format!(
-            "Failed to copy {from:?} to {to:?}: {e:?}",
-            from = file_path,
-            to = cache_file_path,
-        )
-    })?;
+    fs::copy(file_path, &cache_file_path).map_err(FixerError::io(format!(
+        "Failed to copy {from:?} to {to:?}",
+        from = file_path,
+        to = cache_file_path,
+    )))?;
 
     Ok((cache_folder, cache_file_path))
 }
@@ -365,7 +384,7 @@ const CODEC_HANDLERS: &[CodecHandler] = &[
         can_handle: |codec| matches!(codec, "webp"),
         handle: |file_format_info, _matched_stream| {
             let from_path = PathBuf::from(file_format_info.format.filename.clone());
-            let img = image::open(&from_path).map_err(|e| e.to_string())?;
+            let img = image::open(&from_path)?;
             let color = img.color();
 
             match color {
@@ -385,11 +404,11 @@ const CODEC_HANDLERS: &[CodecHandler] = &[
                         color_type = color_type,
                     );
 
-                    Err(format!(
+                    Err(FixerError::UnsupportedMedia(format!(
                         "File has an unknown color type ({color_type:?}), please report this \
                          issue to the developers.",
                         color_type = color_type,
-                    ))
+                    )))
                 }
             }
         },
@@ -6,8 +6,10 @@ use std::{
 use app_config::CONFIGURATION;
 use app_helpers::dirs::create_temp_dir;
 
-pub fn split_video_into_scenes(file_path: &Path) -> Result<Vec<PathBuf>, String> {
-    let tmp_dir = create_temp_dir().map_err(|e| format!("Error while getting temp dir: {e:?}"))?;
+use crate::FixerError;
+
+pub fn split_video_into_scenes(file_path: &Path) -> Result<Vec<PathBuf>, FixerError> {
+    let tmp_dir = create_temp_dir().map_err(FixerError::io("Error while getting temp dir"))?;
 
     split_into_scenes(&SplitVideoConfig::new(&tmp_dir, file_path))
 }
@@ -36,9 +38,9 @@ impl<'a> SplitVideoConfig<'a> {
     }
 }
 
-pub fn split_into_scenes(config: &SplitVideoConfig) -> Result<Vec<PathBuf>, String> {
+pub fn split_into_scenes(config: &SplitVideoConfig) -> Result<Vec<PathBuf>, FixerError> {
     let Some(scenedetect_path) = &CONFIGURATION.scenedetect_path else {
-        return Err("scenedetect not found".into());
+        return Err(FixerError::MissingBinary("scenedetect".to_string()));
     };
 
     let mut cmd = Command::new(scenedetect_path);
@@ -58,17 +60,17 @@ pub fn split_into_scenes(config: &SplitVideoConfig) -> Result<Vec<PathBuf>, Stri
 
     let output = cmd
         .output()
-        .map_err(|e| format!("Error while running scenedetect: {e:?}"))?;
+        .map_err(FixerError::io("Error while running scenedetect"))?;
 
     if !output.status.success() {
-        return Err(format!(
-            "Scenedetect exited with code: {code:?}",
-            code = output.status.code()
-        ));
+        return Err(FixerError::Scenedetect(output));
     }
 
     let scenes = std::fs::read_dir(config.download_dir)
-        .map_err(|x| format!("Error while reading temp dir: {x:?}"))?
+        .map_err(FixerError::io(format!(
+            "Error while reading {dir:?}",
+            dir = config.download_dir
+        )))?
         .filter_map(std::result::Result::ok)
         .map(|x| x.path())
         .collect::<Vec<_>>();
@@ -1,21 +1,30 @@
-use std::path::{Path, PathBuf};
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    thread,
+};
 
+use app_helpers::{
+    ffprobe,
+    progress::{self, Progress},
+};
 use app_logger::trace;
 use filetime::FileTime;
 
-pub fn transfer_file_times(path_from: &PathBuf, path_to: &PathBuf) -> Result<(), String> {
+use crate::FixerError;
+
+pub fn transfer_file_times(path_from: &PathBuf, path_to: &PathBuf) -> Result<(), FixerError> {
     trace!(
         "Getting file times of {from:?} and setting them on {to:?}",
         from = path_from,
         to = path_to,
     );
 
-    let old_meta = path_from.metadata().map_err(|e| {
-        format!(
-            "Failed to get metadata of {path:?}: {e:?}",
-            path = path_from,
-        )
-    })?;
+    let old_meta = path_from.metadata().map_err(FixerError::io(format!(
+        "Failed to get metadata of {path:?}",
+        path = path_from,
+    )))?;
 
     trace!("Setting file times of {path:?}", path = path_to);
     filetime::set_file_times(
@@ -23,22 +32,21 @@ pub fn transfer_file_times(path_from: &PathBuf, path_to: &PathBuf) -> Result<(),
         FileTime::from_last_access_time(&old_meta),
         FileTime::from_last_modification_time(&old_meta),
     )
-    .map_err(|e| {
-        format!(
-            "Failed to set file times of {path:?}: {e:?}",
-            path = path_from,
-        )
-    })
+    .map_err(FixerError::io(format!(
+        "Failed to set file times of {path:?}",
+        path = path_from,
+    )))
 }
 
 pub fn transferable_file_times(
     path_from: PathBuf,
-) -> Result<impl FnOnce(&Path) -> Result<(), String>, String> {
+) -> Result<impl FnOnce(&Path) -> Result<(), FixerError>, FixerError> {
     trace!("Getting file times of {path:?}", path = path_from);
 
-    let old_meta = path_from
-        .metadata()
-        .map_err(|e| format!("Failed to get metadata of {old:?}: {e:?}", old = path_from))?;
+    let old_meta = path_from.metadata().map_err(FixerError::io(format!(
+        "Failed to get metadata of {old:?}",
+        old = path_from
+    )))?;
 
     Ok(move |path_to: &Path| {
         trace!("Setting file times of {new:?}", new = path_from);
@@ -47,11 +55,76 @@ pub fn transferable_file_times(
             FileTime::from_last_access_time(&old_meta),
             FileTime::from_last_modification_time(&old_meta),
         )
-        .map_err(|e| {
-            format!(
-                "Failed to set file times of {new:?}: {e:?}",
-                new = path_from
-            )
+        .map_err(FixerError::io(format!(
+            "Failed to set file times of {new:?}",
+            new = path_from
+        )))
+    })
+}
+
+/// Run an `ffmpeg` command that encodes `input` and report how far along it is.
+///
+/// `cmd` has to have been given `-progress pipe:1`, so `ffmpeg` writes
+/// its progress to stdout. Otherwise works like [`process::Command::output`].
+pub fn ffmpeg_output_with_progress(
+    cmd: &mut process::Command,
+    input: &Path,
+) -> io::Result<process::Output> {
+    if !progress::is_enabled() {
+        return cmd.output();
+    }
+
+    let duration = ffprobe::ffprobe(input)
+        .ok()
+        .and_then(|x| x.format.get_duration())
+        .filter(|x| !x.is_zero());
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stderr = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut output = vec![];
+            let _ = stderr.read_to_end(&mut output);
+            output
         })
+    });
+
+    let mut stdout = vec![];
+    if let Some(reader) = child.stdout.take() {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            // Progress comes in blocks of `key=value` lines, `out_time_us` is how much of
+            // the output is encoded
+            if let (Some(duration), Some(out_time)) = (duration, line.strip_prefix("out_time_us="))
+            {
+                if let Ok(out_time) = out_time.parse::<u64>() {
+                    #[allow(clippy::cast_precision_loss)]
+                    let percent = out_time as f32 / duration.as_micros() as f32 * 100.0;
+
+                    progress::report(Progress::Transcoding {
+                        file: input.to_path_buf(),
+                        percent: percent.clamp(0.0, 100.0),
+                    });
+                }
+            }
+
+            stdout.extend(line.into_bytes());
+            stdout.push(b'\n');
+        }
+    }
+
+    let stderr = stderr.and_then(|x| x.join().ok()).unwrap_or_default();
+
+    Ok(process::Output {
+        status: child.wait()?,
+        stdout,
+        stderr,
     })
 }
@@ -1,10 +1,13 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 use app_config::CONFIGURATION;
 
-use crate::id::time_thread_id;
+use crate::{id::time_thread_id, metadata::is_sidecar};
 
-pub fn create_temp_dir() -> anyhow::Result<PathBuf> {
+pub fn create_temp_dir() -> io::Result<PathBuf> {
     let id = time_thread_id();
     let temp_dir = CONFIGURATION.cache_dir().join(id);
 
@@ -12,3 +15,46 @@ pub fn create_temp_dir() -> anyhow::Result<PathBuf> {
 
     Ok(temp_dir)
 }
+
+/// All regular files in `dir` and its subdirectories, skipping hidden entries and sidecar files.
+pub fn files_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+
+            if entry.file_name().to_string_lossy().starts_with('.') || is_sidecar(&entry.path()) {
+                continue;
+            }
+
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Remove empty subdirectories of `dir`, but not `dir` itself.
+pub fn remove_empty_dirs(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            let path = entry.path();
+
+            remove_empty_dirs(&path)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,4 +1,8 @@
-use std::{process, thread, time};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    process, thread, time,
+};
 
 use base64::Engine;
 
@@ -21,6 +25,22 @@ pub fn time_id() -> String {
     encode(now_ns().to_string())
 }
 
+/// The nanosecond timestamp a [`time_id`] was made from.
+#[must_use]
+pub fn parse_time_id(id: &str) -> Option<u128> {
+    let decoded = base64::engine::general_purpose::STANDARD_NO_PAD
+        .decode(id)
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+
+    // Anything shorter would be from before 2001
+    if decoded.len() < 19 {
+        return None;
+    }
+
+    decoded.parse().ok()
+}
+
 #[must_use]
 pub fn time_thread_id() -> String {
     let thread_id = thread::current().id();
@@ -31,3 +51,22 @@ pub fn time_thread_id() -> String {
 
     encode(id)
 }
+
+/// An 8 character lowercase alphanumeric id, unique enough to tell apart
+/// files downloaded around the same time.
+#[must_use]
+pub fn short_id() -> String {
+    const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    let mut hasher = DefaultHasher::new();
+    (now_ns(), process::id(), thread::current().id()).hash(&mut hasher);
+    let mut n = hasher.finish();
+
+    (0..8)
+        .map(|_| {
+            let c = ALPHABET[(n % 36) as usize];
+            n /= 36;
+            char::from(c)
+        })
+        .collect()
+}
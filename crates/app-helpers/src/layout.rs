@@ -0,0 +1,134 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use app_config::CONFIG;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+
+use crate::{
+    id::parse_time_id,
+    metadata::{move_sidecar, PostMetadata, Sidecar},
+    template::{render_template, TemplateContext},
+};
+
+/// Only one thread at a time may pick a name for a file,
+/// otherwise two files moved in parallel could end up with the same name.
+static MOVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Directory a file belongs in according to the configured `directory_template`,
+/// relative to the memes directory.
+///
+/// Every `/`-separated part of the template is rendered with [`render_template`].
+/// Parts that render to nothing are left out, so `{site}/{yyyy}` puts files
+/// without a known site straight into the year directory.
+/// Empty if no template is configured.
+#[must_use]
+pub fn layout_directory(context: &TemplateContext) -> PathBuf {
+    let Some(template) = CONFIG.app.directory_template.as_deref() else {
+        return PathBuf::new();
+    };
+
+    template
+        .split(['/', '\\'])
+        .map(|x| render_template(x, context))
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Like [`layout_directory`], but for a file that was downloaded earlier.
+///
+/// Uses the file's sidecar if it has one. Otherwise the download time is taken
+/// from a date in the file name, or from the file's modification time.
+#[must_use]
+pub fn layout_directory_for_existing(path: &Path) -> PathBuf {
+    if let Some(sidecar) = Sidecar::read(path).ok().flatten() {
+        let context = TemplateContext {
+            time: sidecar.downloaded_at.with_timezone(&Local),
+            ..TemplateContext::new(&sidecar.source_url, &sidecar.post)
+        };
+
+        return layout_directory(&context);
+    }
+
+    let time = path
+        .file_name()
+        .and_then(|x| time_from_file_name(&x.to_string_lossy()))
+        .or_else(|| {
+            fs::metadata(path)
+                .and_then(|x| x.modified())
+                .ok()
+                .map(DateTime::<Local>::from)
+        })
+        .unwrap_or_else(Local::now);
+
+    let post = PostMetadata::default();
+    let context = TemplateContext {
+        time,
+        ..TemplateContext::new("", &post)
+    };
+
+    layout_directory(&context)
+}
+
+/// Move a file to `dir/file_name`, along with its sidecar file if it has one.
+///
+/// `dir` is created if needed. A `-2`, `-3`, ... suffix is added to the name
+/// if a different file with that name already exists.
+/// Falls back to copying if the file is moved to a different filesystem.
+pub fn move_file(path: &Path, dir: &Path, file_name: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let _lock = MOVE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let (stem, dot_extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (file_name, String::new()),
+    };
+
+    let mut new_path = dir.join(file_name);
+    let mut n = 1;
+    while new_path.exists() && new_path != path {
+        n += 1;
+        new_path = dir.join(format!("{stem}-{n}{dot_extension}"));
+    }
+
+    if new_path == path {
+        return Ok(new_path);
+    }
+
+    fs::rename(path, &new_path).or_else(|_| {
+        fs::copy(path, &new_path)?;
+        fs::remove_file(path)
+    })?;
+    move_sidecar(path, &new_path)?;
+
+    Ok(new_path)
+}
+
+/// Find a download time in a file name.
+///
+/// Understands dates like `2024-01-31`, optionally followed by a time like `_13-37-00`,
+/// and the ids that file names used to start with.
+fn time_from_file_name(name: &str) -> Option<DateTime<Local>> {
+    let date_time = (0..name.len()).find_map(|i| {
+        let date = name
+            .get(i..i + 10)
+            .and_then(|x| NaiveDate::parse_from_str(x, "%Y-%m-%d").ok())?;
+        let time = name
+            .get(i + 11..i + 19)
+            .and_then(|x| NaiveTime::parse_from_str(x, "%H-%M-%S").ok())
+            .unwrap_or_default();
+
+        Local.from_local_datetime(&date.and_time(time)).earliest()
+    });
+
+    date_time.or_else(|| {
+        let ns = name.split('.').next().and_then(parse_time_id)?;
+        let secs = i64::try_from(ns / 1_000_000_000).ok()?;
+        let nanos = u32::try_from(ns % 1_000_000_000).ok()?;
+
+        DateTime::<Utc>::from_timestamp(secs, nanos).map(|x| x.with_timezone(&Local))
+    })
+}
@@ -1,5 +1,9 @@
 pub mod dirs;
 pub mod ffprobe;
 pub mod id;
+pub mod layout;
+pub mod metadata;
+pub mod progress;
 pub mod results;
+pub mod template;
 pub mod trash;
@@ -0,0 +1,142 @@
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Suffix appended to a file name to get the name of its sidecar file.
+pub static SIDECAR_SUFFIX: &str = ".meta.json";
+
+/// What is known about the post a file was downloaded from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The text or caption of the post.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_id: Option<String>,
+    /// Description of the image or video for people who can't see it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alt_text: Option<String>,
+}
+
+impl PostMetadata {
+    /// Fill in fields that are missing here from `other`.
+    pub fn merge_missing(&mut self, other: &Self) -> &mut Self {
+        if self.author.is_none() {
+            self.author.clone_from(&other.author);
+        }
+
+        if self.title.is_none() {
+            self.title.clone_from(&other.title);
+        }
+
+        if self.description.is_none() {
+            self.description.clone_from(&other.description);
+        }
+
+        if self.post_id.is_none() {
+            self.post_id.clone_from(&other.post_id);
+        }
+
+        if self.alt_text.is_none() {
+            self.alt_text.clone_from(&other.alt_text);
+        }
+
+        self
+    }
+}
+
+/// Provenance of a downloaded file, stored in a JSON file next to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sidecar {
+    /// The URL that was given to the downloader.
+    pub source_url: String,
+    /// Name of the downloader that handled the URL.
+    pub downloader: String,
+    #[serde(flatten)]
+    pub post: PostMetadata,
+    pub downloaded_at: DateTime<Utc>,
+    /// Names of the fixers that changed the file after it was downloaded.
+    #[serde(default)]
+    pub fixers: Vec<String>,
+}
+
+impl Sidecar {
+    /// A sidecar for a file that was just downloaded.
+    #[must_use]
+    pub fn new(source_url: &str, downloader: &str, post: PostMetadata) -> Self {
+        Self {
+            source_url: source_url.to_string(),
+            downloader: downloader.to_string(),
+            post,
+            downloaded_at: Utc::now(),
+            fixers: vec![],
+        }
+    }
+
+    /// Read the sidecar of `file`, if it has one.
+    pub fn read(file: &Path) -> io::Result<Option<Self>> {
+        let sidecar_path = sidecar_path(file);
+
+        if !sidecar_path.is_file() {
+            return Ok(None);
+        }
+
+        let contents = fs::read(sidecar_path)?;
+
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(io::Error::other)
+    }
+
+    /// Write the sidecar next to `file`, replacing an existing one.
+    pub fn write(&self, file: &Path) -> io::Result<PathBuf> {
+        let sidecar_path = sidecar_path(file);
+        let contents = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+
+        fs::write(&sidecar_path, contents)?;
+
+        Ok(sidecar_path)
+    }
+}
+
+/// Path of the sidecar file belonging to `file`.
+#[must_use]
+pub fn sidecar_path(file: &Path) -> PathBuf {
+    let mut file_name = file.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(SIDECAR_SUFFIX);
+
+    file.with_file_name(file_name)
+}
+
+/// Whether `path` looks like a sidecar file.
+#[must_use]
+pub fn is_sidecar(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|x| x.to_string_lossy().ends_with(SIDECAR_SUFFIX))
+}
+
+/// Move the sidecar of `from` so that it belongs to `to`, if there is one.
+///
+/// Falls back to copying if the files are on different filesystems.
+pub fn move_sidecar(from: &Path, to: &Path) -> io::Result<()> {
+    let from = sidecar_path(from);
+    let to = sidecar_path(to);
+
+    if !from.is_file() {
+        return Ok(());
+    }
+
+    fs::rename(&from, &to).or_else(|_| {
+        fs::copy(&from, &to)?;
+        fs::remove_file(&from)
+    })
+}
@@ -0,0 +1,133 @@
+use std::{cell::RefCell, fmt, path::PathBuf, sync::mpsc::Sender};
+
+/// Something a download or fix is busy with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// Bytes of the file being downloaded that arrived so far.
+    Downloading { downloaded: u64, total: Option<u64> },
+    /// A fixer started working on a file.
+    Fixing { file: PathBuf, fixer: &'static str },
+    /// How far along `ffmpeg` is with re-encoding a file, from 0 to 100.
+    Transcoding { file: PathBuf, percent: f32 },
+}
+
+impl Progress {
+    /// How far along the current step is, from 0 to 100, if that is known.
+    #[must_use]
+    pub fn percent(&self) -> Option<f32> {
+        match self {
+            #[allow(clippy::cast_precision_loss)]
+            Self::Downloading {
+                downloaded,
+                total: Some(total),
+            } if *total > 0 => Some((*downloaded as f32 / *total as f32 * 100.0).min(100.0)),
+            Self::Transcoding { percent, .. } => Some(*percent),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Downloading {
+                downloaded,
+                total: Some(total),
+            } => write!(
+                f,
+                "Downloading {} of {} ({:.0}%)",
+                Bytes(*downloaded),
+                Bytes(*total),
+                self.percent().unwrap_or_default()
+            ),
+            Self::Downloading { downloaded, .. } => {
+                write!(f, "Downloading {}", Bytes(*downloaded))
+            }
+            Self::Fixing { file, fixer } => write!(
+                f,
+                "Fixing {}: {fixer}",
+                file.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            Self::Transcoding { file, percent } => write!(
+                f,
+                "Transcoding {}: {percent:.0}%",
+                file.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        }
+    }
+}
+
+/// Formats a byte count in binary units.
+struct Bytes(u64);
+
+impl fmt::Display for Bytes {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+
+        let mut size = self.0 as f64 / 1024.0;
+        let mut unit = UNITS[0];
+        for next in &UNITS[1..] {
+            if size < 1024.0 {
+                break;
+            }
+            size /= 1024.0;
+            unit = next;
+        }
+
+        write!(f, "{size:.1} {unit}")
+    }
+}
+
+pub type ProgressSender = Sender<Progress>;
+
+thread_local! {
+    static SINK: RefCell<Option<ProgressSender>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with progress events of the current thread going to `sink`.
+///
+/// Code that hands work to other threads has to pass [`current`] along itself.
+pub fn with_progress<F, R>(sink: Option<ProgressSender>, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    /// Puts the previous sink back, even if `f` panics.
+    struct Restore(Option<ProgressSender>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            SINK.with(|x| x.replace(previous));
+        }
+    }
+
+    let _restore = Restore(SINK.with(|x| x.replace(sink)));
+    f()
+}
+
+/// Where progress events of the current thread go, if anywhere.
+#[must_use]
+pub fn current() -> Option<ProgressSender> {
+    SINK.with(|x| x.borrow().clone())
+}
+
+/// Whether anyone listens to progress events of the current thread.
+#[must_use]
+pub fn is_enabled() -> bool {
+    SINK.with(|x| x.borrow().is_some())
+}
+
+/// Report progress to whoever listens on the current thread. Does nothing otherwise.
+pub fn report(progress: Progress) {
+    SINK.with(|x| {
+        if let Some(sink) = x.borrow().as_ref() {
+            // The receiver going away just means nobody is interested anymore
+            let _ = sink.send(progress);
+        }
+    });
+}
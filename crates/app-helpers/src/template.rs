@@ -0,0 +1,276 @@
+use chrono::{DateTime, Local};
+use url::Url;
+
+use crate::{id::short_id, metadata::PostMetadata};
+
+/// Used when `filename_template` isn't configured.
+pub static DEFAULT_FILENAME_TEMPLATE: &str = "{date}_{time}_{site}_{post_id|name|id}_{index}";
+
+/// Longest file name (including the extension) a template may produce.
+pub const MAX_FILENAME_LENGTH: usize = 120;
+
+/// Longest a single placeholder value may get.
+const MAX_VALUE_LENGTH: usize = 64;
+
+/// Characters that separate placeholders in a template.
+///
+/// Separators before an empty placeholder are dropped
+/// so missing values don't leave `__` or a trailing `_` behind.
+const SEPARATORS: &[char] = &['_', '-', '.', ' '];
+
+/// Values that can be filled into a file name template.
+#[derive(Debug, Clone)]
+pub struct TemplateContext<'a> {
+    /// When the file was downloaded.
+    pub time: DateTime<Local>,
+    pub source_url: &'a str,
+    pub post: &'a PostMetadata,
+    /// 1-based position of the file in its post, if the post has more than one file.
+    pub index: Option<usize>,
+    /// Name of the file at its source, without the extension.
+    pub name: Option<&'a str>,
+}
+
+impl<'a> TemplateContext<'a> {
+    /// Context for a file downloaded just now.
+    #[must_use]
+    pub fn new(source_url: &'a str, post: &'a PostMetadata) -> Self {
+        Self {
+            time: Local::now(),
+            source_url,
+            post,
+            index: None,
+            name: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_index(mut self, index: Option<usize>) -> Self {
+        self.index = index;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_name(mut self, name: Option<&'a str>) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// The value of a placeholder, or [`None`] if `name` isn't a placeholder.
+    ///
+    /// Placeholders without a value for this file resolve to an empty string.
+    fn value(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "date" => self.time.format("%Y-%m-%d").to_string(),
+            "time" => self.time.format("%H-%M-%S").to_string(),
+            "yyyy" => self.time.format("%Y").to_string(),
+            "mm" => self.time.format("%m").to_string(),
+            "dd" => self.time.format("%d").to_string(),
+            "site" => site_name(self.source_url).unwrap_or_default(),
+            "author" => self.post.author.clone().unwrap_or_default(),
+            "post_id" => self.post.post_id.clone().unwrap_or_default(),
+            "title" => self.post.title.clone().unwrap_or_default(),
+            "index" => self.index.map(|x| x.to_string()).unwrap_or_default(),
+            "name" => self.name.unwrap_or_default().to_string(),
+            "id" => short_id(),
+            _ => return None,
+        };
+
+        Some(sanitize(&value))
+    }
+}
+
+/// Fill in a template like `{date}_{author|unknown}_{post_id|id}`.
+///
+/// Placeholders:
+/// - `{date}` and `{time}`: download date and time, as `2024-01-31` and `13-37-00`
+/// - `{yyyy}`, `{mm}` and `{dd}`: download year, month and day
+/// - `{site}`: host of the source URL, without `www.`
+/// - `{author}`, `{post_id}` and `{title}`: details of the post, if the downloader found them
+/// - `{index}`: position of the file in a gallery, empty for single-file posts
+/// - `{name}`: name of the file at its source, eg. from its URL, without the extension
+/// - `{id}`: a short random id
+///
+/// `{a|b|text}` uses the first alternative that isn't empty.
+/// Alternatives that aren't placeholder names are used as literal text.
+///
+/// Whitespace in values becomes `_`, and control characters and characters
+/// that aren't allowed in paths on some platforms are dropped.
+#[must_use]
+pub fn render_template(template: &str, context: &TemplateContext) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let Some(end) = rest.find('}') else {
+            out.push('{');
+            break;
+        };
+
+        let value = rest[..end]
+            .split('|')
+            .map(|x| context.value(x.trim()).unwrap_or_else(|| sanitize(x)))
+            .find(|x| !x.is_empty())
+            .unwrap_or_default();
+
+        if value.is_empty() {
+            out.truncate(out.trim_end_matches(SEPARATORS).len());
+        } else {
+            out.push_str(&value);
+        }
+
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+
+    out.trim_matches(SEPARATORS).to_string()
+}
+
+/// Render `template` into a file name with the given extension,
+/// keeping it under [`MAX_FILENAME_LENGTH`].
+///
+/// Falls back to a short random id if the template renders to nothing.
+#[must_use]
+pub fn render_file_name(template: &str, context: &TemplateContext, extension: &str) -> String {
+    let mut stem = render_template(template, context);
+
+    let max_stem_length = MAX_FILENAME_LENGTH.saturating_sub(extension.len() + 1);
+    truncate_at_char_boundary(&mut stem, max_stem_length);
+    stem.truncate(stem.trim_end_matches(SEPARATORS).len());
+
+    if stem.is_empty() {
+        stem = short_id();
+    }
+
+    if extension.is_empty() {
+        stem
+    } else {
+        format!("{stem}.{extension}")
+    }
+}
+
+/// Host of a URL without the `www.` or `m.` prefix.
+#[must_use]
+pub fn site_name(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+
+    let host = host
+        .strip_prefix("www.")
+        .or_else(|| host.strip_prefix("m."))
+        .unwrap_or(host);
+
+    Some(host.to_string())
+}
+
+/// Make a placeholder value safe to use in a file name.
+///
+/// Whitespace becomes a single `_`, and control characters and characters
+/// that aren't allowed in paths on some platforms are dropped.
+fn sanitize(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if c.is_whitespace() {
+            if !out.ends_with('_') {
+                out.push('_');
+            }
+        } else if !c.is_control()
+            && !matches!(c, '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+        {
+            out.push(c);
+        }
+
+        if out.len() >= MAX_VALUE_LENGTH {
+            break;
+        }
+    }
+
+    truncate_at_char_boundary(&mut out, MAX_VALUE_LENGTH);
+    out.trim_matches(SEPARATORS).to_string()
+}
+
+/// Shorten `value` to at most `max_length` bytes without splitting a character.
+fn truncate_at_char_boundary(value: &mut String, max_length: usize) {
+    if value.len() <= max_length {
+        return;
+    }
+
+    let mut end = max_length;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+}
+
+#[cfg(test)]
+#[allow(clippy::literal_string_with_formatting_args)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, post: &PostMetadata) -> String {
+        render_template(
+            template,
+            &TemplateContext::new("https://www.example.com/post", post),
+        )
+    }
+
+    #[test]
+    fn keeps_non_ascii_values() {
+        let post = PostMetadata {
+            author: Some("Zoë Ünal".to_string()),
+            title: Some("猫のミーム".to_string()),
+            ..PostMetadata::default()
+        };
+
+        assert_eq!(
+            render("{site}_{author}_{title}", &post),
+            "example.com_Zoë_Ünal_猫のミーム"
+        );
+    }
+
+    #[test]
+    fn uses_source_name_without_post_id() {
+        let post = PostMetadata::default();
+        let context = TemplateContext::new("https://www.example.com/files/cat.png", &post)
+            .with_name(Some("cat pic"));
+
+        assert_eq!(
+            render_template("{site}_{post_id|name|id}", &context),
+            "example.com_cat_pic"
+        );
+    }
+
+    #[test]
+    fn drops_path_unsafe_and_control_characters() {
+        let post = PostMetadata {
+            title: Some("a/b\\c:d*e?f\"g<h>i|j\u{7}k  l!".to_string()),
+            ..PostMetadata::default()
+        };
+
+        assert_eq!(render("{title}", &post), "abcdefghijk_l!");
+    }
+
+    #[test]
+    fn truncates_long_values_between_characters() {
+        let post = PostMetadata {
+            title: Some("é".repeat(100)),
+            ..PostMetadata::default()
+        };
+
+        let title = render("{title}", &post);
+        assert!(title.len() <= MAX_VALUE_LENGTH);
+        assert!(title.chars().all(|x| x == 'é'));
+
+        let file_name = render_file_name(
+            &"{title}_".repeat(3),
+            &TemplateContext::new("", &post),
+            "jpg",
+        );
+        assert!(file_name.len() <= MAX_FILENAME_LENGTH);
+        assert_eq!(file_name.rsplit_once('.').map(|x| x.1), Some("jpg"));
+    }
+}
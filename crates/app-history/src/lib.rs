@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use anyhow::Context;
+use app_config::CONFIG;
+use app_logger::{debug, trace};
+use chrono::{DateTime, Local, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+pub static HISTORY_FILE_NAME: &str = "history.sqlite3";
+
+/// Query parameters that only track where a link was shared from
+/// and don't change what the URL points to.
+static TRACKING_QUERY_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "igsh", "igshid", "si", "ref_src", "ref_url", "feature",
+];
+
+/// Query parameters that are only for tracking or timestamps on some sites,
+/// but can point to different content elsewhere, eg. `?t=123` as an ID.
+static SITE_TRACKING_QUERY_PARAMS: &[(&str, &[&str])] = &[
+    ("youtube.com", &["s", "t", "ref"]),
+    ("youtu.be", &["s", "t", "ref"]),
+    ("twitter.com", &["s", "t", "ref"]),
+    ("x.com", &["s", "t", "ref"]),
+    ("instagram.com", &["s", "t", "ref"]),
+];
+
+/// A single successful download.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub id: i64,
+    /// The canonical form of the URL, see [`canonicalize_url`].
+    pub url: String,
+    /// The URL as it was given to the downloader.
+    pub original_url: String,
+    /// Name of the downloader that handled the URL.
+    pub downloader: String,
+    pub downloaded_at: DateTime<Local>,
+    pub paths: Vec<PathBuf>,
+}
+
+impl HistoryEntry {
+    /// Whether any of the downloaded files are still on disk.
+    #[must_use]
+    pub fn any_file_exists(&self) -> bool {
+        self.paths.iter().any(|x| x.exists())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let downloaded_at: i64 = row.get("downloaded_at")?;
+        let paths: String = row.get("paths")?;
+
+        Ok(Self {
+            id: row.get("id")?,
+            url: row.get("url")?,
+            original_url: row.get("original_url")?,
+            downloader: row.get("downloader")?,
+            downloaded_at: DateTime::<Utc>::from_timestamp(downloaded_at, 0)
+                .unwrap_or_default()
+                .with_timezone(&Local),
+            paths: serde_json::from_str(&paths).unwrap_or_default(),
+        })
+    }
+}
+
+/// Persistent record of what has already been downloaded.
+///
+/// Backed by an `SQLite` database, by default in the application data directory.
+#[derive(Debug)]
+pub struct History {
+    conn: Mutex<Connection>,
+}
+
+impl History {
+    /// Open the history database in the default location.
+    pub fn open_default() -> anyhow::Result<Self> {
+        Self::open(&CONFIG.data_dir().join(HISTORY_FILE_NAME))
+    }
+
+    /// Open the history database at `path`, creating it if needed.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create history directory {parent:?}"))?;
+        }
+
+        trace!("Opening history database at {path:?}");
+
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open history database {path:?}"))?;
+
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS downloads (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL,
+                original_url TEXT NOT NULL,
+                downloader TEXT NOT NULL,
+                downloaded_at INTEGER NOT NULL,
+                paths TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS downloads_url ON downloads (url);
+            ",
+        )
+        .context("Failed to create history tables")?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Record a successful download of `url`.
+    pub fn record(&self, url: &str, downloader: &str, paths: &[PathBuf]) -> anyhow::Result<()> {
+        let canonical_url = canonicalize_url(url);
+
+        debug!("Recording download of {canonical_url:?} in history");
+
+        self.conn()
+            .execute(
+                "INSERT INTO downloads (url, original_url, downloader, downloaded_at, paths) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    canonical_url,
+                    url,
+                    downloader,
+                    Utc::now().timestamp(),
+                    serde_json::to_string(paths)?,
+                ],
+            )
+            .context("Failed to record download in history")?;
+
+        Ok(())
+    }
+
+    /// Find the latest download of `url`, compared by its canonical form.
+    pub fn find(&self, url: &str) -> anyhow::Result<Option<HistoryEntry>> {
+        self.conn()
+            .query_row(
+                "SELECT * FROM downloads WHERE url = ?1 ORDER BY downloaded_at DESC, id DESC LIMIT 1",
+                params![canonicalize_url(url)],
+                HistoryEntry::from_row,
+            )
+            .optional()
+            .context("Failed to query history")
+    }
+
+    /// List past downloads, newest first.
+    ///
+    /// If `query` is given, only entries where the URL,
+    /// downloader name or one of the file paths contain it are returned.
+    pub fn search(&self, query: Option<&str>, limit: usize) -> anyhow::Result<Vec<HistoryEntry>> {
+        let pattern = query.map_or_else(
+            || "%".to_string(),
+            |x| {
+                format!(
+                    "%{}%",
+                    x.replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_")
+                )
+            },
+        );
+
+        let entries = self
+            .conn()
+            .prepare(
+                "SELECT * FROM downloads \
+                 WHERE url LIKE ?1 ESCAPE '\\' \
+                    OR original_url LIKE ?1 ESCAPE '\\' \
+                    OR downloader LIKE ?1 ESCAPE '\\' \
+                    OR paths LIKE ?1 ESCAPE '\\' \
+                 ORDER BY downloaded_at DESC, id DESC \
+                 LIMIT ?2",
+            )?
+            .query_map(
+                params![pattern, i64::try_from(limit).unwrap_or(i64::MAX)],
+                HistoryEntry::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to query history")?;
+
+        Ok(entries)
+    }
+
+    /// Point entries at the new locations of files that were moved.
+    ///
+    /// Returns how many entries were changed.
+    pub fn update_paths(&self, moves: &HashMap<PathBuf, PathBuf>) -> anyhow::Result<usize> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+
+        let rows = tx
+            .prepare("SELECT id, paths FROM downloads")?
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut updated = 0;
+        for (id, paths) in rows {
+            let mut paths = serde_json::from_str::<Vec<PathBuf>>(&paths).unwrap_or_default();
+            let mut changed = false;
+
+            for path in &mut paths {
+                if let Some(new_path) = moves.get(path) {
+                    path.clone_from(new_path);
+                    changed = true;
+                }
+            }
+
+            if changed {
+                tx.execute(
+                    "UPDATE downloads SET paths = ?1 WHERE id = ?2",
+                    params![serde_json::to_string(&paths)?, id],
+                )?;
+                updated += 1;
+            }
+        }
+
+        tx.commit().context("Failed to update paths in history")?;
+        drop(conn);
+
+        Ok(updated)
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Normalise a URL so that different links to the same post compare equal.
+///
+/// Drops the fragment, tracking query parameters and `www.`/`m.` host prefixes,
+/// sorts the remaining query parameters and trims trailing slashes.
+/// Strings that aren't valid URLs are only trimmed.
+#[must_use]
+pub fn canonicalize_url(url: &str) -> String {
+    let url = url.trim();
+
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+
+    if parsed.scheme() == "http" {
+        let _ = parsed.set_scheme("https");
+    }
+
+    if let Some(host) = parsed.host_str() {
+        let host = host
+            .strip_prefix("www.")
+            .or_else(|| host.strip_prefix("m."))
+            .map(ToString::to_string);
+
+        if let Some(host) = host {
+            let _ = parsed.set_host(Some(&host));
+        }
+    }
+
+    parsed.set_fragment(None);
+
+    let host = parsed.host_str().unwrap_or_default();
+    let site_params = SITE_TRACKING_QUERY_PARAMS
+        .iter()
+        .filter(|(site, _)| host == *site || host.ends_with(&format!(".{site}")))
+        .flat_map(|(_, params)| params.iter())
+        .collect::<Vec<_>>();
+
+    let mut query = parsed
+        .query_pairs()
+        .filter(|(k, _)| {
+            !k.starts_with("utm_")
+                && !TRACKING_QUERY_PARAMS.contains(&k.as_ref())
+                && !site_params.contains(&&k.as_ref())
+        })
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect::<Vec<_>>();
+    query.sort();
+
+    if query.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    let path = parsed.path().trim_end_matches('/').to_string();
+    parsed.set_path(&path);
+
+    parsed.to_string().trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::canonicalize_url;
+
+    #[test]
+    fn strips_tracking_params_everywhere() {
+        assert_eq!(
+            canonicalize_url("https://example.com/post?utm_source=x&fbclid=abc&id=1"),
+            "https://example.com/post?id=1"
+        );
+    }
+
+    #[test]
+    fn strips_site_params_only_on_their_sites() {
+        assert_eq!(
+            canonicalize_url("https://www.youtube.com/watch?v=abc&t=42"),
+            "https://youtube.com/watch?v=abc"
+        );
+        assert_eq!(
+            canonicalize_url("https://x.com/user/status/1?s=20&t=xyz"),
+            "https://x.com/user/status/1"
+        );
+        assert_eq!(
+            canonicalize_url("https://example.com/view?t=123"),
+            "https://example.com/view?t=123"
+        );
+        assert_ne!(
+            canonicalize_url("https://example.com/view?t=123"),
+            canonicalize_url("https://example.com/view?t=456")
+        );
+    }
+
+    #[test]
+    fn normalizes_scheme_host_and_trailing_slash() {
+        assert_eq!(
+            canonicalize_url("http://m.example.com/a/b/#comments"),
+            "https://example.com/a/b"
+        );
+    }
+}
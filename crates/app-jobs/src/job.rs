@@ -0,0 +1,212 @@
+use std::{fmt, path::PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+
+/// What a job should do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum JobKind {
+    /// Download media from a URL.
+    Download {
+        url: String,
+        /// Download even if the URL is already in the download history.
+        #[serde(default)]
+        force: bool,
+    },
+    /// Fix a file that is already on disk.
+    Fix { path: PathBuf },
+}
+
+impl fmt::Display for JobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Download { url, .. } => write!(f, "download {url}"),
+            Self::Fix { path } => write!(f, "fix {}", path.to_string_lossy()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    /// There was nothing to do, eg. the URL was already downloaded before.
+    Skipped,
+    /// Some of the files were produced, `error` says what happened to the rest.
+    Partial,
+    Failed,
+}
+
+impl JobStatus {
+    #[must_use]
+    pub const fn is_finished(self) -> bool {
+        matches!(
+            self,
+            Self::Succeeded | Self::Skipped | Self::Partial | Self::Failed
+        )
+    }
+
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Succeeded => "succeeded",
+            Self::Skipped => "skipped",
+            Self::Partial => "partial",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn from_str(s: &str) -> Self {
+        match s {
+            "queued" => Self::Queued,
+            "running" => Self::Running,
+            "succeeded" => Self::Succeeded,
+            "skipped" => Self::Skipped,
+            "partial" => Self::Partial,
+            _ => Self::Failed,
+        }
+    }
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A unit of work in the [`JobQueue`](crate::JobQueue).
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: i64,
+    /// Which front end enqueued the job, and so is the one to run it.
+    pub origin: String,
+    #[serde(flatten)]
+    pub kind: JobKind,
+    /// Data the front end needs to report the result, eg. which chat to reply to.
+    pub context: Option<serde_json::Value>,
+    pub status: JobStatus,
+    /// How many times the job was started.
+    pub attempts: u32,
+    pub files: Vec<PathBuf>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+}
+
+impl Job {
+    /// Whether the job won't be retried if this attempt fails.
+    #[must_use]
+    pub const fn is_last_attempt(&self) -> bool {
+        self.attempts >= crate::MAX_ATTEMPTS
+    }
+
+    pub(crate) fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let kind: String = row.get("kind")?;
+        let context: Option<String> = row.get("context")?;
+        let status: String = row.get("status")?;
+        let files: String = row.get("files")?;
+
+        Ok(Self {
+            id: row.get("id")?,
+            origin: row.get("origin")?,
+            kind: serde_json::from_str(&kind).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+            })?,
+            context: context.and_then(|x| serde_json::from_str(&x).ok()),
+            status: JobStatus::from_str(&status),
+            attempts: row.get("attempts")?,
+            files: serde_json::from_str(&files).unwrap_or_default(),
+            error: row.get("error")?,
+            created_at: local_time(row.get("created_at")?),
+            updated_at: local_time(row.get("updated_at")?),
+        })
+    }
+}
+
+/// What a finished job produced.
+#[derive(Debug, Clone, Default)]
+pub struct JobOutput {
+    pub files: Vec<PathBuf>,
+    /// There was nothing to do, the files are ones that were already there.
+    pub skipped: bool,
+    /// Why some of the files could not be produced, if only some of them were.
+    pub errors: Vec<String>,
+}
+
+impl JobOutput {
+    #[must_use]
+    pub const fn done(files: Vec<PathBuf>) -> Self {
+        Self {
+            files,
+            skipped: false,
+            errors: vec![],
+        }
+    }
+
+    #[must_use]
+    pub const fn skipped(files: Vec<PathBuf>) -> Self {
+        Self {
+            files,
+            skipped: true,
+            errors: vec![],
+        }
+    }
+
+    /// Files along with why the rest could not be produced.
+    /// Without errors, this is the same as [`JobOutput::done`].
+    #[must_use]
+    pub const fn partial(files: Vec<PathBuf>, errors: Vec<String>) -> Self {
+        Self {
+            files,
+            skipped: false,
+            errors,
+        }
+    }
+}
+
+/// Why a job failed.
+#[derive(Debug, Clone)]
+pub struct JobError {
+    pub message: String,
+    /// Whether running the job again later has a reasonable chance of succeeding.
+    pub transient: bool,
+}
+
+impl JobError {
+    pub fn permanent<S>(message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            message: message.into(),
+            transient: false,
+        }
+    }
+
+    pub fn transient<S>(message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            message: message.into(),
+            transient: true,
+        }
+    }
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+fn local_time(timestamp: i64) -> DateTime<Local> {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .with_timezone(&Local)
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use anyhow::Context;
+use app_config::CONFIG;
+use app_logger::{debug, trace};
+use chrono::Utc;
+pub use job::{Job, JobError, JobKind, JobOutput, JobStatus};
+use rusqlite::{params, Connection, OptionalExtension};
+pub use worker::{JobHandler, WorkerPool};
+
+mod job;
+mod worker;
+
+pub static JOBS_FILE_NAME: &str = "jobs.sqlite3";
+
+/// How many times a job is started before a transient failure is final.
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// How long front ends keep finished jobs around before pruning them.
+pub const KEEP_FINISHED_JOBS_FOR: Duration = Duration::from_hours(30 * 24);
+
+/// How long to wait before the first retry. Doubles with every further attempt.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// How often waiting workers look for new jobs,
+/// in case they were added by a different process.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Persistent queue of downloads and fixes.
+///
+/// Backed by an `SQLite` database, by default in the application data directory,
+/// so jobs that were queued or running when the program stopped aren't lost.
+///
+/// Every job belongs to the front end (its origin) that enqueued it.
+/// Only workers of the same origin run it, since that front end
+/// is the one that knows how to report the result.
+#[derive(Debug)]
+pub struct JobQueue {
+    conn: Mutex<Connection>,
+    changed: Condvar,
+}
+
+impl JobQueue {
+    /// Open the job database in the default location.
+    pub fn open_default() -> anyhow::Result<Self> {
+        Self::open(&CONFIG.data_dir().join(JOBS_FILE_NAME))
+    }
+
+    /// Open the job database at `path`, creating it if needed.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create jobs directory {parent:?}"))?;
+        }
+
+        trace!("Opening jobs database at {path:?}");
+
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open jobs database {path:?}"))?;
+
+        Self::from_connection(conn)
+    }
+
+    /// A queue that only lives as long as the process.
+    ///
+    /// Useful as a fallback if the database can't be opened.
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> anyhow::Result<Self> {
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                origin TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                context TEXT,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                run_after INTEGER NOT NULL,
+                files TEXT NOT NULL DEFAULT '[]',
+                error TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS jobs_pending ON jobs (origin, status, run_after);
+            ",
+        )
+        .context("Failed to create jobs tables")?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            changed: Condvar::new(),
+        })
+    }
+
+    /// Add a job to the end of the queue.
+    pub fn enqueue(
+        &self,
+        origin: &str,
+        kind: &JobKind,
+        context: Option<&serde_json::Value>,
+    ) -> anyhow::Result<Job> {
+        let now = Utc::now().timestamp();
+
+        let job = self
+            .conn()
+            .query_row(
+                "INSERT INTO jobs (origin, kind, context, status, run_after, created_at, updated_at) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?5) \
+                 RETURNING *",
+                params![
+                    origin,
+                    serde_json::to_string(kind)?,
+                    context.map(serde_json::Value::to_string),
+                    JobStatus::Queued.as_str(),
+                    now,
+                ],
+                Job::from_row,
+            )
+            .context("Failed to add job to queue")?;
+
+        debug!("Queued job {id}: {kind}", id = job.id);
+        self.changed.notify_all();
+
+        Ok(job)
+    }
+
+    pub fn get(&self, id: i64) -> anyhow::Result<Option<Job>> {
+        self.conn()
+            .query_row(
+                "SELECT * FROM jobs WHERE id = ?1",
+                params![id],
+                Job::from_row,
+            )
+            .optional()
+            .context("Failed to query jobs")
+    }
+
+    /// List jobs, newest first.
+    ///
+    /// If `origin` is given, only jobs enqueued by that front end are returned.
+    pub fn list(&self, origin: Option<&str>, limit: usize) -> anyhow::Result<Vec<Job>> {
+        let jobs = self
+            .conn()
+            .prepare(
+                "SELECT * FROM jobs \
+                 WHERE ?1 IS NULL OR origin = ?1 \
+                 ORDER BY id DESC \
+                 LIMIT ?2",
+            )?
+            .query_map(
+                params![origin, i64::try_from(limit).unwrap_or(i64::MAX)],
+                Job::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to query jobs")?;
+
+        Ok(jobs)
+    }
+
+    /// Mark the oldest job of `origin` that is due as running and return it.
+    pub fn claim_next(&self, origin: &str) -> anyhow::Result<Option<Job>> {
+        let now = Utc::now().timestamp();
+
+        self.conn()
+            .query_row(
+                "UPDATE jobs SET status = ?1, attempts = attempts + 1, updated_at = ?2 \
+                 WHERE id = ( \
+                    SELECT id FROM jobs \
+                    WHERE origin = ?3 AND status = ?4 AND run_after <= ?2 \
+                    ORDER BY run_after, id \
+                    LIMIT 1 \
+                 ) \
+                 RETURNING *",
+                params![
+                    JobStatus::Running.as_str(),
+                    now,
+                    origin,
+                    JobStatus::Queued.as_str(),
+                ],
+                Job::from_row,
+            )
+            .optional()
+            .context("Failed to claim job")
+    }
+
+    /// Record that a job finished successfully, or [partially](JobStatus::Partial)
+    /// if the output has errors.
+    pub fn succeed(&self, id: i64, output: &JobOutput) -> anyhow::Result<()> {
+        let status = if output.skipped {
+            JobStatus::Skipped
+        } else if !output.errors.is_empty() {
+            JobStatus::Partial
+        } else {
+            JobStatus::Succeeded
+        };
+        let error = (!output.errors.is_empty()).then(|| output.errors.join("\n"));
+
+        self.conn()
+            .execute(
+                "UPDATE jobs SET status = ?1, files = ?2, error = ?3, updated_at = ?4 \
+                 WHERE id = ?5",
+                params![
+                    status.as_str(),
+                    serde_json::to_string(&output.files)?,
+                    error,
+                    Utc::now().timestamp(),
+                    id,
+                ],
+            )
+            .context("Failed to update job")?;
+
+        self.changed.notify_all();
+
+        Ok(())
+    }
+
+    /// Record that an attempt at a job failed.
+    ///
+    /// Transient failures put the job back in the queue with an increasing delay,
+    /// until it was tried [`MAX_ATTEMPTS`] times.
+    /// Returns the status the job ended up with.
+    pub fn fail(&self, job: &Job, error: &JobError) -> anyhow::Result<JobStatus> {
+        let retry = error.transient && !job.is_last_attempt();
+        let now = Utc::now().timestamp();
+
+        let (status, run_after) = if retry {
+            let delay =
+                RETRY_DELAY.saturating_mul(2_u32.saturating_pow(job.attempts.saturating_sub(1)));
+            debug!(
+                "Job {id} failed, retrying in {delay:?}: {error}",
+                id = job.id
+            );
+
+            (
+                JobStatus::Queued,
+                now.saturating_add(i64::try_from(delay.as_secs()).unwrap_or(i64::MAX)),
+            )
+        } else {
+            (JobStatus::Failed, now)
+        };
+
+        self.conn()
+            .execute(
+                "UPDATE jobs SET status = ?1, error = ?2, run_after = ?3, updated_at = ?4 \
+                 WHERE id = ?5",
+                params![status.as_str(), error.message, run_after, now, job.id],
+            )
+            .context("Failed to update job")?;
+
+        self.changed.notify_all();
+
+        Ok(status)
+    }
+
+    /// Put jobs of `origin` that were running when the program stopped back in the queue.
+    ///
+    /// Only safe to call when no other process runs jobs of the same origin.
+    /// Returns how many jobs were put back.
+    pub fn requeue_interrupted(&self, origin: &str) -> anyhow::Result<usize> {
+        let requeued = self
+            .conn()
+            .execute(
+                "UPDATE jobs SET status = ?1, updated_at = ?2 WHERE origin = ?3 AND status = ?4",
+                params![
+                    JobStatus::Queued.as_str(),
+                    Utc::now().timestamp(),
+                    origin,
+                    JobStatus::Running.as_str(),
+                ],
+            )
+            .context("Failed to requeue interrupted jobs")?;
+
+        if requeued > 0 {
+            self.changed.notify_all();
+        }
+
+        Ok(requeued)
+    }
+
+    /// Remove finished jobs that were last updated more than `older_than` ago.
+    ///
+    /// If `origin` is given, only jobs enqueued by that front end are removed.
+    /// Returns the removed jobs.
+    pub fn prune(&self, origin: Option<&str>, older_than: Duration) -> anyhow::Result<Vec<Job>> {
+        let cutoff = Utc::now()
+            .timestamp()
+            .saturating_sub(i64::try_from(older_than.as_secs()).unwrap_or(i64::MAX));
+
+        let removed = self
+            .conn()
+            .prepare(
+                "DELETE FROM jobs \
+                 WHERE status IN (?1, ?2, ?3, ?4) AND updated_at < ?5 AND (?6 IS NULL OR origin = ?6) \
+                 RETURNING *",
+            )?
+            .query_map(
+                params![
+                    JobStatus::Succeeded.as_str(),
+                    JobStatus::Skipped.as_str(),
+                    JobStatus::Partial.as_str(),
+                    JobStatus::Failed.as_str(),
+                    cutoff,
+                    origin,
+                ],
+                Job::from_row,
+            )?
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to prune jobs")?;
+
+        Ok(removed)
+    }
+
+    /// Block until all of the given jobs are finished and return them.
+    pub fn wait_for(&self, ids: &[i64]) -> anyhow::Result<Vec<Job>> {
+        loop {
+            let jobs = ids
+                .iter()
+                .map(|id| {
+                    self.get(*id)?
+                        .with_context(|| format!("Job {id} disappeared from the queue"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            if jobs.iter().all(|x| x.status.is_finished()) {
+                return Ok(jobs);
+            }
+
+            self.wait_for_change(POLL_INTERVAL);
+        }
+    }
+
+    /// Sleep until a job is added or updated, or `timeout` passes.
+    pub(crate) fn wait_for_change(&self, timeout: Duration) {
+        let conn = self.conn();
+        let _ = self
+            .changed
+            .wait_timeout(conn, timeout)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    pub(crate) fn notify(&self) {
+        self.changed.notify_all();
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
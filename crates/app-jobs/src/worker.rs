@@ -0,0 +1,120 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use app_logger::{debug, error, warn};
+
+use crate::{Job, JobError, JobOutput, JobQueue, POLL_INTERVAL};
+
+/// Does the actual work of the jobs a front end enqueued.
+pub trait JobHandler: Send + Sync + 'static {
+    /// Run one attempt at a job.
+    fn run(&self, job: &Job) -> Result<JobOutput, JobError>;
+
+    /// Called once a job succeeded or failed for good.
+    fn finished(&self, _job: &Job) {}
+}
+
+/// A fixed number of threads that run the queued jobs of one origin.
+#[derive(Debug)]
+pub struct WorkerPool {
+    queue: Arc<JobQueue>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn spawn<H>(queue: Arc<JobQueue>, origin: &str, workers: usize, handler: &Arc<H>) -> Self
+    where
+        H: JobHandler,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let threads = (0..workers.max(1))
+            .map(|i| {
+                let queue = Arc::clone(&queue);
+                let stop = Arc::clone(&stop);
+                let handler = Arc::clone(handler);
+                let origin = origin.to_string();
+
+                thread::Builder::new()
+                    .name(format!("job-worker-{i}"))
+                    .spawn(move || work(&queue, &origin, &stop, handler.as_ref()))
+                    .expect("Failed to spawn job worker")
+            })
+            .collect();
+
+        Self {
+            queue,
+            stop,
+            threads,
+        }
+    }
+
+    /// Stop the workers once they finish the jobs they are running.
+    pub fn shutdown(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.queue.notify();
+
+        for thread in self.threads {
+            if thread.join().is_err() {
+                error!("Job worker panicked");
+            }
+        }
+    }
+}
+
+fn work<H>(queue: &JobQueue, origin: &str, stop: &AtomicBool, handler: &H)
+where
+    H: JobHandler,
+{
+    while !stop.load(Ordering::Relaxed) {
+        let job = match queue.claim_next(origin) {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                queue.wait_for_change(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to get next job: {e:?}");
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        debug!(
+            "Running job {id} (attempt {attempt}): {kind}",
+            id = job.id,
+            attempt = job.attempts,
+            kind = job.kind
+        );
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| handler.run(&job)))
+            .unwrap_or_else(|_| Err(JobError::permanent("Job panicked")));
+
+        let status = match &result {
+            Ok(output) => queue.succeed(job.id, output).map(|()| None),
+            Err(e) => queue.fail(&job, e).map(Some),
+        };
+
+        match status {
+            Ok(Some(status)) if !status.is_finished() => continue,
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to record result of job {id}: {e:?}", id = job.id);
+                continue;
+            }
+        }
+
+        match queue.get(job.id) {
+            Ok(Some(job)) => handler.finished(&job),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to get finished job {id}: {e:?}", id = job.id),
+        }
+    }
+}
@@ -1,12 +1,40 @@
-use std::{fs, path::PathBuf, process::exit};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, prelude::*, IsTerminal},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::Arc,
+};
 
-use app_config::{APPLICATION_NAME, CONFIG};
-use app_logger::{error, info, trace, LoggerConfig};
+use app_config::{Command, APPLICATION_NAME, CONFIG};
+use app_downloader::DownloaderError;
+use app_helpers::{
+    dirs::{files_in, remove_empty_dirs},
+    layout::{layout_directory_for_existing, move_file},
+    metadata::move_sidecar,
+};
+use app_history::History;
+use app_jobs::{
+    Job, JobError, JobHandler, JobKind, JobOutput, JobQueue, JobStatus, WorkerPool,
+    KEEP_FINISHED_JOBS_FOR,
+};
+use app_logger::{error, info, trace, warn, LoggerConfig};
+
+/// Origin of the jobs enqueued from the command line.
+///
+/// All runs share it, so jobs of a run that was interrupted are picked up by a later one.
+const CLI_JOB_ORIGIN: &str = "cli";
+
+/// Lock file that tells whether any run is working on CLI jobs, in the data directory.
+const CLI_LOCK_FILE_NAME: &str = "cli-jobs.lock";
 
 #[cfg(feature = "desktop-notifications")]
 mod notif;
+mod progress_bar;
+#[cfg(feature = "http-server")]
+mod server;
 
-#[allow(clippy::too_many_lines)]
 fn main() {
     #[cfg(feature = "telegram-bot")]
     {
@@ -15,23 +43,39 @@ fn main() {
         }
     }
 
-    let download_url = get_download_url().map_or_else(
-        |_| {
-            eprintln!("Failed to get download URL.");
-            exit(1);
-        },
-        |url| url,
-    );
+    #[cfg(feature = "http-server")]
+    {
+        if matches!(
+            CONFIG.run.run_as_bot,
+            Some(app_config::RunAsBot::HttpServer)
+        ) {
+            return run_http_server();
+        }
+    }
+
+    if let Some(command) = &CONFIG.run.command {
+        return run_command(command);
+    }
 
-    if download_url.is_empty() {
+    let inputs = get_download_urls().unwrap_or_else(|e| {
+        eprintln!("Failed to get download URL: {e}");
+        exit(1);
+    });
+
+    if inputs.is_empty() || inputs.iter().any(String::is_empty) {
         eprintln!("No download URL provided. Please provide one.");
         exit(1);
     }
 
+    let log_name_suffix = match inputs.as_slice() {
+        [input] => input.as_str(),
+        _ => "batch",
+    };
+
     if app_logger::init(
         LoggerConfig::builder()
             .program_name(APPLICATION_NAME)
-            .name_suffix(&download_url),
+            .name_suffix(log_name_suffix),
     )
     .is_err()
     {
@@ -41,53 +85,424 @@ fn main() {
 
     trace!("Config: {:?}", *CONFIG);
 
-    if CONFIG.run.fix {
-        let file_path = PathBuf::from(&download_url);
-
-        #[cfg(feature = "desktop-notifications")]
-        {
-            let _ = notif::send_notification(&notif::NotificationInfo {
-                urgency: notify_rust::Urgency::Normal,
-                timeout: notify_rust::Timeout::Milliseconds(5_000),
-                icon: "info".to_string(),
-                title: "Starting file fix".to_string(),
-                message: format!("Fixing file: {}", &download_url),
+    let history = if CONFIG.run.fix {
+        None
+    } else {
+        let meme_dir = CONFIG.app.memes_directory.clone();
+        if !meme_dir.exists() {
+            info!("Memes directory does not exist. Creating...");
+            fs::create_dir_all(&meme_dir).unwrap_or_else(|e| {
+                error!("Error creating memes directory: {:?}", e);
+                exit(1);
             });
         }
+        trace!("Meme dir: {meme_dir:?}");
 
-        info!("Fixing file: {:?}", &file_path);
+        History::open_default()
+            .map_err(|e| {
+                warn!("Download history is unavailable: {e:?}");
+            })
+            .ok()
+    };
 
-        app_fixers::fix_files(&[file_path]).unwrap_or_else(|e| {
-            #[cfg(feature = "desktop-notifications")]
-            {
-                let _ = notif::send_notification(&notif::NotificationInfo {
-                    urgency: notify_rust::Urgency::Normal,
-                    timeout: notify_rust::Timeout::Milliseconds(5_000),
-                    icon: "error".to_string(),
-                    title: "Failed to fix file".to_string(),
-                    message: format!("Failed to fix file: {}", &download_url),
-                });
+    let outcomes = run_batch(&inputs, history);
+
+    if outcomes.len() > 1 {
+        print_summary(&outcomes);
+    }
+
+    if outcomes
+        .iter()
+        .any(|x| matches!(x.result, Err(_) | Ok(Processed::Partial { .. })))
+    {
+        exit(1);
+    }
+}
+
+enum Processed {
+    Done(Vec<PathBuf>),
+    /// Already downloaded before, the paths are the ones from the history.
+    Skipped(Vec<PathBuf>),
+    /// Only some of the files could be downloaded.
+    Partial {
+        paths: Vec<PathBuf>,
+        errors: Vec<String>,
+    },
+}
+
+struct Outcome<'a> {
+    input: &'a str,
+    result: anyhow::Result<Processed>,
+}
+
+/// Runs downloads and fixes from the job queue.
+struct JobRunner {
+    history: Option<History>,
+    notify: bool,
+    /// Draw the progress of the job on the terminal.
+    progress_bar: bool,
+}
+
+impl JobRunner {
+    fn process(&self, job: &Job) -> anyhow::Result<Processed> {
+        match &job.kind {
+            JobKind::Download { url, force } => {
+                download_url_with_history(self.history.as_ref(), url, *force, self.notify)
             }
-            error!("Error fixing file: {:?}", e);
+            JobKind::Fix { path } => {
+                fix_file(&path.to_string_lossy(), self.notify).map(Processed::Done)
+            }
+        }
+    }
+}
+
+impl JobHandler for JobRunner {
+    fn run(&self, job: &Job) -> Result<JobOutput, JobError> {
+        let result = if self.progress_bar {
+            progress_bar::with_progress_bar(|| self.process(job))
+        } else {
+            self.process(job)
+        };
+
+        match result {
+            Ok(Processed::Done(paths)) => Ok(JobOutput::done(paths)),
+            Ok(Processed::Skipped(paths)) => Ok(JobOutput::skipped(paths)),
+            Ok(Processed::Partial { paths, errors }) => Ok(JobOutput::partial(paths, errors)),
+            Err(e) => {
+                let transient = e
+                    .downcast_ref::<DownloaderError>()
+                    .is_some_and(DownloaderError::is_transient);
+                let message = format!("{e:#}");
+
+                if transient {
+                    Err(JobError::transient(message))
+                } else {
+                    Err(JobError::permanent(message))
+                }
+            }
+        }
+    }
+}
+
+/// Lock the CLI jobs for as long as this run lasts, and clean up after earlier runs.
+///
+/// Every run holds a shared lock, so a run that can get an exclusive lock knows
+/// no other run is working on CLI jobs and that any still marked as running were interrupted.
+/// Those are put back in the queue for this run to finish.
+fn lock_cli_jobs(queue: &JobQueue) -> Option<fs::File> {
+    let lock_path = CONFIG.data_dir().join(CLI_LOCK_FILE_NAME);
+    let lock = fs::create_dir_all(CONFIG.data_dir())
+        .and_then(|()| {
+            fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&lock_path)
+        })
+        .map_err(|e| warn!("Failed to open CLI job lock {lock_path:?}: {e:?}"))
+        .ok()?;
+
+    if lock.try_lock().is_ok() {
+        if let Err(e) = queue.prune(Some(CLI_JOB_ORIGIN), KEEP_FINISHED_JOBS_FOR) {
+            warn!("Failed to prune old jobs: {e:?}");
+        }
+
+        match queue.requeue_interrupted(CLI_JOB_ORIGIN) {
+            Ok(0) => {}
+            Ok(n) => info!("Resuming {n} jobs interrupted in an earlier run"),
+            Err(e) => warn!("Failed to resume interrupted jobs: {e:?}"),
+        }
+
+        let _ = lock.unlock();
+    }
+
+    if let Err(e) = lock.lock_shared() {
+        warn!("Failed to lock CLI jobs: {e:?}");
+    }
+
+    Some(lock)
+}
+
+/// Open the persistent job queue, or an in-memory one if that fails.
+fn open_job_queue() -> JobQueue {
+    JobQueue::open_default()
+        .or_else(|e| {
+            warn!("Job queue is unavailable, unfinished jobs won't survive a restart: {e:?}");
+            JobQueue::open_in_memory()
+        })
+        .unwrap_or_else(|e| {
+            error!("Error opening job queue: {:?}", e);
             exit(1);
+        })
+}
+
+fn run_batch(inputs: &[String], history: Option<History>) -> Vec<Outcome<'_>> {
+    let is_batch = inputs.len() > 1;
+
+    #[cfg(feature = "desktop-notifications")]
+    if is_batch {
+        let _ = notif::send_notification(&notif::NotificationInfo {
+            urgency: notify_rust::Urgency::Normal,
+            timeout: notify_rust::Timeout::Milliseconds(5_000),
+            icon: "info".to_string(),
+            title: "Starting batch".to_string(),
+            message: format!("Processing {} items", inputs.len()),
         });
+    }
 
+    let queue = Arc::new(open_job_queue());
+    let _run_lock = lock_cli_jobs(&queue);
+    let origin = CLI_JOB_ORIGIN;
+
+    let ids = inputs
+        .iter()
+        .map(|input| {
+            let kind = if CONFIG.run.fix {
+                JobKind::Fix {
+                    path: fs::canonicalize(input).unwrap_or_else(|_| input.into()),
+                }
+            } else {
+                JobKind::Download {
+                    url: input.clone(),
+                    force: CONFIG.run.force,
+                }
+            };
+
+            queue.enqueue(origin, &kind, None).map(|x| x.id)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap_or_else(|e| {
+            error!("Error queueing jobs: {:?}", e);
+            exit(1);
+        });
+
+    let workers = WorkerPool::spawn(
+        Arc::clone(&queue),
+        origin,
+        CONFIG.run.jobs.min(inputs.len()),
+        &Arc::new(JobRunner {
+            history,
+            notify: !is_batch,
+            progress_bar: !is_batch && io::stderr().is_terminal(),
+        }),
+    );
+    let jobs = queue.wait_for(&ids);
+    workers.shutdown();
+
+    let jobs = jobs.unwrap_or_else(|e| {
+        error!("Error waiting for jobs: {:?}", e);
+        exit(1);
+    });
+
+    let outcomes = inputs
+        .iter()
+        .zip(jobs)
+        .map(|(input, job)| Outcome {
+            input,
+            result: job_result(job),
+        })
+        .collect::<Vec<_>>();
+
+    #[cfg(feature = "desktop-notifications")]
+    if is_batch {
+        let failed = outcomes.iter().filter(|x| x.result.is_err()).count();
+        let partial = outcomes
+            .iter()
+            .filter(|x| matches!(x.result, Ok(Processed::Partial { .. })))
+            .count();
+
+        let notif = notif::send_notification(&notif::NotificationInfo {
+            urgency: if failed + partial == 0 {
+                notify_rust::Urgency::Low
+            } else {
+                notify_rust::Urgency::Normal
+            },
+            timeout: notify_rust::Timeout::Milliseconds(10_000),
+            icon: if failed + partial == 0 {
+                "success"
+            } else {
+                "error"
+            }
+            .to_string(),
+            title: "Batch finished".to_string(),
+            message: format!(
+                "{succeeded} succeeded, {partial} partly failed, {failed} failed",
+                succeeded = outcomes.len() - failed - partial,
+            ),
+        });
+
+        if let Err(e) = notif {
+            error!("Error sending notification: {}", e);
+        }
+    }
+
+    outcomes
+}
+
+/// What a finished job did, as the result of processing its input.
+fn job_result(job: Job) -> anyhow::Result<Processed> {
+    match job.status {
+        JobStatus::Skipped => Ok(Processed::Skipped(job.files)),
+        JobStatus::Partial => Ok(Processed::Partial {
+            paths: job.files,
+            errors: job
+                .error
+                .unwrap_or_default()
+                .lines()
+                .map(ToString::to_string)
+                .collect(),
+        }),
+        JobStatus::Failed => Err(anyhow::anyhow!(job.error.unwrap_or_default())),
+        _ => Ok(Processed::Done(job.files)),
+    }
+}
+
+#[cfg_attr(not(feature = "desktop-notifications"), allow(unused_variables))]
+fn fix_file(file: &str, notify: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let file_path = PathBuf::from(file);
+
+    #[cfg(feature = "desktop-notifications")]
+    if notify {
+        let _ = notif::send_notification(&notif::NotificationInfo {
+            urgency: notify_rust::Urgency::Normal,
+            timeout: notify_rust::Timeout::Milliseconds(5_000),
+            icon: "info".to_string(),
+            title: "Starting file fix".to_string(),
+            message: format!("Fixing file: {}", file),
+        });
+    }
+
+    info!("Fixing file: {:?}", &file_path);
+
+    let paths = app_fixers::fix_files(std::slice::from_ref(&file_path)).map_err(|e| {
         #[cfg(feature = "desktop-notifications")]
-        {
+        if notify {
             let _ = notif::send_notification(&notif::NotificationInfo {
                 urgency: notify_rust::Urgency::Normal,
                 timeout: notify_rust::Timeout::Milliseconds(5_000),
-                icon: "success".to_string(),
-                title: "Successfully fixed file".to_string(),
-                message: format!("Done fixing file: {}", &download_url),
+                icon: "error".to_string(),
+                title: "Failed to fix file".to_string(),
+                message: format!("Failed to fix file: {}", file),
             });
         }
+        error!("Error fixing file {:?}: {}", &file, e);
 
-        return;
+        e
+    })?;
+    let paths = paths
+        .into_iter()
+        .map(|path| relocate_fixed_file(&file_path, path))
+        .collect();
+
+    #[cfg(feature = "desktop-notifications")]
+    if notify {
+        let _ = notif::send_notification(&notif::NotificationInfo {
+            urgency: notify_rust::Urgency::Normal,
+            timeout: notify_rust::Timeout::Milliseconds(5_000),
+            icon: "success".to_string(),
+            title: "Successfully fixed file".to_string(),
+            message: format!("Done fixing file: {}", file),
+        });
+    }
+
+    Ok(paths)
+}
+
+/// Keep the sidecar file with a fixed file, and if the file is in the memes directory,
+/// move it to where the directory template says it belongs.
+fn relocate_fixed_file(original: &Path, path: PathBuf) -> PathBuf {
+    if original != path {
+        if let Err(e) = move_sidecar(original, &path) {
+            warn!("Failed to move sidecar of {original:?} to {path:?}: {e:?}");
+        }
+    }
+
+    if CONFIG.app.directory_template.is_none() {
+        return path;
+    }
+
+    let Ok(memes_dir) = CONFIG.app.memes_directory.canonicalize() else {
+        return path;
+    };
+    if !path.starts_with(&memes_dir) {
+        return path;
+    }
+
+    let dir = memes_dir.join(layout_directory_for_existing(&path));
+    if path.parent() == Some(dir.as_path()) {
+        return path;
+    }
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    match move_file(&path, &dir, &name) {
+        Ok(new_path) => {
+            info!("Moved {path:?} to {new_path:?}");
+            new_path
+        }
+        Err(e) => {
+            warn!("Failed to move {path:?} to {dir:?}: {e:?}");
+            path
+        }
+    }
+}
+
+fn download_url_with_history(
+    history: Option<&History>,
+    url: &str,
+    force: bool,
+    notify: bool,
+) -> anyhow::Result<Processed> {
+    let Some(history) = history else {
+        return download_url(url, notify).map(|(_, paths, errors)| processed(paths, errors));
+    };
+
+    if !force {
+        match history.find(url) {
+            Ok(Some(entry)) if entry.any_file_exists() => {
+                info!(
+                    "Skipping {url:?}, already downloaded on {date} into {paths:?}. Use --force to download again.",
+                    date = entry.downloaded_at.format("%Y-%m-%d %H:%M:%S"),
+                    paths = entry.paths,
+                );
+
+                return Ok(Processed::Skipped(entry.paths));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Failed to look up {url:?} in download history: {e:?}");
+            }
+        }
+    }
+
+    let (downloader, paths, errors) = download_url(url, notify)?;
+
+    // A later run should try the missing files again
+    if !errors.is_empty() {
+        info!("Not recording {url:?} in download history, some of its files failed");
+    } else if let Err(e) = history.record(url, downloader, &paths) {
+        warn!("Failed to record {url:?} in download history: {e:?}");
     }
 
+    Ok(processed(paths, errors))
+}
+
+fn processed(paths: Vec<PathBuf>, errors: Vec<String>) -> Processed {
+    if errors.is_empty() {
+        Processed::Done(paths)
+    } else {
+        Processed::Partial { paths, errors }
+    }
+}
+
+#[cfg_attr(not(feature = "desktop-notifications"), allow(unused_variables))]
+/// Download a URL, returning the name of the downloader that handled it, the saved files
+/// and why the rest of the files could not be downloaded, if only some of them were.
+fn download_url(
+    download_url: &str,
+    notify: bool,
+) -> anyhow::Result<(&'static str, Vec<PathBuf>, Vec<String>)> {
     #[cfg(feature = "desktop-notifications")]
-    {
+    if notify {
         let _ = notif::send_notification(&notif::NotificationInfo {
             urgency: notify_rust::Urgency::Normal,
             timeout: notify_rust::Timeout::Milliseconds(5_000),
@@ -97,18 +512,15 @@ fn main() {
         });
     }
 
-    let meme_dir = CONFIG.app.memes_directory.clone();
-    if !meme_dir.exists() {
-        info!("Memes directory does not exist. Creating...");
-        fs::create_dir_all(&meme_dir).unwrap_or_else(|e| {
-            error!("Error creating memes directory: {:?}", e);
-            exit(1);
-        });
-    }
-    trace!("Meme dir: {meme_dir:?}");
+    match app_downloader::download_file_detailed(download_url, &CONFIG.app.memes_directory) {
+        Ok(download) => {
+            let paths = download
+                .files
+                .into_iter()
+                .map(|x| x.path)
+                .collect::<Vec<_>>();
+            let paths = app_dedupe::deduplicate_new_files(&paths);
 
-    match app_downloader::download_file(&download_url, &meme_dir) {
-        Ok(paths) => {
             info!(
                 "Downloaded file(s): {}",
                 paths
@@ -119,25 +531,37 @@ fn main() {
             );
 
             #[cfg(feature = "desktop-notifications")]
-            {
+            if notify {
                 let notif = notif::send_notification(&notif::NotificationInfo {
                     urgency: notify_rust::Urgency::Low,
                     timeout: notify_rust::Timeout::Milliseconds(5000),
                     icon: "success".to_string(),
                     title: "Download finished".to_string(),
-                    message: format!("The meme from {} has finished downloading", &download_url),
+                    message: match download.errors.len() {
+                        0 => format!("The meme from {} has finished downloading", &download_url),
+                        n => format!(
+                            "The meme from {} has finished downloading, but {n} of its files failed",
+                            &download_url
+                        ),
+                    },
                 });
 
                 if let Err(e) = notif {
                     error!("Error sending notification: {}", e);
                 }
             }
+
+            Ok((
+                download.downloader,
+                paths,
+                download.errors.iter().map(ToString::to_string).collect(),
+            ))
         }
         Err(e) => {
-            error!("Error downloading file: {}", e);
+            error!("Error downloading file {:?}: {}", &download_url, e);
 
             #[cfg(feature = "desktop-notifications")]
-            {
+            if notify {
                 let notif = notif::send_notification(&notif::NotificationInfo {
                     urgency: notify_rust::Urgency::Normal,
                     timeout: notify_rust::Timeout::Milliseconds(10_000),
@@ -153,11 +577,283 @@ fn main() {
                     error!("Error sending notification: {}", e);
                 }
             }
+
+            Err(e.into())
+        }
+    }
+}
+
+fn print_summary(outcomes: &[Outcome]) {
+    let failed = outcomes.iter().filter(|x| x.result.is_err()).count();
+    let partial = outcomes
+        .iter()
+        .filter(|x| matches!(x.result, Ok(Processed::Partial { .. })))
+        .count();
+    let skipped = outcomes
+        .iter()
+        .filter(|x| matches!(x.result, Ok(Processed::Skipped(_))))
+        .count();
+    let input_width = outcomes
+        .iter()
+        .map(|x| x.input.chars().count())
+        .max()
+        .unwrap_or_default();
+
+    println!();
+    println!(
+        "{succeeded} succeeded, {skipped} skipped, {partial} partly failed, {failed} failed",
+        succeeded = outcomes.len() - failed - skipped - partial
+    );
+    println!();
+
+    for outcome in outcomes {
+        match &outcome.result {
+            Ok(processed) => {
+                let (status, paths, errors) = match processed {
+                    Processed::Done(paths) => ("OK", paths, &[][..]),
+                    Processed::Skipped(paths) => ("SKIP", paths, &[][..]),
+                    Processed::Partial { paths, errors } => ("PART", paths, &errors[..]),
+                };
+                let paths = paths
+                    .iter()
+                    .map(|x| x.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                println!("{status:4}  {:input_width$}  {}", outcome.input, paths);
+                for error in errors {
+                    println!("{:4}  {:input_width$}  {}", "", "", error);
+                }
+            }
+            Err(e) => {
+                println!("FAIL  {:input_width$}  {}", outcome.input, e);
+            }
+        }
+    }
+}
+
+fn run_command(command: &Command) {
+    match command {
+        Command::History { query, limit } => {
+            let entries = History::open_default()
+                .and_then(|history| history.search(query.as_deref(), *limit))
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to read download history: {e:?}");
+                    exit(1);
+                });
+
+            if entries.is_empty() {
+                eprintln!("No downloads found.");
+                return;
+            }
+
+            for entry in entries {
+                println!(
+                    "{date}  [{downloader}] {url}",
+                    date = entry.downloaded_at.format("%Y-%m-%d %H:%M:%S"),
+                    downloader = entry.downloader,
+                    url = entry.original_url,
+                );
+
+                for path in &entry.paths {
+                    println!("    {}", path.to_string_lossy());
+                }
+            }
+        }
+
+        Command::Jobs { limit } => print_jobs(*limit),
+
+        Command::Dedupe { directory, dry_run } => {
+            let directory = directory
+                .clone()
+                .unwrap_or_else(|| CONFIG.app.memes_directory.clone());
+
+            let groups = app_dedupe::find_duplicates(&directory).unwrap_or_else(|e| {
+                eprintln!("Failed to scan {directory:?} for duplicates: {e:?}");
+                exit(1);
+            });
+
+            let mut removed = 0;
+            let mut failed = 0;
+            for group in &groups {
+                println!("{}", group.original.to_string_lossy());
+
+                for duplicate in &group.duplicates {
+                    println!("    {}", duplicate.to_string_lossy());
+
+                    if *dry_run {
+                        continue;
+                    }
+
+                    match app_dedupe::trash_with_sidecar(duplicate) {
+                        Ok(()) => removed += 1,
+                        Err(e) => {
+                            eprintln!("Failed to remove {duplicate:?}: {e}");
+                            failed += 1;
+                        }
+                    }
+                }
+            }
+
+            let found = groups.iter().map(|x| x.duplicates.len()).sum::<usize>();
+            println!();
+            if *dry_run {
+                println!("{found} duplicates found");
+            } else {
+                println!("{found} duplicates found, {removed} removed, {failed} failed");
+            }
+
+            if failed > 0 {
+                exit(1);
+            }
+        }
+
+        Command::Similar {
+            directory,
+            distance,
+        } => {
+            let directory = directory
+                .clone()
+                .unwrap_or_else(|| CONFIG.app.memes_directory.clone());
+            let distance = distance
+                .or(CONFIG.app.near_duplicate_distance)
+                .unwrap_or(app_dedupe::DEFAULT_NEAR_DUPLICATE_DISTANCE);
+
+            let groups = app_dedupe::find_similar(&directory, distance).unwrap_or_else(|e| {
+                eprintln!("Failed to scan {directory:?} for similar files: {e:?}");
+                exit(1);
+            });
+
+            for group in &groups {
+                let mut files = group.files.iter();
+
+                if let Some(first) = files.next() {
+                    println!("{}", first.to_string_lossy());
+                }
+                for file in files {
+                    println!("    {}", file.to_string_lossy());
+                }
+            }
+
+            println!();
+            println!("{} groups of similar files found", groups.len());
+        }
+
+        Command::Reorganise { directory, dry_run } => {
+            reorganise(directory.as_ref(), *dry_run);
+        }
+    }
+}
+
+/// Print the most recent jobs from the job queue.
+fn print_jobs(limit: usize) {
+    let jobs = JobQueue::open_default()
+        .and_then(|queue| queue.list(None, limit))
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to read job queue: {e:?}");
             exit(1);
+        });
+
+    if jobs.is_empty() {
+        eprintln!("No jobs found.");
+        return;
+    }
+
+    for job in jobs {
+        println!(
+            "{date}  #{id} {status:9} [{origin}] {kind} (attempts: {attempts})",
+            date = job.updated_at.format("%Y-%m-%d %H:%M:%S"),
+            id = job.id,
+            status = job.status.to_string(),
+            origin = job.origin,
+            kind = job.kind,
+            attempts = job.attempts,
+        );
+
+        if let Some(error) = &job.error {
+            println!("    error: {error}");
+        }
+        for path in &job.files {
+            println!("    {}", path.to_string_lossy());
         }
     }
 }
 
+/// Move the files in `directory` to where the directory template says they belong.
+fn reorganise(directory: Option<&PathBuf>, dry_run: bool) {
+    if CONFIG.app.directory_template.is_none() {
+        eprintln!("No directory template configured. Set `directory_template` first.");
+        exit(1);
+    }
+
+    let directory = directory
+        .cloned()
+        .unwrap_or_else(|| CONFIG.app.memes_directory.clone());
+    let directory = directory
+        .canonicalize()
+        .and_then(|x| files_in(&x).map(|files| (x, files)));
+    let (directory, files) = directory.unwrap_or_else(|e| {
+        eprintln!("Failed to read directory: {e:?}");
+        exit(1);
+    });
+
+    let mut moves = HashMap::new();
+    let mut failed = 0;
+    for path in files {
+        let dir = directory.join(layout_directory_for_existing(&path));
+        if path.parent() == Some(dir.as_path()) {
+            continue;
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        if dry_run {
+            println!(
+                "{} -> {}",
+                path.to_string_lossy(),
+                dir.join(name.as_ref()).to_string_lossy()
+            );
+            continue;
+        }
+
+        match move_file(&path, &dir, &name) {
+            Ok(new_path) => {
+                println!(
+                    "{} -> {}",
+                    path.to_string_lossy(),
+                    new_path.to_string_lossy()
+                );
+                moves.insert(path, new_path);
+            }
+            Err(e) => {
+                eprintln!("Failed to move {path:?}: {e}");
+                failed += 1;
+            }
+        }
+    }
+
+    if !dry_run {
+        if let Err(e) = remove_empty_dirs(&directory) {
+            eprintln!("Failed to remove empty directories: {e}");
+        }
+
+        if let Err(e) = History::open_default().and_then(|x| x.update_paths(&moves)) {
+            eprintln!("Failed to update paths in download history: {e:?}");
+        }
+    }
+
+    println!();
+    if dry_run {
+        println!("Nothing moved, dry run");
+    } else {
+        println!("{} files moved, {failed} failed", moves.len());
+    }
+
+    if failed > 0 {
+        exit(1);
+    }
+}
+
 #[cfg(feature = "telegram-bot")]
 fn run_telegram_bot() {
     if app_logger::init(
@@ -192,20 +888,58 @@ fn run_telegram_bot() {
     exit(0);
 }
 
-fn get_download_url() -> anyhow::Result<String> {
-    let download_url = CONFIG.run.download_url.as_ref();
+#[cfg(feature = "http-server")]
+fn run_http_server() {
+    if app_logger::init(
+        LoggerConfig::builder()
+            .program_name(APPLICATION_NAME)
+            .name_suffix("server")
+            .file_log_level(app_logger::LevelFilter::Debug)
+            .stdout_log_level(if cfg!(debug_assertions) {
+                app_logger::LevelFilter::Trace
+            } else {
+                app_logger::LevelFilter::Info
+            }),
+    )
+    .is_err()
+    {
+        eprintln!("Failed to initialize logger.");
+        exit(1);
+    }
+
+    if let Err(e) = server::run() {
+        error!("HTTP server failed: {e:?}");
+        exit(1);
+    }
+
+    info!("HTTP server stopped");
+    exit(0);
+}
+
+fn get_download_urls() -> anyhow::Result<Vec<String>> {
+    let mut download_urls = CONFIG.run.download_urls.clone();
 
-    if cfg!(feature = "ask-for-url") {
-        use std::{io, io::prelude::*};
+    if let Some(input_file) = &CONFIG.run.input_file {
+        let urls = if input_file.as_os_str() == "-" {
+            read_url_list(io::stdin().lock())?
+        } else {
+            read_url_list(io::BufReader::new(fs::File::open(input_file)?))?
+        };
 
-        if let Some(download_url) = download_url {
-            return Ok(download_url.to_string());
-        }
+        download_urls.extend(urls);
 
-        if atty::isnt(atty::Stream::Stdin) {
-            anyhow::bail!("No download URL provided. Please provide one.");
-        }
+        return Ok(download_urls);
+    }
 
+    if !download_urls.is_empty() {
+        return Ok(download_urls);
+    }
+
+    if !io::stdin().is_terminal() {
+        return read_url_list(io::stdin().lock());
+    }
+
+    if cfg!(feature = "ask-for-url") {
         print!("Download URL: ");
         io::stdout().flush()?;
 
@@ -216,10 +950,25 @@ fn get_download_url() -> anyhow::Result<String> {
             .unwrap_or_else(|| Ok(String::new()))
             .unwrap_or_default();
 
-        Ok(res)
+        Ok(vec![res])
     } else {
-        download_url
-            .ok_or_else(|| anyhow::anyhow!("No download URL provided. Please provide one."))
-            .map(std::string::ToString::to_string)
+        anyhow::bail!("No download URL provided. Please provide one.")
     }
 }
+
+fn read_url_list<R: BufRead>(reader: R) -> anyhow::Result<Vec<String>> {
+    let mut urls = vec![];
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        urls.push(line.to_string());
+    }
+
+    Ok(urls)
+}
@@ -0,0 +1,65 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use app_helpers::progress::{self, Progress};
+
+const BAR_WIDTH: usize = 30;
+
+/// How long the drawing thread waits for an event before checking if it should stop.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Run `f`, drawing the progress it reports as a bar on the last line of stderr.
+pub fn with_progress_bar<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let (sender, receiver) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let drawer = {
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let mut stderr = io::stderr();
+            while !stop.load(Ordering::Relaxed) {
+                if let Ok(progress) = receiver.recv_timeout(REDRAW_INTERVAL) {
+                    let _ = write!(stderr, "\r\x1b[2K{}", render(&progress));
+                    let _ = stderr.flush();
+                }
+            }
+            let _ = write!(stderr, "\r\x1b[2K");
+        })
+    };
+
+    let result = progress::with_progress(Some(sender), f);
+
+    stop.store(true, Ordering::Relaxed);
+    let _ = drawer.join();
+
+    result
+}
+
+fn render(progress: &Progress) -> String {
+    let Some(percent) = progress.percent() else {
+        return progress.to_string();
+    };
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let filled = ((percent / 100.0 * BAR_WIDTH as f32) as usize).min(BAR_WIDTH);
+
+    format!(
+        "[{}{}] {progress}",
+        "#".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled)
+    )
+}
@@ -0,0 +1,338 @@
+//! A local HTTP API for downloading and fixing memes.
+//!
+//! - `POST /download` with a JSON body like `{"url": "...", "force": false}`
+//!   starts downloading a URL into the memes directory
+//! - `POST /fix?name=video.mp4` with the file as the body starts fixing an uploaded file
+//! - `GET /jobs` lists the remembered jobs, newest first
+//! - `GET /jobs/{id}` returns the status of a job and the files it produced
+//! - `GET /jobs/{id}/files/{index}` returns one of the files a finished job produced
+//!
+//! Jobs go through the persistent job queue and run in the background on up to `--jobs` threads.
+//! Jobs that were interrupted by a restart are picked up again when the server starts.
+//! If a token is configured, every request needs an `Authorization: Bearer <token>` header.
+
+use std::{
+    fs,
+    io::{self, Read},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::Arc,
+    thread,
+};
+
+use app_config::{CONFIG, CONFIGURATION};
+use app_helpers::dirs::create_temp_dir;
+use app_history::History;
+use app_jobs::{Job, JobKind, JobQueue, WorkerPool, KEEP_FINISHED_JOBS_FOR};
+use app_logger::{debug, error, info, warn};
+use constant_time_eq::constant_time_eq;
+use serde::Deserialize;
+use tiny_http::{Header, Method, Request, Response, ResponseBox, Server, StatusCode};
+
+use crate::JobRunner;
+
+/// Origin of the jobs the server enqueues.
+const JOB_ORIGIN: &str = "server";
+
+/// Largest file that can be uploaded for fixing.
+const MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// How many requests are handled at the same time.
+///
+/// Downloads and fixes run on the job workers, so handlers only wait on uploads and file responses.
+const HANDLER_THREADS: usize = 8;
+
+/// How many jobs `GET /jobs` returns.
+const MAX_LISTED_JOBS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct DownloadRequest {
+    url: String,
+    #[serde(default)]
+    force: bool,
+}
+
+pub fn run() -> anyhow::Result<()> {
+    let address = CONFIG.server.address();
+
+    let meme_dir = &CONFIG.app.memes_directory;
+    if !meme_dir.exists() {
+        info!("Memes directory does not exist. Creating...");
+        fs::create_dir_all(meme_dir)?;
+    }
+
+    let history = History::open_default()
+        .map_err(|e| {
+            warn!("Download history is unavailable: {e:?}");
+        })
+        .ok();
+
+    let queue = Arc::new(crate::open_job_queue());
+    prune_jobs(&queue);
+    match queue.requeue_interrupted(JOB_ORIGIN) {
+        Ok(0) => {}
+        Ok(n) => info!("Resuming {n} interrupted jobs"),
+        Err(e) => warn!("Failed to resume interrupted jobs: {e:?}"),
+    }
+
+    let _workers = WorkerPool::spawn(
+        Arc::clone(&queue),
+        JOB_ORIGIN,
+        CONFIG.run.jobs,
+        &Arc::new(JobRunner {
+            history,
+            notify: false,
+            progress_bar: false,
+        }),
+    );
+
+    let server = Arc::new(Server::http(&address).map_err(|e| anyhow::anyhow!(e))?);
+    info!("Listening on http://{address}");
+
+    if CONFIG.server.token.is_none() {
+        warn!("No server token configured, anyone who can reach {address} can use the API");
+    }
+
+    let handlers = (0..HANDLER_THREADS)
+        .map(|i| {
+            let server = Arc::clone(&server);
+            let queue = Arc::clone(&queue);
+
+            thread::Builder::new()
+                .name(format!("http-handler-{i}"))
+                .spawn(move || {
+                    for request in server.incoming_requests() {
+                        // Keep the handler around for the next request if this one panics
+                        let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                            handle_request(&queue, request);
+                        }));
+                        if handled.is_err() {
+                            error!("Panicked while handling a request");
+                        }
+                    }
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for handler in handlers {
+        if handler.join().is_err() {
+            error!("HTTP handler thread panicked");
+        }
+    }
+
+    Ok(())
+}
+
+/// Forget old finished jobs, and remove the directories their uploads were fixed in.
+fn prune_jobs(queue: &JobQueue) {
+    let pruned = match queue.prune(Some(JOB_ORIGIN), KEEP_FINISHED_JOBS_FOR) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("Failed to prune old jobs: {e:?}");
+            return;
+        }
+    };
+
+    let cache_dir = CONFIGURATION.cache_dir();
+    for job in pruned {
+        let JobKind::Fix { path } = job.kind else {
+            continue;
+        };
+
+        if let Some(work_dir) = path.parent().filter(|x| x.starts_with(&cache_dir)) {
+            if let Err(e) = fs::remove_dir_all(work_dir) {
+                debug!("Failed to remove work directory {work_dir:?}: {e:?}");
+            }
+        }
+    }
+}
+
+fn handle_request(queue: &JobQueue, mut request: Request) {
+    debug!("{} {}", request.method(), request.url());
+
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments = path
+        .split('/')
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+
+    let response = if is_authorized(&request) {
+        match (request.method(), segments.as_slice()) {
+            (Method::Get, ["health"]) => json_response(200, &serde_json::json!({"status": "ok"})),
+            (Method::Post, ["download"]) => start_download(queue, &mut request),
+            (Method::Post, ["fix"]) => start_fix(queue, &mut request, query),
+            (Method::Get, ["jobs"]) => match queue.list(Some(JOB_ORIGIN), MAX_LISTED_JOBS) {
+                Ok(jobs) => json_response(200, &jobs),
+                Err(e) => {
+                    error!("Failed to list jobs: {e:?}");
+                    error_response(500, "Failed to list jobs")
+                }
+            },
+            (Method::Get, ["jobs", id]) => match find_job(queue, id) {
+                Ok(job) => json_response(200, &job),
+                Err(response) => response,
+            },
+            (Method::Get, ["jobs", id, "files", index]) => job_file(queue, id, index),
+            _ => error_response(404, "Not found"),
+        }
+    } else {
+        error_response(401, "Missing or invalid token")
+    };
+
+    if let Err(e) = request.respond(response) {
+        warn!("Failed to send response: {e:?}");
+    }
+}
+
+fn is_authorized(request: &Request) -> bool {
+    let Some(token) = &CONFIG.server.token else {
+        return true;
+    };
+
+    request
+        .headers()
+        .iter()
+        .find(|x| x.field.equiv("Authorization"))
+        .and_then(|x| x.value.as_str().strip_prefix("Bearer "))
+        .is_some_and(|x| constant_time_eq(x.trim().as_bytes(), token.as_bytes()))
+}
+
+fn start_download(queue: &JobQueue, request: &mut Request) -> ResponseBox {
+    let body: DownloadRequest = match serde_json::from_reader(request.as_reader()) {
+        Ok(x) => x,
+        Err(e) => return error_response(400, &format!("Invalid request body: {e}")),
+    };
+
+    let kind = JobKind::Download {
+        url: body.url,
+        force: body.force,
+    };
+
+    enqueue(queue, &kind)
+}
+
+fn start_fix(queue: &JobQueue, request: &mut Request, query: &str) -> ResponseBox {
+    let file_name = url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "name")
+        .and_then(|(_, value)| {
+            Path::new(value.as_ref())
+                .file_name()
+                .map(|x| x.to_string_lossy().to_string())
+        })
+        .unwrap_or_else(|| "upload".to_string());
+
+    if request
+        .body_length()
+        .is_some_and(|x| x as u64 > MAX_UPLOAD_SIZE)
+    {
+        return error_response(413, "File is too large");
+    }
+
+    let work_dir = match create_temp_dir() {
+        Ok(x) => x,
+        Err(e) => {
+            error!("Failed to create a directory for an upload: {e:?}");
+            return error_response(500, "Failed to save the uploaded file");
+        }
+    };
+    let file_path = work_dir.join(&file_name);
+
+    let saved = fs::File::create(&file_path).and_then(|mut file| {
+        io::copy(
+            &mut request.as_reader().take(MAX_UPLOAD_SIZE + 1),
+            &mut file,
+        )
+    });
+    match saved {
+        Ok(size) if size > MAX_UPLOAD_SIZE => {
+            let _ = fs::remove_dir_all(&work_dir);
+            return error_response(413, "File is too large");
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!("Failed to save uploaded file to {file_path:?}: {e:?}");
+            let _ = fs::remove_dir_all(&work_dir);
+            return error_response(500, "Failed to save the uploaded file");
+        }
+    }
+
+    enqueue(queue, &JobKind::Fix { path: file_path })
+}
+
+fn enqueue(queue: &JobQueue, kind: &JobKind) -> ResponseBox {
+    match queue.enqueue(JOB_ORIGIN, kind, None) {
+        Ok(job) => {
+            info!("Job {id}: {kind}", id = job.id);
+            json_response(202, &job)
+        }
+        Err(e) => {
+            error!("Failed to queue job: {e:?}");
+            error_response(500, "Failed to queue job")
+        }
+    }
+}
+
+fn find_job(queue: &JobQueue, id: &str) -> Result<Job, ResponseBox> {
+    id.parse()
+        .ok()
+        .and_then(|x| queue.get(x).ok().flatten())
+        .filter(|x| x.origin == JOB_ORIGIN)
+        .ok_or_else(|| error_response(404, "No such job"))
+}
+
+fn job_file(queue: &JobQueue, id: &str, index: &str) -> ResponseBox {
+    let job = match find_job(queue, id) {
+        Ok(x) => x,
+        Err(response) => return response,
+    };
+
+    let Some(path) = index.parse::<usize>().ok().and_then(|x| job.files.get(x)) else {
+        return error_response(404, "No such file");
+    };
+
+    let file = match fs::File::open(path) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("Failed to open {path:?}: {e:?}");
+            return error_response(404, "File no longer exists");
+        }
+    };
+
+    let mime_type = infer::get_from_path(path)
+        .ok()
+        .flatten()
+        .map_or("application/octet-stream", |x| x.mime_type());
+    let file_name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .chars()
+        .filter(|x| x.is_ascii() && !x.is_ascii_control() && *x != '"')
+        .collect::<String>();
+
+    Response::from_file(file)
+        .with_header(header("Content-Type", mime_type))
+        .with_header(header(
+            "Content-Disposition",
+            &format!("attachment; filename=\"{file_name}\""),
+        ))
+        .boxed()
+}
+
+fn json_response<T: serde::Serialize>(status: u16, body: &T) -> ResponseBox {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+
+    Response::from_data(body)
+        .with_status_code(StatusCode(status))
+        .with_header(header("Content-Type", "application/json"))
+        .boxed()
+}
+
+fn error_response(status: u16, message: &str) -> ResponseBox {
+    json_response(status, &serde_json::json!({ "error": message }))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("Invalid header")
+}